@@ -0,0 +1,69 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use image_processor::{
+    traits::*,
+    OwnedImage::{box_blur, OwnedImage},
+    utils::Dimensions,
+    kernel::{Kernel, EdgeMode},
+};
+
+const SIZE: u32 = 256;
+
+fn test_image() -> OwnedImage {
+    let dims = Dimensions { width: SIZE, height: SIZE };
+    let pixels: Vec<u8> = (0..SIZE * SIZE)
+        .flat_map(|i| {
+            let (x, y) = (i % SIZE, i / SIZE);
+            vec![(x ^ y) as u8, (x * 3) as u8, (y * 5) as u8, 255]
+        })
+        .collect();
+    OwnedImage::new(dims, pixels.into_boxed_slice()).unwrap()
+}
+
+/// The original box blur: walks the whole (2r+1)^2 window for every pixel,
+/// clamping reads to the image.
+fn window_box_blur(image: &OwnedImage, r: i64) -> Vec<u8> {
+    let (w, h) = (image.dimensions().width as i64, image.dimensions().height as i64);
+    let pixels = image.pixels();
+    let count = ((2 * r + 1) * (2 * r + 1)) as f32;
+    let mut out = pixels.to_vec();
+    for j in 0..h {
+        for i in 0..w {
+            let mut totals = [0.0f32; 3];
+            for y in j - r..=j + r {
+                for x in i - r..=i + r {
+                    let index = ((y.max(0).min(h - 1) * w + x.max(0).min(w - 1)) * 4) as usize;
+                    for (total, value) in totals.iter_mut().zip(&pixels[index..index + 3]) {
+                        *total += *value as f32;
+                    }
+                }
+            }
+            let index = ((j * w + i) * 4) as usize;
+            for (c, total) in totals.iter().enumerate() {
+                out[index + c] = (total / count).round() as u8;
+            }
+        }
+    }
+    out
+}
+
+fn bench_box_blur(c: &mut Criterion) {
+    let image = test_image();
+    let mut group = c.benchmark_group("box_blur");
+    group.sample_size(10);
+
+    for &radius in &[2usize, 8, 32] {
+        group.bench_with_input(BenchmarkId::new("separable_kernel", radius), &radius, |b, &r| {
+            b.iter(|| image.convolved(&Kernel::box_filter(r), EdgeMode::Clamp))
+        });
+        group.bench_with_input(BenchmarkId::new("running_sum", radius), &radius, |b, &r| {
+            b.iter(|| box_blur(&image, r, &EdgeMode::Clamp))
+        });
+        group.bench_with_input(BenchmarkId::new("window", radius), &radius, |b, &r| {
+            b.iter(|| window_box_blur(&image, r as i64))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_box_blur);
+criterion_main!(benches);
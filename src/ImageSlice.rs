@@ -1,203 +1,182 @@
 use super::traits::*;
-use super::OwnedImage::*;
 use super::utils::*;
-use std::{cmp};
+use super::pixel::*;
+use super::view::{StridedView, StridedViewMut};
 
 /// A borrowed slice of an image.
-pub struct ImageSlice<'a> {
-    pixels: &'a [u8],
+pub struct ImageSlice<'a, P: PixelType = Rgba8> {
+    pixels: StridedView<'a, P>,
     spec: SliceSpec,
 }
 
 /// A mutable, borrowed slice of an image.
-pub struct ImageSliceMut<'a> {
-    pixels: &'a mut [u8],
+pub struct ImageSliceMut<'a, P: PixelType = Rgba8> {
+    pixels: StridedViewMut<'a, P>,
     spec: SliceSpec,
 }
 
-impl<'a> ImageSlice<'a> {
+impl<'a, P: PixelType> ImageSlice<'a, P> {
 
-    pub fn new (pixels: &'a [u8], spec: SliceSpec) -> Self {
-        ImageSlice {
-            pixels,
-            spec,
-        }
+    /// Borrows the part of `pixels` that `spec` describes.
+    /// Fails if the slice's rows overlap or run off the end of `pixels`.
+    pub fn new (pixels: &'a [P::Channel], spec: SliceSpec) -> Result<Self, ImageError> {
+        spec.check_fits(pixels.len(), P::CHANNELS)?;
+        Ok(ImageSlice::from_raw(pixels, spec))
     }
 
-    fn get_pixel(&self, x: u32, y: u32) -> Pixel {
-        let w = self.spec.dims.width;
-        let h = self.spec.dims.width;
-        let (off_x, off_y) = self.spec.offset;
+    /// Reads the pixel at (`x`, `y`), relative to the slice's top-left corner.
+    pub fn get_pixel(&self, x: u32, y: u32) -> Result<P, ImageError> {
+        self.view().get_pixel(x, y)
+    }
 
-        // if x, y not contained in the current slice
-        if  x < off_x  || y < off_y || (x >= w+off_x || y >= h + off_y) {
-            dispatch_error(ImageError::IndexOutOfBound)
-        }
-        let index = ((y*w*4) + 4 * x) as usize;
-        Pixel {
-            r: self.pixels[index],
-            g: self.pixels[index+1],
-            b: self.pixels[index+2],
-            alpha: self.pixels[index+3],
-        }
+    /// Where this slice lies in the buffer it borrows.
+    pub fn spec(&self) -> &SliceSpec {
+        &self.spec
     }
-}
 
-impl<'a> ImageSliceMut<'a> {
+    /*************** Crate Functions ***************/
 
-    pub fn new (pixels: &'a mut [u8], spec: SliceSpec) -> Self {
-        ImageSliceMut {
-            pixels,
+    /// Borrows a part of `pixels` already known to fit.
+    pub(crate) fn from_raw(pixels: &'a [P::Channel], spec: SliceSpec) -> Self {
+        ImageSlice {
+            pixels: StridedView::from_raw_at(pixels, spec.origin, spec.dims, spec.stride),
             spec,
         }
     }
+}
 
-    fn get_pixel(&self, x: u32, y: u32) -> Pixel {
-        let w = self.spec.dims.width;
-        let h = self.spec.dims.width;
-        let (off_x, off_y) = self.spec.offset;
+impl<'a, P: PixelType> ImageSliceMut<'a, P> {
 
-        // if x, y not contained in the current slice
-        if  x < off_x  || y < off_y || (x >= w+off_x || y >= h + off_y) {
-            dispatch_error(ImageError::IndexOutOfBound)
-        }
-        let index = ((y*w*4) + 4 * x) as usize;
-        Pixel {
-            r: self.pixels[index],
-            g: self.pixels[index+1],
-            b: self.pixels[index+2],
-            alpha: self.pixels[index+3],
-        }
+    /// Borrows the part of `pixels` that `spec` describes.
+    /// Fails if the slice's rows overlap or run off the end of `pixels`.
+    pub fn new (pixels: &'a mut [P::Channel], spec: SliceSpec) -> Result<Self, ImageError> {
+        spec.check_fits(pixels.len(), P::CHANNELS)?;
+        Ok(ImageSliceMut::from_raw(pixels, spec))
     }
-}
 
-impl<'a> Image for ImageSlice<'a> {
+    /// Reads the pixel at (`x`, `y`), relative to the slice's top-left corner.
+    pub fn get_pixel(&self, x: u32, y: u32) -> Result<P, ImageError> {
+        self.view().get_pixel(x, y)
+    }
 
-    fn dimensions(&self) -> &Dimensions {
-        &self.spec.dims
+    /// Where this slice lies in the buffer it borrows.
+    pub fn spec(&self) -> &SliceSpec {
+        &self.spec
     }
 
-    fn pixels(&self) -> Matrix<u8> {
-        let (off_x, off_y)= self.spec.offset;
-        let (w, _) = self.spec.size;
-        let dims = &self.spec.dims;
 
-        let mut buffer = Vec::new();
+    /// Splits into the rows above `y` and the rest, which can be edited at the
+    /// same time. `y` is clamped to the height.
+    pub fn split_at_row(self, y: u32) -> (Self, Self) {
+        let y = y.min(self.spec.dims.height);
+        let (top, bottom) = self.pixels.split_at_row(y);
+        (ImageSliceMut::part(&self.spec, 0, 0, top), ImageSliceMut::part(&self.spec, 0, y, bottom))
+    }
+
+    /// Splits into the columns left of `x` and the rest, which can be edited
+    /// at the same time. `x` is clamped to the width.
+    pub fn split_at_col(self, x: u32) -> (Self, Self) {
+        let x = x.min(self.spec.dims.width);
+        let (left, right) = self.pixels.split_at_col(x);
+        (ImageSliceMut::part(&self.spec, 0, 0, left), ImageSliceMut::part(&self.spec, x, 0, right))
+    }
 
-        for j in 0..dims.height {
-            let offset = w * j * 4 + off_x*4 + off_y*w*4;
-            for i in 0..dims.width * 4 {
-                buffer.push(self.pixels[(offset+i) as usize]);
+    /// Cuts into tiles of `tile_w` x `tile_h`, row by row, which can be edited
+    /// at the same time. Tiles along the right and bottom edges may be smaller.
+    /// Panics if either tile size is zero.
+    pub fn into_tiles(self, tile_w: u32, tile_h: u32) -> Vec<Self> {
+        assert!(tile_w > 0 && tile_h > 0, "tile size must be non-zero");
+        let mut tiles = Vec::new();
+        let mut rest = self;
+        while rest.spec.dims.height > 0 && rest.spec.dims.width > 0 {
+            let (mut band, below) = rest.split_at_row(tile_h);
+            rest = below;
+            while band.spec.dims.width > 0 {
+                let (tile, right) = band.split_at_col(tile_w);
+                tiles.push(tile);
+                band = right;
             }
         }
-
-        buffer.into_boxed_slice()
+        tiles
     }
 
-    fn crop(&self, x: u32, y: u32, dims: Dimensions) -> ImageSlice {
-        let img_dims = self.dimensions();
-        let x = cmp::min(img_dims.width, x);
-        let y = cmp::min(img_dims.height, y);
-
-        let w = cmp::min(dims.width, img_dims.width - x);
-        let h = cmp::min(dims.height, img_dims.height - y);
-
-        let spec = SliceSpec {offset: (x*4, y*4),
-            dims: Dimensions{ width: w, height: h},
-            size: (img_dims.width, img_dims.height)};
+    /*************** Crate Functions ***************/
 
-        ImageSlice {pixels: &self.pixels, spec}
+    /// Borrows a part of `pixels` already known to fit.
+    pub(crate) fn from_raw(pixels: &'a mut [P::Channel], spec: SliceSpec) -> Self {
+        ImageSliceMut {
+            pixels: StridedViewMut::from_raw_at(pixels, spec.origin, spec.dims, spec.stride),
+            spec,
+        }
     }
 
-    fn blurred(&self, amount: u32) -> OwnedImage {
+    /*************** Private Functions **************/
 
-        let width = self.spec.dims.width;
-        let height = self.spec.dims.height;
-
-        let owned = OwnedImage::new(Dimensions{ width, height}, self.pixels());
-        owned.blurred(amount)
+    /// Wraps `pixels`, the part of the slice `spec` starting at (`x`, `y`).
+    fn part(spec: &SliceSpec, x: u32, y: u32, pixels: StridedViewMut<'a, P>) -> Self {
+        let spec = SliceSpec {
+            origin: spec.index(x, y, P::CHANNELS),
+            stride: spec.stride,
+            dims: *pixels.dimensions(),
+        };
+        ImageSliceMut { pixels, spec }
     }
+}
 
-    fn flipped(&self, horiz: bool, vert: bool) -> OwnedImage {
-
-        let width = self.spec.dims.width;
-        let height = self.spec.dims.height;
+impl<'a, P: PixelType> Image for ImageSlice<'a, P> {
+    type Pixel = P;
 
-        let owned = OwnedImage::new(Dimensions{ width, height}, self.pixels());
-        owned.flipped(horiz, vert)
+    fn dimensions(&self) -> &Dimensions {
+        &self.spec.dims
     }
 
-    fn greyscale(&self) -> OwnedImage {
-
-        let width = self.spec.dims.width;
-        let height = self.spec.dims.height;
+    fn view(&self) -> StridedView<'_, P> {
+        self.pixels
+    }
 
-        let owned = OwnedImage::new(Dimensions{ width, height}, self.pixels());
-        owned.greyscale()
+    fn crop(&self, x: u32, y: u32, dims: Dimensions) -> Result<ImageSlice<'_, P>, ImageError> {
+        let spec = self.spec.crop(x, y, dims, P::CHANNELS)?;
+        Ok(ImageSlice { pixels: self.pixels.sub_view(x, y, spec.dims), spec })
     }
 }
 
-impl<'a> Image for ImageSliceMut<'a> {
+impl<'a, P: PixelType> Image for ImageSliceMut<'a, P> {
+    type Pixel = P;
+
     fn dimensions(&self) -> &Dimensions {
         &self.spec.dims
     }
 
-    fn pixels(&self) -> Matrix<u8> {
-        let (off_x, off_y)= self.spec.offset;
-        let (w, _) = self.spec.size;
-        let dims = &self.spec.dims;
-
-        let mut buffer = Vec::new();
-
-        for j in 0..dims.height {
-            let offset = w * j * 4 + off_x*4+ off_y*w*4;
-            for i in 0..dims.width * 4 {
-                buffer.push(self.pixels[(offset+i) as usize]);
-            }
-        }
-
-        buffer.into_boxed_slice()
+    fn view(&self) -> StridedView<'_, P> {
+        self.pixels.as_view()
     }
 
-    fn crop(&self, x: u32, y: u32, dims: Dimensions) -> ImageSlice {
-        let img_dims = self.dimensions();
-        let x = cmp::min(img_dims.width, x);
-        let y = cmp::min(img_dims.height, y);
-
-        let w = cmp::min(dims.width, img_dims.width - x);
-        let h = cmp::min(dims.height, img_dims.height - y);
-
-        let spec = SliceSpec {offset: (x*4, y*4),
-            dims: Dimensions{ width: w, height: h},
-            size: (img_dims.width, img_dims.height)};
-
-        ImageSlice {pixels: &self.pixels, spec}
+    fn crop(&self, x: u32, y: u32, dims: Dimensions) -> Result<ImageSlice<'_, P>, ImageError> {
+        let spec = self.spec.crop(x, y, dims, P::CHANNELS)?;
+        Ok(ImageSlice { pixels: self.pixels.as_view().sub_view(x, y, spec.dims), spec })
     }
+}
 
-    fn blurred(&self, amount: u32) -> OwnedImage {
-
-        let width = self.spec.dims.width;
-        let height = self.spec.dims.height;
-
-        let owned = OwnedImage::new(Dimensions{ width, height}, self.pixels());
-        owned.blurred(amount)
+impl<'a, P: PixelType> ImageMut for ImageSliceMut<'a, P> {
+    fn crop_mut(&mut self, x: u32, y: u32, dims: Dimensions) -> Result<ImageSliceMut<'_, P>, ImageError> {
+        let spec = self.spec.crop(x, y, dims, P::CHANNELS)?;
+        Ok(ImageSliceMut { pixels: self.pixels.reborrow().sub_view(x, y, spec.dims), spec })
     }
 
-    fn flipped(&self, horiz: bool, vert: bool) -> OwnedImage {
-
-        let width = self.spec.dims.width;
-        let height = self.spec.dims.height;
-
-        let owned = OwnedImage::new(Dimensions{ width, height}, self.pixels());
-        owned.flipped(horiz, vert)
+    fn view_mut(&mut self) -> StridedViewMut<'_, P> {
+        self.pixels.reborrow()
     }
 
-    fn greyscale(&self) -> OwnedImage {
-
-        let width = self.spec.dims.width;
-        let height = self.spec.dims.height;
-
-        let owned = OwnedImage::new(Dimensions{ width, height}, self.pixels());
-        owned.greyscale()
+    /// Writes `source` into this slice's rectangle of the parent buffer. Fails,
+    /// writing nothing, unless `source` is exactly the slice's size.
+    fn copy_from(&mut self, source: &impl Image<Pixel = P>) -> Result<(), ImageError> {
+        let (dims, other) = (self.spec.dims, *source.dimensions());
+        if dims != other {
+            return DimensionMismatch {
+                width: dims.width, height: dims.height, source_width: other.width, source_height: other.height,
+            }.fail();
+        }
+        self.view_mut().copy_from_view(&source.view());
+        Ok(())
     }
-}
\ No newline at end of file
+}
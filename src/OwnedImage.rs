@@ -1,7 +1,10 @@
 use super::traits::*;
 use super::ImageSlice::*;
 use super::utils::*;
-use std::{cmp};
+use super::pixel::*;
+use super::kernel::EdgeMode;
+use super::blur;
+use super::view::{StridedView, StridedViewMut};
 pub type Matrix<T> = Box<[T]>;
 
 use image::{DynamicImage, GenericImageView};
@@ -10,315 +13,153 @@ use image::{DynamicImage, GenericImageView};
 /******************************* OwnedImage Struct and Member Functions *******************************/
 /******************************************************************************************************/
 
-/// An owned image.
-pub struct OwnedImage {
+/// An owned image. Pixels are 8-bit RGBA unless another `PixelType` is given.
+pub struct OwnedImage<P: PixelType = Rgba8> {
     dims: Dimensions,
-    pixels: Matrix<u8>,
+    pixels: Matrix<P::Channel>,
 }
 
-impl OwnedImage {
+impl<P: PixelType> OwnedImage<P> {
 
-    pub fn import(image:DynamicImage) -> OwnedImage {
+    /// Converts a decoded image of any colour type and bit depth into this
+    /// image's pixel type. Greyscale is spread over colour channels, colour
+    /// collapses to luma, missing alpha becomes opaque, and channels are
+    /// rescaled to the target depth.
+    pub fn import(image:DynamicImage) -> OwnedImage<P> {
         let (w, h) = image.dimensions();
         OwnedImage {
             dims: Dimensions { width: w, height: h},
-            pixels: image.raw_pixels().into_boxed_slice(),
+            pixels: P::from_dynamic(image).into_boxed_slice(),
         }
     }
 
-    pub fn new (dims: Dimensions, pixels: Matrix<u8>) -> OwnedImage {
+    /// Builds an image from a raw buffer of interleaved channels.
+    /// Fails if `pixels` doesn't hold exactly `CHANNELS * width * height` values.
+    pub fn new (dims: Dimensions, pixels: Matrix<P::Channel>) -> Result<OwnedImage<P>, ImageError> {
+        let expected = (dims.width as usize) * (dims.height as usize) * P::CHANNELS;
+        if pixels.len() != expected {
+            return BufferSize {
+                width: dims.width,
+                height: dims.height,
+                expected,
+                actual: pixels.len(),
+            }.fail();
+        }
+        Ok(OwnedImage::from_raw(dims, pixels))
+    }
+
+    /// Reads the pixel at (`x`, `y`).
+    pub fn get_pixel(&self, x: u32, y: u32) -> Result<P, ImageError> {
+        check_bounds(x, y, &self.dims)?;
+        Ok(self.pixel(x, y))
+    }
+
+    /// Overwrites the pixel at (`x`, `y`).
+    pub fn set_pixel(&mut self, x: u32, y: u32, pix: P) -> Result<(), ImageError> {
+        check_bounds(x, y, &self.dims)?;
+        self.put_pixel(x, y, pix);
+        Ok(())
+    }
+
+    /*************** Crate Functions ***************/
+
+    /// Builds an image from a buffer whose length is already known to match `dims`.
+    pub(crate) fn from_raw(dims: Dimensions, pixels: Matrix<P::Channel>) -> OwnedImage<P> {
         OwnedImage {
             dims,
             pixels,
         }
     }
 
-    /*************** Private Functions **************/
+    /// Copies a view's pixels into a new image.
+    pub(crate) fn from_view(view: &StridedView<'_, P>) -> OwnedImage<P> {
+        OwnedImage::from_raw(*view.dimensions(), view.to_vec().into_boxed_slice())
+    }
 
-    fn get_pixels(&self) -> &[u8] {
+    pub(crate) fn get_pixels(&self) -> &[P::Channel] {
         &self.pixels
     }
 
-    fn get_pixels_mut(&mut self) -> &mut [u8] {
+    /*************** Private Functions **************/
+
+    fn get_pixels_mut(&mut self) -> &mut [P::Channel] {
         &mut self.pixels
     }
 
-    fn get_pixel(&self, x: u32, y: u32) -> Pixel {
-        let w = self.dims.width;
-        let h = self.dims.height;
+    fn index(&self, x: u32, y: u32) -> usize {
+        (y as usize * self.dims.width as usize + x as usize) * P::CHANNELS
+    }
 
-        if x >= w || y >= h{
-            dispatch_error(ImageError::IndexOutOfBound)
-        }
-        let index = ((y*w*4) + 4 * x) as usize;
-        Pixel {
-            r: self.pixels[index],
-            g: self.pixels[index+1],
-            b: self.pixels[index+2],
-            alpha: self.pixels[index+3],
-        }
+    // Unchecked accessors for loops that already stay within the image.
+    fn pixel(&self, x: u32, y: u32) -> P {
+        let index = self.index(x, y);
+        P::from_channels(&self.pixels[index..index + P::CHANNELS])
     }
 
-    fn set_pixel(&mut self, x: u32, y: u32, pix: Pixel) {
-        let w = self.dims.width;
-        let h = self.dims.height;
-        if x >= w || y >= h{
-            dispatch_error(ImageError::IndexOutOfBound)
-        }
-        let index = ((y*w*4) + 4 * x) as usize;
-        self.pixels[index] = pix.r;
-        self.pixels[index+1] = pix.g;
-        self.pixels[index+2] = pix.b;
-        self.pixels[index+3] = pix.alpha;
+    fn put_pixel(&mut self, x: u32, y: u32, pix: P) {
+        let index = self.index(x, y);
+        pix.write_channels(&mut self.pixels[index..index + P::CHANNELS]);
     }
 }
 
 /************************************* Image Trait for OwnedImage *************************************/
 /******************************************************************************************************/
 
-impl Image for OwnedImage{
+impl<P: PixelType> Image for OwnedImage<P> {
+    type Pixel = P;
 
     fn dimensions(&self) -> &Dimensions {
         &self.dims
     }
 
-    fn pixels(&self) -> Matrix<u8> {
-        self.pixels.clone()
+    fn view(&self) -> StridedView<'_, P> {
+        StridedView::from_raw(&self.pixels, self.dims, self.dims.width as usize * P::CHANNELS)
     }
 
-    fn crop(&self, x: u32, y: u32, dims: Dimensions) -> ImageSlice {
-        let img_dims = self.dimensions();
-        let x = cmp::min(img_dims.width, x);
-        let y = cmp::min(img_dims.height, y);
-
-        let w = cmp::min(dims.width, img_dims.width - x);
-        let h = cmp::min(dims.height, img_dims.height - y);
-
-        let spec = SliceSpec {offset: (x, y),
-            dims: Dimensions{ width: w, height: h},
-            size: (img_dims.width, img_dims.height)};
-
-        ImageSlice::new(&self.get_pixels(), spec)
-    }
-
-    fn blurred(&self, amount: u32) -> OwnedImage {
-        let sigma = amount as f32;
-        let n = 3.0;
-        let wideal = (12.0 * sigma * sigma / n + 1.0).sqrt();
-        let wl = wideal.floor();
-        let wu = wl + 2.0;
-
-        let mideal = (12.0 * sigma * sigma - n * wl * wl - 4.0*n*wl-3.0*n) / (-4.0*wl - 4.0);
-        let m = mideal.ceil();
-
-        let mut sizes: Vec<i32> = Vec::new();
-        for i in 0..n as i32{
-            if i < m as i32 {
-                sizes.push(wl as i32);
-            } else {
-                sizes.push(wu as i32);
-            }
-        }
-
-        let w  = self.dims.width;
-        let h = self.dims.height;
-
-        let mut copy = OwnedImage:: new(Dimensions{ width: w, height: h}, self.pixels.clone());
-
-        box_blur(&mut copy, w, h, (sizes[0]-1)/2);
-        box_blur(&mut copy, w, h, (sizes[1]-1)/2 );
-        box_blur(&mut copy, w, h, (sizes[2]-1)/2 );
-
-        copy
-    }
-
-    fn flipped(&self, horiz: bool, vert: bool) -> OwnedImage {
-        let width = self.dims.width;
-        let height = self.dims.height;
-
-        let mut pixels = Vec::new();
-
-        for j in 0..height {
-            for i in 0..width {
-                let p;
-                if horiz && vert {
-                    p = self.get_pixel(width-i-1, height-j-1);
-                } else if horiz {
-                    p = self.get_pixel(width-i-1, j);
-                } else if vert {
-                    p = self.get_pixel(i, height-j-1);
-                } else {
-                    p = self.get_pixel(i, j);
-                }
-                pixels.push(p.r);
-                pixels.push(p.g);
-                pixels.push(p.b);
-                pixels.push(p.alpha);
-            }
-        }
-
-        OwnedImage:: new(Dimensions{ width, height}, pixels.into_boxed_slice())
-    }
-
-    fn greyscale(&self) -> OwnedImage {
-        let width = self.dims.width;
-        let height = self.dims.height;
-
-        let mut pixels = Vec::new();
-
-        for j in 0..height {
-            for i in 0..width {
-                let p = self.get_pixel(i,j);
-                let avg = 0.299 * (p.r as f32) + 0.587 * (p.g as f32) + 0.114 * (p.b as f32);
-                let avg = avg as u8;
-                pixels.push(avg);
-                pixels.push(avg);
-                pixels.push(avg);
-                pixels.push(p.alpha);
-            }
-        }
-
-        OwnedImage:: new(Dimensions{ width, height}, pixels.into_boxed_slice())
+    fn crop(&self, x: u32, y: u32, dims: Dimensions) -> Result<ImageSlice<'_, P>, ImageError> {
+        let spec = SliceSpec::whole(self.dims, P::CHANNELS).crop(x, y, dims, P::CHANNELS)?;
+        Ok(ImageSlice::from_raw(self.get_pixels(), spec))
     }
 }
 
 /*********************************** ImageMut Trait for OwnedImage ************************************/
 /******************************************************************************************************/
 
-impl ImageMut for OwnedImage {
-    fn crop_mut(&mut self, x: u32, y: u32, dims: Dimensions) -> ImageSliceMut {
-        let img_dims = self.dimensions();
-        let x = cmp::min(img_dims.width, x);
-        let y = cmp::min(img_dims.height, y);
-
-        let w = cmp::min(dims.width, img_dims.width - x);
-        let h = cmp::min(dims.height, img_dims.height - y);
-
-        let spec = SliceSpec {offset: (x, y),
-            dims: Dimensions{ width: w, height: h},
-            size: (img_dims.width, img_dims.height)};
-
-        ImageSliceMut::new(self.get_pixels_mut(), spec)
-    }
-
-    fn blur(&mut self, amount: u32) {
-        let sigma = amount as f32;
-        let n = 3.0;
-        let wideal = (12.0 * sigma * sigma / n + 1.0).sqrt();
-        let wl = wideal.floor();
-        let wu = wl + 2.0;
-
-        let mideal = (12.0 * sigma * sigma - n * wl * wl - 4.0*n*wl-3.0*n) / (-4.0*wl - 4.0);
-        let m = mideal.ceil();
-
-        let mut sizes: Vec<i32> = Vec::new();
-        for i in 0..3{
-            if i < m as i32 {
-                sizes.push(wl as i32);
-            } else {
-                sizes.push(wu as i32);
-            }
-        }
-
-        let w  = self.dims.width;
-        let h = self.dims.height;
-
-        box_blur(self, w, h, (sizes[0]-1)/2);
-        box_blur(self, w, h, (sizes[1]-1)/2 );
-        box_blur(self, w, h, (sizes[2]-1)/2 );
+impl<P: PixelType> ImageMut for OwnedImage<P> {
+    fn crop_mut(&mut self, x: u32, y: u32, dims: Dimensions) -> Result<ImageSliceMut<'_, P>, ImageError> {
+        let spec = SliceSpec::whole(self.dims, P::CHANNELS).crop(x, y, dims, P::CHANNELS)?;
+        Ok(ImageSliceMut::from_raw(self.get_pixels_mut(), spec))
     }
 
-    fn flip(&mut self, horiz: bool, vert: bool) {
-        let width = self.dims.width;
-        let height = self.dims.height;
-
-        for j in 0..height {
-            for i in 0..(width+1)/2 {
-                let saved = self.get_pixel(i, j);
-
-                let p;
-                if horiz && vert {
-                    p = self.get_pixel(width-i-1, height-j-1);
-                    self.set_pixel(width-i-1, height-j-1, saved);
-                } else if horiz {
-                    p = self.get_pixel(width-i-1, j);
-                    self.set_pixel(width-i-1, j, saved);
-                } else if vert {
-                    p = self.get_pixel(i, height-j-1);
-                    self.set_pixel(i, height-j-1, saved);
-                } else {
-                    break;
-                }
-                self.set_pixel(i, j, p);
-            }
-        }
+    fn view_mut(&mut self) -> StridedViewMut<'_, P> {
+        let row_len = self.dims.width as usize * P::CHANNELS;
+        StridedViewMut::from_raw(&mut self.pixels, self.dims, row_len)
     }
 
-    fn greyscale_mut(&mut self) {
-        let width = self.dims.width;
-        let height = self.dims.height;
-
-        for j in 0..height {
-            for i in 0..width {
-                let p = self.get_pixel(i,j);
-                let avg = 0.299 * (p.r as f32) + 0.587 * (p.g as f32) + 0.114 * (p.b as f32);
-                let avg = avg as u8;
-                self.set_pixel(i, j, Pixel {
-                    r:avg,
-                    g:avg,
-                    b:avg,
-                    alpha:p.alpha
-                });
-            }
-        }
-    }
-
-    fn blur_from(&mut self, amount: u32, source: impl Image) {
-        self.copy_from(&source);
-        self.blur(amount);
+    fn blur_from(&mut self, amount: u32, edge: EdgeMode<P>, source: impl Image<Pixel = P>) -> Result<(), ImageError> {
+        self.copy_from(&source)?;
+        self.blur(amount, edge)
     }
 
-    fn flip_from(&mut self, horiz: bool, vert: bool, source: impl Image) {
-        self.copy_from(&source);
+    fn flip_from(&mut self, horiz: bool, vert: bool, source: impl Image<Pixel = P>) -> Result<(), ImageError> {
+        self.copy_from(&source)?;
         self.flip(horiz, vert);
+        Ok(())
     }
 
-    fn copy_from(&mut self, source: &impl Image) {
+    fn copy_from(&mut self, source: &impl Image<Pixel = P>) -> Result<(), ImageError> {
         self.dims = Dimensions {
             width: source.dimensions().width,
             height: source.dimensions().height
         };
-        self.pixels = source.pixels().clone()
+        self.pixels = source.pixels();
+        Ok(())
     }
 }
 
 
-pub fn box_blur(image: &mut OwnedImage, w: u32, h:u32, r:i32){
-    for j in 0..h as i32{
-        for i in 0..w as i32{
-            let initial_pixel = image.get_pixel(i as u32, j as u32);
-            let total_ct= ((r+r+1)*(r+r+1)) as u32;
-            let mut r_total = 0;
-            let mut g_total = 0;
-            let mut b_total = 0;
-            for iy in (j-r)..(j+r+1){
-                for ix in (i-r)..(i+r+1){
-                    let x = cmp::min(w-1, cmp::max(0, ix as u32));
-                    let y = cmp::min(h-1, cmp::max(0, iy as u32));
-                    let this_pixel = image.get_pixel(x as u32, y as u32);
-
-                    r_total += this_pixel.r as u32;
-                    b_total += this_pixel.b as u32;
-                    g_total += this_pixel.g as u32;
-                }
-            }
-            image.set_pixel(i as u32, j as u32, Pixel{
-                r: (r_total/total_ct) as u8,
-                g: (g_total/total_ct) as u8,
-                b:(b_total/total_ct) as u8,
-                alpha: initial_pixel.alpha,
-            });
-
-        }
-    }
+/// One box-blur pass of the given radius. Alpha keeps each pixel's initial value.
+/// Costs the same for any radius.
+pub fn box_blur<P: PixelType>(image: &OwnedImage<P>, r: usize, edge: &EdgeMode<P>) -> OwnedImage<P> {
+    blur::box_blur(&image.view(), &[r], edge)
 }
@@ -0,0 +1,62 @@
+use super::pixel::*;
+use super::view::StridedViewMut;
+
+/************************************** Tone Adjustments **********************************************/
+/******************************************************************************************************/
+
+// Each adjustment is a curve over channel values scaled to 0.0-1.0. Colour
+// (or luma) channels go through it one by one; alpha is left alone.
+
+/// Adds `amount` to every value, so -1.0 turns everything black and 1.0 white.
+pub(crate) fn brightness(amount: f32) -> impl Fn(f32) -> f32 + Sync {
+    move |v| v + amount
+}
+
+/// Scales each value's distance from mid-grey by `factor`: 1.0 keeps the
+/// image as it is, 0.0 turns it flat grey, and above 1.0 adds contrast.
+pub(crate) fn contrast(factor: f32) -> impl Fn(f32) -> f32 + Sync {
+    move |v| (v - 0.5) * factor + 0.5
+}
+
+/// Raises each value to `1 / gamma`, so gammas above 1.0 brighten the
+/// midtones and below darken them, keeping black and white. A gamma of zero
+/// (or a negative or NaN one) leaves the image unchanged.
+pub(crate) fn gamma(gamma: f32) -> impl Fn(f32) -> f32 + Sync {
+    let exponent = if gamma.is_nan() || gamma <= 0.0 { 1.0 } else { 1.0 / gamma };
+    // negative float values mirror the positive curve
+    move |v| v.signum() * v.abs().powf(exponent)
+}
+
+/// Multiplies every value by 2 to the power of `stops`, like opening the lens
+/// by that many stops.
+pub(crate) fn exposure(stops: f32) -> impl Fn(f32) -> f32 + Sync {
+    let scale = stops.exp2();
+    move |v| v * scale
+}
+
+/// Passes every colour channel of `view` through `curve`. 8-bit channels look
+/// their results up in a table; others are computed exactly. Integer results
+/// are rounded and clamped, floats are stored as they are.
+pub(crate) fn apply_curve<P: PixelType>(view: &mut StridedViewMut<'_, P>, curve: impl Fn(f32) -> f32 + Sync) {
+    let map = channel_map::<P::Channel>(curve);
+    view.for_each_row(|_, row| {
+        for pixel in row.chunks_mut(P::CHANNELS) {
+            for (c, value) in pixel.iter_mut().enumerate() {
+                if Some(c) != P::ALPHA {
+                    *value = map(*value);
+                }
+            }
+        }
+    });
+}
+
+/// `curve` as a function on channel values, tabulated when they are few.
+fn channel_map<C: Primitive>(curve: impl Fn(f32) -> f32 + Sync) -> impl Fn(C) -> C + Sync {
+    let table: Vec<C> = (0..C::LUT_SIZE)
+        .map(|i| C::from_unit(curve(i as f32 / C::MAX)))
+        .collect();
+    move |value| match table.get(value.to_f32() as usize) {
+        Some(&mapped) => mapped,
+        None => C::from_unit(curve(value.to_unit())),
+    }
+}
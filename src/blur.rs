@@ -0,0 +1,479 @@
+use super::OwnedImage::*;
+use super::utils::*;
+use super::pixel::*;
+use super::kernel::{self, EdgeMode};
+use super::parallel;
+use super::view::StridedView;
+use std::cmp::Ordering;
+
+/*************************************** Gaussian Blur ************************************************/
+/******************************************************************************************************/
+
+/// Sigmas up to this use an exact Gaussian kernel. Larger ones use three box
+/// blurs, whose cost doesn't grow with sigma.
+const EXACT_SIGMA_LIMIT: f32 = 4.0;
+
+/// Sigma is capped at this many times the image's extent along the axis.
+/// Past that the result no longer changes visibly, and infinite sigmas stay finite.
+const MAX_SIGMA_PER_PIXEL: f32 = 256.0;
+
+/// Blurs with a Gaussian of `sigma_x` horizontally and `sigma_y` vertically.
+/// A sigma of zero (or a negative or NaN one) leaves that axis untouched.
+/// Alpha keeps each pixel's initial value, like the box blur.
+pub(crate) fn gaussian_blur<P: PixelType>(view: &StridedView<'_, P>, sigma_x: f32, sigma_y: f32,
+                                          edge: &EdgeMode<P>) -> OwnedImage<P> {
+    separable_blur(view, edge, |values, size, horizontal, constant| {
+        let sigma = if horizontal { sigma_x } else { sigma_y };
+        gaussian_axis(values, size, P::CHANNELS, sigma, horizontal, edge, constant)
+    })
+}
+
+/// The original blur: three box blurs approximating a Gaussian of sigma `amount`.
+pub(crate) fn blurred<P: PixelType>(view: &StridedView<'_, P>, amount: u32, edge: &EdgeMode<P>) -> OwnedImage<P> {
+    let radii: Vec<usize> = box_sizes(amount).iter().map(|size| (size - 1) as usize / 2).collect();
+    box_blur(view, &radii, edge)
+}
+
+/// Box widths for three box-blur passes that approximate a Gaussian of the given sigma.
+fn box_sizes(amount: u32) -> [i32; 3] {
+    let sigma = amount as f32;
+    let n = 3.0;
+    let wideal = (12.0 * sigma * sigma / n + 1.0).sqrt();
+    let wl = wideal.floor();
+    let wu = wl + 2.0;
+
+    let mideal = (12.0 * sigma * sigma - n * wl * wl - 4.0*n*wl-3.0*n) / (-4.0*wl - 4.0);
+    let m = mideal.ceil();
+
+    let mut sizes = [0; 3];
+    for (i, size) in sizes.iter_mut().enumerate() {
+        if (i as i32) < m as i32 {
+            *size = wl as i32;
+        } else {
+            *size = wu as i32;
+        }
+    }
+    sizes
+}
+
+/// Blurs with successive box blurs of the given radii along both axes.
+/// Alpha keeps each pixel's initial value.
+pub(crate) fn box_blur<P: PixelType>(view: &StridedView<'_, P>, radii: &[usize], edge: &EdgeMode<P>)
+                                     -> OwnedImage<P> {
+    let radii: Vec<Radius> = radii.iter().map(|&radius| (radius, 0.0)).collect();
+    separable_blur(view, edge, |values, size, horizontal, constant| {
+        box_axis(values, size, P::CHANNELS, &radii, horizontal, edge, constant)
+    })
+}
+
+/// Runs `blur_axis` horizontally then vertically over the image as unit
+/// floats, so channels are only rounded once at the end. `blur_axis` gets the
+/// values, their size, the axis and the constant edge colour, and returns the
+/// new extent along its axis, the values, and how far cropping shifted them.
+fn separable_blur<P: PixelType>(view: &StridedView<'_, P>, edge: &EdgeMode<P>,
+                                blur_axis: impl Fn(Vec<f32>, (usize, usize), bool, &[f32]) -> (usize, Vec<f32>, usize))
+                                -> OwnedImage<P> {
+    let c = P::CHANNELS;
+    let (w, h) = (view.dimensions().width as usize, view.dimensions().height as usize);
+    if w == 0 || h == 0 {
+        return OwnedImage::from_view(view);
+    }
+
+    let constant: Vec<f32> = match edge {
+        EdgeMode::Constant(colour) => {
+            let mut channels = [P::Channel::default(); 4];
+            colour.write_channels(&mut channels);
+            channels[..c].iter().map(|v| v.to_unit()).collect()
+        }
+        _ => vec![0.0; c],
+    };
+    let input: Vec<f32> = view.rows().flatten().map(|v| v.to_unit()).collect();
+
+    let (out_w, horiz, ox) = blur_axis(input, (w, h), true, &constant);
+    let (out_h, values, oy) = blur_axis(horiz, (out_w, h), false, &constant);
+
+    // output pixel (x, y) comes from source pixel (x + ox, y + oy) when cropping
+    let mut out = vec![P::Channel::default(); out_w * out_h * c];
+    parallel::for_each_band(&mut out, out_w * c, |first_row, band| {
+        let start = first_row * out_w;
+        for (i, (dst, result)) in (start..).zip(band.chunks_mut(c).zip(values[start * c..].chunks(c))) {
+            let src = view.at(i % out_w + ox, i / out_w + oy);
+            for (ch, (dst, value)) in dst.iter_mut().zip(result).enumerate() {
+                *dst = if Some(ch) == P::ALPHA { src[ch] } else { P::Channel::from_unit(*value) };
+            }
+        }
+    });
+
+    OwnedImage::from_raw(Dimensions { width: out_w as u32, height: out_h as u32 }, out.into_boxed_slice())
+}
+
+/// Gaussian blur along one axis.
+fn gaussian_axis<P: PixelType>(input: Vec<f32>, (w, h): (usize, usize), c: usize, sigma: f32, horizontal: bool,
+                               edge: &EdgeMode<P>, constant: &[f32]) -> (usize, Vec<f32>, usize) {
+    let len = if horizontal { w } else { h };
+    if sigma.is_nan() || sigma <= 0.0 || len == 0 {
+        return (len, input, 0);
+    }
+    let sigma = sigma.min(len as f32 * MAX_SIGMA_PER_PIXEL);
+
+    if sigma <= EXACT_SIGMA_LIMIT {
+        let taps = gaussian_taps(sigma);
+        let shift = if *edge == EdgeMode::Crop { taps.len() / 2 } else { 0 };
+        let (out_len, out) = kernel::convolve_axis(&input, (w, h), c, &taps, horizontal, edge, constant);
+        return (out_len, out, shift);
+    }
+    let radii = box_radii(sigma);
+    match edge {
+        // periodic extensions of a blurred line are still exact, and cropping reads nothing outside
+        EdgeMode::Wrap | EdgeMode::Mirror | EdgeMode::Crop => box_axis(input, (w, h), c, &radii, horizontal, edge, constant),
+        _ => extended_box_axis(input, (w, h), c, &radii, horizontal, edge, constant),
+    }
+}
+
+/// Successive box blurs along one axis that read beyond the edges only once,
+/// as the exact kernel does. Letting each pass extend the previous one's
+/// result instead would weigh the edge pixels more than the Gaussian does.
+/// The passes are applied together, as differences of each line's third
+/// running sum, which has a closed form beyond the edges, so the cost doesn't
+/// grow with their reach. With `Skip`, each result is divided by how much of
+/// its window lay inside the line.
+fn extended_box_axis<P: PixelType>(input: Vec<f32>, (w, h): (usize, usize), c: usize, radii: &[Radius],
+                                   horizontal: bool, edge: &EdgeMode<P>, constant: &[f32])
+                                   -> (usize, Vec<f32>, usize) {
+    let len = if horizontal { w } else { h };
+    let taps = cascade_taps(radii);
+    let apply = |sums: &ThirdSums, x: usize, lane: usize| -> f64 {
+        taps.iter().map(|&(offset, weight)| weight * sums.at(x as i64 + offset, lane)).sum()
+    };
+    let coverage: Option<Vec<f64>> = match edge {
+        EdgeMode::Skip => {
+            let inside = ThirdSums::new(&vec![1.0; len], 0, 1, 1, len, |_| (0.0, 0.0));
+            Some((0..len).map(|x| apply(&inside, x, 0)).collect())
+        }
+        _ => None,
+    };
+
+    // blurs the line of `lanes` values from `base`, repeated every `step`, into `out`
+    let blur_line = |base: usize, step: usize, lanes: usize, out: &mut [f32]| {
+        let sums = ThirdSums::new(&input, base, step, lanes, len, |lane| match edge {
+            EdgeMode::Clamp => (input[base + lane] as f64, input[base + (len - 1) * step + lane] as f64),
+            EdgeMode::Constant(_) => (constant[lane % c] as f64, constant[lane % c] as f64),
+            _ => (0.0, 0.0),
+        });
+        for (x, values) in out.chunks_mut(lanes).enumerate() {
+            let inside = coverage.as_ref().map_or(1.0, |coverage| coverage[x]);
+            for (lane, value) in values.iter_mut().enumerate() {
+                *value = (apply(&sums, x, lane) / inside) as f32;
+            }
+        }
+    };
+
+    let mut out = vec![0.0; w * h * c];
+    if horizontal {
+        parallel::for_each_band(&mut out, w * c, |first_row, band| {
+            for (y, row) in (first_row..).zip(band.chunks_mut(w * c)) {
+                blur_line(y * w * c, c, c, row);
+            }
+        });
+    } else {
+        // columns go in strips, as in `box_pass`
+        let strips: Vec<usize> = (0..w).step_by(COLUMN_STRIP).collect();
+        let columns = parallel::map_each(&strips, |&x| {
+            let lanes = (w - x).min(COLUMN_STRIP) * c;
+            let mut strip = vec![0.0; h * lanes];
+            blur_line(x * c, w * c, lanes, &mut strip);
+            strip
+        });
+        for (x, strip) in strips.iter().zip(columns) {
+            let lanes = strip.len() / h;
+            for (row, values) in out.chunks_mut(w * c).zip(strip.chunks(lanes)) {
+                row[x * c..x * c + lanes].copy_from_slice(values);
+            }
+        }
+    }
+    (len, out, 0)
+}
+
+/// Offsets into a line's third running sum and their weights which, summed,
+/// give the successive box blurs of `radii`. A box of radius `r` is the first
+/// running sum at `x + r + 1` less that at `x - r`, divided by its width; each
+/// further box takes the next running sum.
+fn cascade_taps(radii: &[Radius]) -> Vec<(i64, f64)> {
+    radii.iter().fold(vec![(0, 1.0)], |taps, &(radius, wider)| {
+        let r = radius as i64;
+        let (narrow, wide) = ((1.0 - wider as f64) / (2 * r + 1) as f64, wider as f64 / (2 * r + 3) as f64);
+        let pass: Vec<(i64, f64)> = [(r + 1, narrow), (-r, -narrow), (r + 2, wide), (-r - 1, -wide)]
+            .iter().copied().filter(|&(_, weight)| weight != 0.0).collect();
+        taps.iter()
+            .flat_map(|&(offset, weight)| pass.iter().map(move |&(by, scale)| (offset + by, weight * scale)))
+            .collect()
+    })
+}
+
+/// Third running sums of interleaved lines, each extended beyond its ends by
+/// a constant. The first running sum at `j` adds up the values before `j`, the
+/// second the first ones before `j`, and so on; over negative indices the
+/// sums run the other way and count negatively, so every running sum differs
+/// from its neighbour by the previous one.
+struct ThirdSums {
+    /// The third running sums at 0 to `len` of each lane, interleaved.
+    sums: Vec<f64>,
+    /// The first and second running sums at `len` of each lane, and the values
+    /// the lane is extended with before and after.
+    ends: Vec<[f64; 4]>,
+    len: usize,
+    lanes: usize,
+}
+
+impl ThirdSums {
+    /// The sums of `lanes` lines of `len` values from `base`, repeated every
+    /// `step`, extended by the values `ends` gives for each lane.
+    fn new(values: &[f32], base: usize, step: usize, lanes: usize, len: usize, ends: impl Fn(usize) -> (f64, f64))
+           -> ThirdSums {
+        let mut sums = vec![0.0; (len + 1) * lanes];
+        let mut running = vec![[0.0f64; 2]; lanes];
+        for i in 0..len {
+            let line = &values[base + i * step..base + i * step + lanes];
+            let (done, next) = sums.split_at_mut((i + 1) * lanes);
+            for (((next, third), [first, second]), &value) in next[..lanes].iter_mut()
+                .zip(&done[i * lanes..]).zip(running.iter_mut()).zip(line) {
+                *next = third + *second;
+                *second += *first;
+                *first += value as f64;
+            }
+        }
+        let ends = running.iter().enumerate().map(|(lane, &[first, second])| {
+            let (before, after) = ends(lane);
+            [first, second, before, after]
+        }).collect();
+        ThirdSums { sums, ends, len, lanes }
+    }
+
+    /// The third running sum of `lane` at `j`, anywhere on the extended line.
+    fn at(&self, j: i64, lane: usize) -> f64 {
+        let n = self.len as i64;
+        let [first, second, before, after] = self.ends[lane];
+        if j < 0 {
+            let m = -j as f64;
+            -before * m * (m + 1.0) * (m + 2.0) / 6.0
+        } else if j <= n {
+            self.sums[j as usize * self.lanes + lane]
+        } else {
+            let m = (j - n) as f64;
+            self.sums[self.len * self.lanes + lane] + m * second + first * m * (m - 1.0) / 2.0
+                + after * m * (m - 1.0) * (m - 2.0) / 6.0
+        }
+    }
+}
+
+/// Normalised Gaussian weights out to three sigma on either side.
+fn gaussian_taps(sigma: f32) -> Vec<f32> {
+    let radius = (3.0 * sigma).ceil() as i32;
+    let taps: Vec<f32> = (-radius..=radius)
+        .map(|i| (-(i * i) as f32 / (2.0 * sigma * sigma)).exp())
+        .collect();
+    let sum: f32 = taps.iter().sum();
+    taps.into_iter().map(|weight| weight / sum).collect()
+}
+
+/// Radii of three successive box blurs whose combined variance matches a
+/// Gaussian of `sigma` (Kovesi's method). Odd box widths only match some
+/// sigmas, so one pass blends two widths to make up the difference, which
+/// keeps the result from jumping as sigma grows.
+fn box_radii(sigma: f32) -> [Radius; 3] {
+    let n = 3.0;
+    let ideal = (12.0 * sigma * sigma / n + 1.0).sqrt();
+    let mut lower = ideal.floor();
+    if (lower as u64).is_multiple_of(2) {
+        lower -= 1.0;
+    }
+    let upper = lower + 2.0;
+
+    // how many passes of the lower width match the variance, which is seldom a whole number
+    let ideal_count = (12.0 * sigma * sigma - n * lower * lower - 4.0 * n * lower - 3.0 * n) / (-4.0 * lower - 4.0);
+    let ideal_count = ideal_count.clamp(0.0, n);
+    let count = ideal_count.floor() as usize;
+
+    let (lower, upper) = ((lower as usize - 1) / 2, (upper as usize - 1) / 2);
+    let mut radii = [(0, 0.0); 3];
+    for (i, radius) in radii.iter_mut().enumerate() {
+        *radius = match i.cmp(&count) {
+            Ordering::Less => (lower, 0.0),
+            Ordering::Equal => (lower, 1.0 - (ideal_count - count as f32)),
+            Ordering::Greater => (upper, 0.0),
+        };
+    }
+    radii
+}
+
+/********************************************* Box Blur ***********************************************/
+/******************************************************************************************************/
+
+/// Successive box blurs along one axis.
+fn box_axis<P: PixelType>(input: Vec<f32>, (w, h): (usize, usize), c: usize, radii: &[Radius], horizontal: bool,
+                          edge: &EdgeMode<P>, constant: &[f32]) -> (usize, Vec<f32>, usize) {
+    let (mut values, mut size, mut shift) = (input, (w, h), 0);
+    for radius in radii {
+        let (out_len, out) = box_pass(&values, size, c, *radius, horizontal, edge, constant);
+        if horizontal { size.0 = out_len } else { size.1 = out_len }
+        if *edge == EdgeMode::Crop {
+            shift += reach(radius);
+        }
+        values = out;
+    }
+    (if horizontal { size.0 } else { size.1 }, values, shift)
+}
+
+/// One box blur of `radius` along rows (`horizontal`) or columns, from `input`
+/// into a new buffer. Each window sum is the difference of two running sums
+/// over the line, so the cost doesn't depend on the radius. Returns the new
+/// extent along the pass axis, which only changes when cropping, and the values.
+fn box_pass<P: PixelType>(input: &[f32], (w, h): (usize, usize), c: usize, (radius, wider): Radius,
+                          horizontal: bool, edge: &EdgeMode<P>, constant: &[f32]) -> (usize, Vec<f32>) {
+    let len = if horizontal { w } else { h };
+    let (out_len, shift) = if *edge == EdgeMode::Crop {
+        let shift = reach(&(radius, wider));
+        (len.saturating_sub(2 * shift), shift)
+    } else {
+        (len, 0)
+    };
+    let pass = BoxPass { input, len, out_len, shift, radius, wider, c, edge, constant };
+
+    if horizontal {
+        let mut out = vec![0.0; out_len * h * c];
+        parallel::for_each_band(&mut out, out_len * c, |first_row, band| {
+            let mut prefix = Vec::new();
+            for (y, row) in (first_row..).zip(band.chunks_mut(out_len * c)) {
+                pass.line(y * w * c, c, c, row, &mut prefix);
+            }
+        });
+        (out_len, out)
+    } else {
+        // columns go in strips of neighbours, so each row read is contiguous
+        let strips: Vec<usize> = (0..w).step_by(COLUMN_STRIP).collect();
+        let blurred = parallel::map_each(&strips, |&x| {
+            let lanes = (w - x).min(COLUMN_STRIP) * c;
+            let mut strip = vec![0.0; out_len * lanes];
+            pass.line(x * c, w * c, lanes, &mut strip, &mut Vec::new());
+            strip
+        });
+
+        let mut out = vec![0.0; out_len * w * c];
+        for (x, strip) in strips.iter().zip(blurred) {
+            let lanes = strip.len() / out_len.max(1);
+            for (row, values) in out.chunks_mut(w * c).zip(strip.chunks(lanes.max(1))) {
+                row[x * c..x * c + lanes].copy_from_slice(values);
+            }
+        }
+        (out_len, out)
+    }
+}
+
+/// Columns blurred together by a vertical box pass.
+const COLUMN_STRIP: usize = 16;
+
+/// A box radius, and the weight given to the box one pixel wider on each side
+/// when blending the two.
+type Radius = (usize, f32);
+
+/// How far beyond its centre a box of `radius` reads.
+fn reach(&(radius, wider): &Radius) -> usize {
+    if wider > 0.0 { radius + 1 } else { radius }
+}
+
+/// What a box pass shares between the lines it blurs.
+struct BoxPass<'a, P: PixelType> {
+    input: &'a [f32],
+    /// Length of each line, and of each blurred line.
+    len: usize,
+    out_len: usize,
+    /// Offset into the line of the first blurred value's centre.
+    shift: usize,
+    radius: usize,
+    wider: f32,
+    c: usize,
+    edge: &'a EdgeMode<P>,
+    constant: &'a [f32],
+}
+
+impl<'a, P: PixelType> BoxPass<'a, P> {
+    /// Blurs one line: `lanes` values starting at `base`, repeated every
+    /// `step` along the axis. The blurred line is written to `out` with the
+    /// lanes packed together. `prefix` is scratch space.
+    fn line(&self, base: usize, step: usize, lanes: usize, out: &mut [f32], prefix: &mut Vec<f64>) {
+        let (n, r, c) = (self.len as i64, self.radius as i64, self.c);
+        if n == 0 {
+            return;
+        }
+        let (edge, constant) = (self.edge, self.constant);
+
+        prefix.clear();
+        prefix.resize((self.len + 1) * lanes, 0.0);
+        for i in 0..self.len {
+            let src = &self.input[base + i * step..base + i * step + lanes];
+            let (done, next) = prefix.split_at_mut((i + 1) * lanes);
+            for ((next, previous), value) in next[..lanes].iter_mut().zip(&done[i * lanes..]).zip(src) {
+                *next = previous + *value as f64;
+            }
+        }
+        let prefix = &prefix[..];
+        let at = |i: i64, k: usize| prefix[i as usize * lanes + k];
+        let total = |k: usize| at(n, k);
+
+        // sum over the extended line of the samples at indices [0, i), negative for i < 0
+        let cumulative = |i: i64, k: usize| -> f64 {
+            match edge {
+                EdgeMode::Clamp | EdgeMode::Constant(_) => {
+                    let (first, last) = match edge {
+                        EdgeMode::Constant(_) => (constant[k % c] as f64, constant[k % c] as f64),
+                        _ => (at(1, k) - at(0, k), at(n, k) - at(n - 1, k)),
+                    };
+                    if i <= 0 {
+                        i as f64 * first
+                    } else if i <= n {
+                        at(i, k)
+                    } else {
+                        total(k) + (i - n) as f64 * last
+                    }
+                }
+                EdgeMode::Wrap => i.div_euclid(n) as f64 * total(k) + at(i.rem_euclid(n), k),
+                EdgeMode::Mirror => {
+                    // one period is the line followed by its reverse
+                    let m = i.rem_euclid(2 * n);
+                    let partial = if m <= n { at(m, k) } else { 2.0 * total(k) - at(2 * n - m, k) };
+                    i.div_euclid(2 * n) as f64 * 2.0 * total(k) + partial
+                }
+                EdgeMode::Skip | EdgeMode::Crop => at(i.max(0).min(n), k),
+            }
+        };
+
+        // the box of `radius` and, when blending, the one a pixel wider, with their weights
+        let boxes = [(r, 1.0 - self.wider as f64), (r + 1, self.wider as f64)];
+        let boxes = if self.wider > 0.0 { &boxes[..] } else { &boxes[..1] };
+        for (p, dst) in out.chunks_mut(lanes).take(self.out_len).enumerate() {
+            let centre = (p + self.shift) as i64;
+            for (b, &(r, weight)) in boxes.iter().enumerate() {
+                let (first, last) = (centre - r, centre + r + 1);
+                let count = match edge {
+                    EdgeMode::Skip => (last.min(n) - first.max(0)) as f64,
+                    _ => (2 * r + 1) as f64,
+                };
+                let put = |value: &mut f32, sum: f64| {
+                    let mean = (sum / (count / weight)) as f32;
+                    if b == 0 { *value = mean } else { *value += mean }
+                };
+                if first >= 0 && last <= n {
+                    // the common case: the window lies inside the line
+                    let (low, high) = (&prefix[first as usize * lanes..], &prefix[last as usize * lanes..]);
+                    for ((value, low), high) in dst.iter_mut().zip(low).zip(high) {
+                        put(value, high - low);
+                    }
+                } else {
+                    for (k, value) in dst.iter_mut().enumerate() {
+                        put(value, cumulative(last, k) - cumulative(first, k));
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,453 @@
+use super::OwnedImage::OwnedImage;
+use super::traits::*;
+use super::utils::*;
+use super::pixel::*;
+use super::view::StridedViewMut;
+
+/**************************************** Colour Spaces ***********************************************/
+/******************************************************************************************************/
+
+// Hues are in degrees, 0.0 up to 360.0, with red at 0, green at 120 and blue
+// at 240. Greys have a hue and saturation of 0. Everything else is 0.0-1.0,
+// like the RGB values they convert from.
+
+/// A colour as hue, saturation and value.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Hsv {
+    pub h: f32,
+    pub s: f32,
+    pub v: f32,
+}
+
+/// A colour as hue, saturation and lightness.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Hsl {
+    pub h: f32,
+    pub s: f32,
+    pub l: f32,
+}
+
+impl Hsv {
+    pub fn from_rgb([r, g, b]: [f32; 3]) -> Hsv {
+        let (max, min) = (r.max(g).max(b), r.min(g).min(b));
+        let s = if max > 0.0 { (max - min) / max } else { 0.0 };
+        Hsv { h: hue(r, g, b, max, max - min), s, v: max }
+    }
+
+    pub fn to_rgb(&self) -> [f32; 3] {
+        let chroma = self.v * self.s;
+        from_hue(self.h, chroma, self.v - chroma)
+    }
+}
+
+impl Hsl {
+    pub fn from_rgb([r, g, b]: [f32; 3]) -> Hsl {
+        let (max, min) = (r.max(g).max(b), r.min(g).min(b));
+        let l = (max + min) / 2.0;
+        let spread = 1.0 - (2.0 * l - 1.0).abs();
+        // rounding can push the saturation of very dark or light colours just past 1
+        let s = if max > min && spread > 0.0 { ((max - min) / spread).min(1.0) } else { 0.0 };
+        Hsl { h: hue(r, g, b, max, max - min), s, l }
+    }
+
+    pub fn to_rgb(&self) -> [f32; 3] {
+        let chroma = (1.0 - (2.0 * self.l - 1.0).abs()) * self.s;
+        from_hue(self.h, chroma, self.l - chroma / 2.0)
+    }
+}
+
+/// Hue of an RGB colour whose largest component is `max`, `chroma` above its smallest.
+fn hue(r: f32, g: f32, b: f32, max: f32, chroma: f32) -> f32 {
+    if chroma <= 0.0 {
+        return 0.0;
+    }
+    let sector = if max == r {
+        (g - b) / chroma
+    } else if max == g {
+        (b - r) / chroma + 2.0
+    } else {
+        (r - g) / chroma + 4.0
+    };
+    (sector * 60.0).rem_euclid(360.0)
+}
+
+/// RGB of the colour with hue `h` and `chroma`, whose smallest component is `min`.
+fn from_hue(h: f32, chroma: f32, min: f32) -> [f32; 3] {
+    let sector = h.rem_euclid(360.0) / 60.0;
+    let x = chroma * (1.0 - (sector % 2.0 - 1.0).abs());
+    let [r, g, b] = match sector as u32 {
+        0 => [chroma, x, 0.0],
+        1 => [x, chroma, 0.0],
+        2 => [0.0, chroma, x],
+        3 => [0.0, x, chroma],
+        4 => [x, 0.0, chroma],
+        _ => [chroma, 0.0, x],
+    };
+    [r + min, g + min, b + min]
+}
+
+/************************************** Greyscale Methods *********************************************/
+/******************************************************************************************************/
+
+/// How `greyscale_by` and `luma` weigh a colour's channels into one grey value.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub enum GreyscaleMethod {
+    /// Rec.601 (SD video) luma: 0.299 R + 0.587 G + 0.114 B. `greyscale` uses this.
+    #[default]
+    Rec601,
+    /// Rec.709 (HD video and sRGB) luma: 0.2126 R + 0.7152 G + 0.0722 B.
+    Rec709,
+    /// Rec.2100 (UHD and HDR video) luma: 0.2627 R + 0.6780 G + 0.0593 B.
+    Rec2100,
+    /// The mean of red, green and blue.
+    Average,
+    /// Halfway between the largest and smallest channel, as in HSL.
+    Lightness,
+    /// Only the red channel.
+    Red,
+    /// Only the green channel.
+    Green,
+    /// Only the blue channel.
+    Blue,
+    /// Rec.709 luminance of the linear light the sRGB values encode, encoded
+    /// back to sRGB. Closest to how bright colours look.
+    LinearLuminance,
+}
+
+impl GreyscaleMethod {
+    /// The grey value of RGB scaled to 0.0-1.0.
+    pub fn grey(&self, [r, g, b]: [f32; 3]) -> f32 {
+        match self {
+            GreyscaleMethod::Rec601 => weigh([r, g, b], [0.299, 0.587, 0.114]),
+            GreyscaleMethod::Rec709 => weigh([r, g, b], [0.2126, 0.7152, 0.0722]),
+            GreyscaleMethod::Rec2100 => weigh([r, g, b], [0.2627, 0.6780, 0.0593]),
+            GreyscaleMethod::Average => weigh([r, g, b], [1.0 / 3.0; 3]),
+            GreyscaleMethod::Lightness => (r.max(g).max(b) + r.min(g).min(b)) / 2.0,
+            GreyscaleMethod::Red => r,
+            GreyscaleMethod::Green => g,
+            GreyscaleMethod::Blue => b,
+            GreyscaleMethod::LinearLuminance => {
+                let linear = [srgb_to_linear(r), srgb_to_linear(g), srgb_to_linear(b)];
+                linear_to_srgb(weigh(linear, [0.2126, 0.7152, 0.0722]))
+            }
+        }
+    }
+}
+
+/// Weighted sum of RGB whose weights add up to 1. Greys keep their exact value.
+fn weigh([r, g, b]: [f32; 3], [wr, wg, wb]: [f32; 3]) -> f32 {
+    if r == g && g == b {
+        return r;
+    }
+    wr * r + wg * g + wb * b
+}
+
+/// Turns `pixel` grey by `method`, as a pixel of layout `Q`. Alpha is kept.
+pub(crate) fn grey_pixel<P: PixelType, Q: PixelType>(pixel: P, method: GreyscaleMethod) -> Q {
+    let [r, g, b, alpha] = pixel.to_rgba();
+    let grey = method.grey([r, g, b]);
+    Q::from_rgba([grey, grey, grey, alpha])
+}
+
+/// Decodes an sRGB value (0.0-1.0) into linear light.
+pub fn srgb_to_linear(value: f32) -> f32 {
+    if value <= 0.04045 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/// Encodes linear light (0.0-1.0) as an sRGB value.
+pub fn linear_to_srgb(value: f32) -> f32 {
+    if value <= 0.003_130_8 {
+        value * 12.92
+    } else {
+        1.055 * value.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+/*************************************** Colour Operations ********************************************/
+/******************************************************************************************************/
+
+/// Turns every hue `degrees` round the colour wheel.
+pub(crate) fn hue_rotate(degrees: f32) -> impl Fn(Hsl) -> Hsl + Sync {
+    move |hsl| Hsl { h: (hsl.h + degrees).rem_euclid(360.0), ..hsl }
+}
+
+/// Scales saturation by `factor`, capped at full saturation. 0.0 turns the
+/// image grey, keeping each pixel's lightness.
+pub(crate) fn saturate(factor: f32) -> impl Fn(Hsl) -> Hsl + Sync {
+    move |hsl| Hsl { s: (hsl.s * factor).clamp(0.0, 1.0), ..hsl }
+}
+
+/// Gives every pixel `hue` and `saturation`, keeping its lightness, like a
+/// tinted monochrome print.
+pub(crate) fn colorize(hue: f32, saturation: f32) -> impl Fn(Hsl) -> Hsl + Sync {
+    move |hsl| Hsl { h: hue.rem_euclid(360.0), s: saturation.clamp(0.0, 1.0), ..hsl }
+}
+
+/// Passes every pixel of `view` through `f` in HSL. Alpha is kept.
+pub(crate) fn apply_hsl<P: PixelType>(view: &mut StridedViewMut<'_, P>, f: impl Fn(Hsl) -> Hsl + Sync) {
+    view.for_each_row(|_, row| {
+        for chunk in row.chunks_mut(P::CHANNELS) {
+            let [r, g, b, alpha] = P::from_channels(chunk).to_rgba();
+            let [r, g, b] = f(Hsl::from_rgb([r, g, b])).to_rgb();
+            P::from_rgba([r, g, b, alpha]).write_channels(chunk);
+        }
+    });
+}
+
+/************************************** CIE Colour Spaces *********************************************/
+/******************************************************************************************************/
+
+// XYZ, Lab and LCh are relative to the D65 white point, which sRGB uses, with
+// Y running 0.0-1.0 and L 0-100. RGB values are sRGB-encoded, 0.0-1.0.
+
+/// D65 white in XYZ.
+const WHITE: [f32; 3] = [0.950_47, 1.0, 1.088_83];
+
+/// A colour in CIE 1931 XYZ.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Xyz {
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+}
+
+/// A colour in CIE L*a*b*: lightness, green-red and blue-yellow.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Lab {
+    pub l: f32,
+    pub a: f32,
+    pub b: f32,
+}
+
+/// A colour in CIE LCh: Lab's lightness, with chroma and hue (in degrees)
+/// in place of a and b.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Lch {
+    pub l: f32,
+    pub c: f32,
+    pub h: f32,
+}
+
+impl Xyz {
+    pub fn from_rgb(rgb: [f32; 3]) -> Xyz {
+        let [r, g, b] = [srgb_to_linear(rgb[0]), srgb_to_linear(rgb[1]), srgb_to_linear(rgb[2])];
+        Xyz {
+            x: 0.412_456_4 * r + 0.357_576_1 * g + 0.180_437_5 * b,
+            y: 0.212_672_9 * r + 0.715_152_2 * g + 0.072_175 * b,
+            z: 0.019_333_9 * r + 0.119_192 * g + 0.950_304_1 * b,
+        }
+    }
+
+    /// sRGB of this colour. Colours outside the sRGB gamut come out below 0.0
+    /// or above 1.0.
+    pub fn to_rgb(&self) -> [f32; 3] {
+        let Xyz { x, y, z } = *self;
+        let r = 3.240_454_2 * x - 1.537_138_5 * y - 0.498_531_4 * z;
+        let g = -0.969_266 * x + 1.876_010_8 * y + 0.041_556 * z;
+        let b = 0.055_643_4 * x - 0.204_025_9 * y + 1.057_225_2 * z;
+        [linear_to_srgb(r), linear_to_srgb(g), linear_to_srgb(b)]
+    }
+
+    /// The colour of `pixel`, ignoring alpha.
+    pub fn from_pixel<P: PixelType>(pixel: P) -> Xyz {
+        let [r, g, b, _] = pixel.to_rgba();
+        Xyz::from_rgb([r, g, b])
+    }
+
+    /// This colour as an opaque pixel, clamped into range for integer layouts.
+    pub fn to_pixel<P: PixelType>(&self) -> P {
+        let [r, g, b] = self.to_rgb();
+        P::from_rgba([r, g, b, 1.0])
+    }
+}
+
+impl Lab {
+    pub fn from_xyz(xyz: Xyz) -> Lab {
+        let [fx, fy, fz] = [lab_f(xyz.x / WHITE[0]), lab_f(xyz.y / WHITE[1]), lab_f(xyz.z / WHITE[2])];
+        Lab { l: 116.0 * fy - 16.0, a: 500.0 * (fx - fy), b: 200.0 * (fy - fz) }
+    }
+
+    pub fn to_xyz(&self) -> Xyz {
+        let fy = (self.l + 16.0) / 116.0;
+        let (fx, fz) = (fy + self.a / 500.0, fy - self.b / 200.0);
+        Xyz { x: WHITE[0] * lab_f_inv(fx), y: WHITE[1] * lab_f_inv(fy), z: WHITE[2] * lab_f_inv(fz) }
+    }
+
+    pub fn from_rgb(rgb: [f32; 3]) -> Lab {
+        Lab::from_xyz(Xyz::from_rgb(rgb))
+    }
+
+    pub fn to_rgb(&self) -> [f32; 3] {
+        self.to_xyz().to_rgb()
+    }
+
+    /// The colour of `pixel`, ignoring alpha.
+    pub fn from_pixel<P: PixelType>(pixel: P) -> Lab {
+        Lab::from_xyz(Xyz::from_pixel(pixel))
+    }
+
+    /// This colour as an opaque pixel, clamped into range for integer layouts.
+    pub fn to_pixel<P: PixelType>(&self) -> P {
+        self.to_xyz().to_pixel()
+    }
+}
+
+impl Lch {
+    pub fn from_lab(lab: Lab) -> Lch {
+        let h = if lab.a == 0.0 && lab.b == 0.0 { 0.0 } else { lab.b.atan2(lab.a).to_degrees().rem_euclid(360.0) };
+        Lch { l: lab.l, c: lab.a.hypot(lab.b), h }
+    }
+
+    pub fn to_lab(&self) -> Lab {
+        let h = self.h.to_radians();
+        Lab { l: self.l, a: self.c * h.cos(), b: self.c * h.sin() }
+    }
+
+    /// The colour of `pixel`, ignoring alpha.
+    pub fn from_pixel<P: PixelType>(pixel: P) -> Lch {
+        Lch::from_lab(Lab::from_pixel(pixel))
+    }
+
+    /// This colour as an opaque pixel, clamped into range for integer layouts.
+    pub fn to_pixel<P: PixelType>(&self) -> P {
+        self.to_lab().to_pixel()
+    }
+}
+
+const LAB_EPSILON: f32 = 6.0 / 29.0;
+
+/// Lab's companding function: a cube root, straightened near black.
+fn lab_f(t: f32) -> f32 {
+    if t > LAB_EPSILON * LAB_EPSILON * LAB_EPSILON {
+        t.cbrt()
+    } else {
+        t / (3.0 * LAB_EPSILON * LAB_EPSILON) + 4.0 / 29.0
+    }
+}
+
+fn lab_f_inv(t: f32) -> f32 {
+    if t > LAB_EPSILON {
+        t * t * t
+    } else {
+        3.0 * LAB_EPSILON * LAB_EPSILON * (t - 4.0 / 29.0)
+    }
+}
+
+/// Every pixel of `image` in XYZ, row by row.
+pub fn to_xyz<I: Image + ?Sized>(image: &I) -> Vec<Xyz> {
+    image.pixels_iter().map(|(_, _, pixel)| Xyz::from_pixel(pixel)).collect()
+}
+
+/// Every pixel of `image` in Lab, row by row.
+pub fn to_lab<I: Image + ?Sized>(image: &I) -> Vec<Lab> {
+    image.pixels_iter().map(|(_, _, pixel)| Lab::from_pixel(pixel)).collect()
+}
+
+/// Every pixel of `image` in LCh, row by row.
+pub fn to_lch<I: Image + ?Sized>(image: &I) -> Vec<Lch> {
+    image.pixels_iter().map(|(_, _, pixel)| Lch::from_pixel(pixel)).collect()
+}
+
+/****************************************** Delta E ***************************************************/
+/******************************************************************************************************/
+
+/// A formula for how different two colours look. A difference of about 1
+/// is the smallest most people notice.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum DeltaE {
+    /// CIE 1976: straight-line distance in Lab. Quick, but overstates
+    /// differences between saturated colours.
+    Cie76,
+    /// CIEDE2000, which corrects CIE76 for how vision weighs lightness,
+    /// chroma and hue.
+    #[default]
+    Ciede2000,
+}
+
+impl DeltaE {
+    /// The difference between `a` and `b`.
+    pub fn between(&self, a: &Lab, b: &Lab) -> f32 {
+        match self {
+            DeltaE::Cie76 => delta_e76(a, b),
+            DeltaE::Ciede2000 => delta_e2000(a, b),
+        }
+    }
+}
+
+/// CIE 1976 colour difference between `a` and `b`.
+pub fn delta_e76(a: &Lab, b: &Lab) -> f32 {
+    ((a.l - b.l).powi(2) + (a.a - b.a).powi(2) + (a.b - b.b).powi(2)).sqrt()
+}
+
+/// CIEDE2000 colour difference between `a` and `b`, with unit weights.
+pub fn delta_e2000(a: &Lab, b: &Lab) -> f32 {
+    // worked in f64, following Sharma, Wu and Dalal's notes on the formula
+    let (l1, a1, b1) = (a.l as f64, a.a as f64, a.b as f64);
+    let (l2, a2, b2) = (b.l as f64, b.a as f64, b.b as f64);
+    let pow7 = |v: f64| v.powi(7);
+    let twenty_five7 = pow7(25.0);
+
+    let c_mean = (a1.hypot(b1) + a2.hypot(b2)) / 2.0;
+    let g = 0.5 * (1.0 - (pow7(c_mean) / (pow7(c_mean) + twenty_five7)).sqrt());
+    let (a1, a2) = (a1 * (1.0 + g), a2 * (1.0 + g));
+    let (c1, c2) = (a1.hypot(b1), a2.hypot(b2));
+    let hue = |b: f64, a: f64| if a == 0.0 && b == 0.0 { 0.0 } else { b.atan2(a).to_degrees().rem_euclid(360.0) };
+    let (h1, h2) = (hue(b1, a1), hue(b2, a2));
+
+    let delta_l = l2 - l1;
+    let delta_c = c2 - c1;
+    let delta_h = if c1 * c2 == 0.0 {
+        0.0
+    } else if (h2 - h1).abs() <= 180.0 {
+        h2 - h1
+    } else if h2 > h1 {
+        h2 - h1 - 360.0
+    } else {
+        h2 - h1 + 360.0
+    };
+    let delta_h = 2.0 * (c1 * c2).sqrt() * (delta_h / 2.0).to_radians().sin();
+
+    let l_mean = (l1 + l2) / 2.0;
+    let c_mean = (c1 + c2) / 2.0;
+    let h_mean = if c1 * c2 == 0.0 {
+        h1 + h2
+    } else if (h1 - h2).abs() <= 180.0 {
+        (h1 + h2) / 2.0
+    } else if h1 + h2 < 360.0 {
+        (h1 + h2 + 360.0) / 2.0
+    } else {
+        (h1 + h2 - 360.0) / 2.0
+    };
+
+    let cos = |degrees: f64| degrees.to_radians().cos();
+    let t = 1.0 - 0.17 * cos(h_mean - 30.0) + 0.24 * cos(2.0 * h_mean)
+        + 0.32 * cos(3.0 * h_mean + 6.0) - 0.20 * cos(4.0 * h_mean - 63.0);
+    let delta_theta = 30.0 * (-((h_mean - 275.0) / 25.0).powi(2)).exp();
+    let r_c = 2.0 * (pow7(c_mean) / (pow7(c_mean) + twenty_five7)).sqrt();
+    let s_l = 1.0 + 0.015 * (l_mean - 50.0).powi(2) / (20.0 + (l_mean - 50.0).powi(2)).sqrt();
+    let s_c = 1.0 + 0.045 * c_mean;
+    let s_h = 1.0 + 0.015 * c_mean * t;
+    let r_t = -(2.0 * delta_theta).to_radians().sin() * r_c;
+
+    let (l, c, h) = (delta_l / s_l, delta_c / s_c, delta_h / s_h);
+    (l * l + c * c + h * h + r_t * c * h).sqrt() as f32
+}
+
+/// How different each pixel of `a` looks from the one at the same place in
+/// `b`, by `formula`, as an image of raw ΔE values (not scaled to 0.0-1.0).
+/// Alpha is ignored. Fails if the sizes differ.
+pub fn delta_e_map<A: Image, B: Image>(a: &A, b: &B, formula: DeltaE) -> Result<OwnedImage<Luma32F>, ImageError> {
+    a.zip_map(b, |p, q| Luma { luma: formula.between(&Lab::from_pixel(p), &Lab::from_pixel(q)) })
+}
+
+/// The average of `delta_e_map`, or 0 for empty images.
+pub fn mean_delta_e<A: Image, B: Image>(a: &A, b: &B, formula: DeltaE) -> Result<f32, ImageError> {
+    let map = delta_e_map(a, b, formula)?;
+    let count = map.pixels_iter().len();
+    let total: f64 = map.pixels_iter().map(|(_, _, delta)| delta.luma as f64).sum();
+    Ok(if count == 0 { 0.0 } else { (total / count as f64) as f32 })
+}
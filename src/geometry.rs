@@ -0,0 +1,188 @@
+use super::OwnedImage::*;
+use super::utils::*;
+use super::pixel::*;
+use super::view::StridedView;
+use super::parallel;
+
+/************************************ Rotation Options and Helpers ************************************/
+/******************************************************************************************************/
+
+/// How to sample the source image between pixel centres.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Interpolation {
+    /// Takes the closest pixel.
+    Nearest,
+    /// Blends the 2x2 closest pixels.
+    Bilinear,
+    /// Blends the 4x4 closest pixels with a Catmull-Rom cubic.
+    Bicubic,
+}
+
+/// The size of an arbitrarily rotated image.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Canvas {
+    /// Grows the canvas so the whole rotated image fits.
+    Expand,
+    /// Keeps the original size, cutting off the rotated corners.
+    Keep,
+}
+
+/// Mirrors horizontally, vertically, or both.
+pub(crate) fn flip<P: PixelType>(view: &StridedView<'_, P>, horiz: bool, vert: bool) -> OwnedImage<P> {
+    let dims = *view.dimensions();
+    let row_len = dims.width as usize * P::CHANNELS;
+
+    let mut pixels = vec![P::Channel::default(); row_len * dims.height as usize];
+    parallel::for_each_band(&mut pixels, row_len, |first_row, band| {
+        for (j, row) in (first_row..).zip(band.chunks_mut(row_len)) {
+            let src = view.row(if vert { dims.height - j as u32 - 1 } else { j as u32 });
+            if horiz {
+                for (dst, src) in row.chunks_mut(P::CHANNELS).zip(src.chunks(P::CHANNELS).rev()) {
+                    dst.copy_from_slice(src);
+                }
+            } else {
+                row.copy_from_slice(src);
+            }
+        }
+    });
+
+    OwnedImage::from_raw(dims, pixels.into_boxed_slice())
+}
+
+/// Rotates by a whole number of clockwise quarter turns, without resampling.
+pub(crate) fn rotate_quarters<P: PixelType>(view: &StridedView<'_, P>, turns: u32) -> OwnedImage<P> {
+    let dims = *view.dimensions();
+    let (w, h) = (dims.width as usize, dims.height as usize);
+    let c = P::CHANNELS;
+    let turns = turns % 4;
+
+    let new_dims = if turns % 2 == 1 {
+        Dimensions { width: dims.height, height: dims.width }
+    } else {
+        dims
+    };
+    let new_w = new_dims.width as usize;
+
+    let mut out = vec![P::Channel::default(); w * h * c];
+    for y in 0..new_dims.height as usize {
+        for x in 0..new_w {
+            let (src_x, src_y) = match turns {
+                0 => (x, y),
+                1 => (y, h - 1 - x),
+                2 => (w - 1 - x, h - 1 - y),
+                _ => (w - 1 - y, x),
+            };
+            let dst = (y * new_w + x) * c;
+            out[dst..dst + c].copy_from_slice(view.at(src_x, src_y));
+        }
+    }
+
+    OwnedImage::from_raw(new_dims, out.into_boxed_slice())
+}
+
+/// Rotates clockwise by `degrees` about the image centre, filling uncovered
+/// areas with `background`.
+pub(crate) fn rotate<P: PixelType>(view: &StridedView<'_, P>, degrees: f32, interpolation: Interpolation,
+                                   canvas: Canvas, background: P) -> OwnedImage<P> {
+    let dims = *view.dimensions();
+    let (sin, cos) = degrees.to_radians().sin_cos();
+    let (w, h) = (dims.width as f32, dims.height as f32);
+
+    let new_dims = match canvas {
+        Canvas::Keep => dims,
+        Canvas::Expand => {
+            // shave a little off so exact right angles don't gain a pixel from rounding
+            let new_w = (w * cos.abs() + h * sin.abs() - 1e-3).ceil().max(0.0);
+            let new_h = (w * sin.abs() + h * cos.abs() - 1e-3).ceil().max(0.0);
+            Dimensions { width: new_w as u32, height: new_h as u32 }
+        }
+    };
+
+    let c = P::CHANNELS;
+    let (cx, cy) = ((w - 1.0) / 2.0, (h - 1.0) / 2.0);
+    let (new_cx, new_cy) = ((new_dims.width as f32 - 1.0) / 2.0, (new_dims.height as f32 - 1.0) / 2.0);
+
+    let mut out = vec![P::Channel::default(); new_dims.width as usize * new_dims.height as usize * c];
+    for (i, dst) in out.chunks_mut(c).enumerate() {
+        let dx = (i % new_dims.width as usize) as f32 - new_cx;
+        let dy = (i / new_dims.width as usize) as f32 - new_cy;
+
+        // inverse rotation maps each output pixel back into the source
+        let src_x = cx + dx * cos + dy * sin;
+        let src_y = cy - dx * sin + dy * cos;
+        sample(view, src_x, src_y, interpolation, background, dst);
+    }
+
+    OwnedImage::from_raw(new_dims, out.into_boxed_slice())
+}
+
+/// Samples the image at a fractional position (pixel centres sit on whole
+/// numbers) into `out`. Pixels outside the image read as `background`.
+pub(crate) fn sample<P: PixelType>(view: &StridedView<'_, P>, x: f32, y: f32, interpolation: Interpolation,
+                                   background: P, out: &mut [P::Channel]) {
+    let dims = *view.dimensions();
+    let c = P::CHANNELS;
+    let mut bg = [P::Channel::default(); 4];
+    background.write_channels(&mut bg);
+
+    let at = |ix: i64, iy: i64| -> &[P::Channel] {
+        if ix < 0 || iy < 0 || ix >= dims.width as i64 || iy >= dims.height as i64 {
+            &bg[..c]
+        } else {
+            view.at(ix as usize, iy as usize)
+        }
+    };
+
+    match interpolation {
+        Interpolation::Nearest => {
+            out[..c].copy_from_slice(at(x.round() as i64, y.round() as i64));
+        }
+        Interpolation::Bilinear => {
+            let (x0, y0) = (x.floor(), y.floor());
+            let (fx, fy) = (x - x0, y - y0);
+            let (x0, y0) = (x0 as i64, y0 as i64);
+            let weights = [
+                (x0, y0, (1.0 - fx) * (1.0 - fy)),
+                (x0 + 1, y0, fx * (1.0 - fy)),
+                (x0, y0 + 1, (1.0 - fx) * fy),
+                (x0 + 1, y0 + 1, fx * fy),
+            ];
+            blend::<P>(weights.iter().map(|&(ix, iy, wt)| (at(ix, iy), wt)), out);
+        }
+        Interpolation::Bicubic => {
+            let (x0, y0) = (x.floor(), y.floor());
+            let (wx, wy) = (catmull_rom_weights(x - x0), catmull_rom_weights(y - y0));
+            let (x0, y0) = (x0 as i64, y0 as i64);
+            let taps = (0..16).map(|k| {
+                let (i, j) = (k % 4, k / 4);
+                (at(x0 - 1 + i as i64, y0 - 1 + j as i64), wx[i] * wy[j])
+            });
+            blend::<P>(taps, out);
+        }
+    }
+}
+
+/// Weighted sum of pixels. Integer channels saturate if the weights overshoot.
+fn blend<'a, P: PixelType>(taps: impl Iterator<Item = (&'a [P::Channel], f32)>, out: &mut [P::Channel]) {
+    let mut totals = [0.0f32; 4];
+    for (channels, weight) in taps {
+        for (total, value) in totals.iter_mut().zip(channels) {
+            *total += value.to_f32() * weight;
+        }
+    }
+    for (dst, total) in out.iter_mut().zip(totals.iter()).take(P::CHANNELS) {
+        *dst = P::Channel::from_f32(*total);
+    }
+}
+
+/// Catmull-Rom weights for the four taps around a sample at fraction `t` past the second tap.
+fn catmull_rom_weights(t: f32) -> [f32; 4] {
+    let t2 = t * t;
+    let t3 = t2 * t;
+    [
+        0.5 * (-t3 + 2.0 * t2 - t),
+        0.5 * (3.0 * t3 - 5.0 * t2 + 2.0),
+        0.5 * (-3.0 * t3 + 4.0 * t2 + t),
+        0.5 * (t3 - t2),
+    ]
+}
@@ -0,0 +1,324 @@
+use super::utils::Dimensions;
+use super::pixel::*;
+use super::colour::GreyscaleMethod;
+use super::parallel;
+use super::view::{StridedView, StridedViewMut};
+
+/*************************************** Histograms ***************************************************/
+/******************************************************************************************************/
+
+/// Number of bins in each histogram, one per 8-bit value.
+pub const BINS: usize = 256;
+
+/// Pixel counts per value, from 0 up to the channel's maximum in 256 even steps.
+pub type Bins = [u64; BINS];
+
+/// How many pixels of an image have each value, per channel. Layouts without
+/// colour count their grey as red, green and blue alike, and those without
+/// alpha count it as opaque.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Histogram {
+    pub red: Bins,
+    pub green: Bins,
+    pub blue: Bins,
+    pub alpha: Bins,
+    /// Rec.709 luma.
+    pub luma: Bins,
+}
+
+impl Histogram {
+    /// Number of pixels counted.
+    pub fn total(&self) -> u64 {
+        self.luma.iter().sum()
+    }
+
+    /// The red, green and blue bins.
+    pub fn colours(&self) -> [&Bins; 3] {
+        [&self.red, &self.green, &self.blue]
+    }
+}
+
+/// Bin of a value scaled to 0.0-1.0. Out-of-range values land in the end bins.
+pub fn bin(value: f32) -> usize {
+    (value.clamp(0.0, 1.0) * (BINS - 1) as f32).round() as usize
+}
+
+/// The first bin by which at least `fraction` (0.0-1.0) of the counted pixels
+/// have been reached, skipping empty bins at the start. 0 for empty bins.
+pub fn percentile(bins: &Bins, fraction: f32) -> usize {
+    let total: u64 = bins.iter().sum();
+    let mut seen = 0;
+    for (i, &count) in bins.iter().enumerate() {
+        seen += count;
+        if seen > 0 && seen as f32 / total as f32 >= fraction {
+            return i;
+        }
+    }
+    0
+}
+
+/// Counts the pixels of `view`, band by band.
+pub(crate) fn histogram<P: PixelType>(view: &StridedView<'_, P>) -> Histogram {
+    let empty = Histogram { red: [0; BINS], green: [0; BINS], blue: [0; BINS], alpha: [0; BINS], luma: [0; BINS] };
+    let bands = parallel::map_bands(view.dimensions().height as usize, |rows| {
+        let mut counts = empty.clone();
+        for y in rows {
+            for pixel in view.row(y as u32).chunks(P::CHANNELS) {
+                let [r, g, b, alpha] = P::from_channels(pixel).to_rgba();
+                counts.red[bin(r)] += 1;
+                counts.green[bin(g)] += 1;
+                counts.blue[bin(b)] += 1;
+                counts.alpha[bin(alpha)] += 1;
+                counts.luma[bin(GreyscaleMethod::Rec709.grey([r, g, b]))] += 1;
+            }
+        }
+        counts
+    });
+
+    bands.iter().fold(empty.clone(), |mut total, band| {
+        let pairs = [(&mut total.red, &band.red), (&mut total.green, &band.green), (&mut total.blue, &band.blue),
+                     (&mut total.alpha, &band.alpha), (&mut total.luma, &band.luma)];
+        for (sum, counts) in pairs {
+            sum.iter_mut().zip(counts.iter()).for_each(|(s, c)| *s += c);
+        }
+        total
+    })
+}
+
+/*********************************** Histogram Adjustments ********************************************/
+/******************************************************************************************************/
+
+// Each adjustment is a curve per colour channel, tabulated at the 256 bin
+// values: 8-bit channels look their result up directly, and finer ones are
+// interpolated between the two nearest entries.
+
+/// Output (0.0-1.0) for each bin.
+type Curve = [f32; BINS];
+
+fn identity() -> Curve {
+    let mut curve = [0.0; BINS];
+    curve.iter_mut().enumerate().for_each(|(i, v)| *v = i as f32 / (BINS - 1) as f32);
+    curve
+}
+
+/// Running totals of `bins`.
+fn cumulative(bins: &Bins) -> Bins {
+    let mut total = 0;
+    let mut sums = [0; BINS];
+    for (sum, &count) in sums.iter_mut().zip(bins.iter()) {
+        total += count;
+        *sum = total;
+    }
+    sums
+}
+
+/// Spreads the luma values evenly over the whole range. The same curve goes
+/// through every colour channel, so greys stay grey.
+pub(crate) fn equalize_curve(histogram: &Histogram) -> [Curve; 3] {
+    let mut counts = [0.0; BINS];
+    counts.iter_mut().zip(histogram.luma.iter()).for_each(|(c, &count)| *c = count as f64);
+    [equalizing(&counts); 3]
+}
+
+/// The curve spreading `counts` evenly over the whole range, with the first
+/// occupied bin mapping to black.
+fn equalizing(counts: &[f64; BINS]) -> Curve {
+    let mut sums = [0.0; BINS];
+    let mut total = 0.0;
+    for (sum, &count) in sums.iter_mut().zip(counts.iter()) {
+        total += count;
+        *sum = total;
+    }
+    let first = sums.iter().copied().find(|&sum| sum > 0.0).unwrap_or(0.0);
+    if total <= first {
+        return identity();
+    }
+    let mut curve = [0.0; BINS];
+    for (out, &sum) in curve.iter_mut().zip(sums.iter()) {
+        *out = ((sum - first).max(0.0) / (total - first)) as f32;
+    }
+    curve
+}
+
+/// Maps each colour channel so its histogram takes the shape of the same
+/// channel's in `target`.
+pub(crate) fn match_curve(histogram: &Histogram, target: &Histogram) -> [Curve; 3] {
+    let mut curves = [identity(); 3];
+    for (curve, (source, target)) in curves.iter_mut().zip(histogram.colours().iter().zip(target.colours().iter())) {
+        let (source, target) = (cumulative(source), cumulative(target));
+        let (source_total, target_total) = (source[BINS - 1], target[BINS - 1]);
+        if source_total == 0 || target_total == 0 {
+            continue;
+        }
+        // the first target value reaching each source value's share of pixels
+        let mut j = 0;
+        for (out, &sum) in curve.iter_mut().zip(source.iter()) {
+            let share = sum as f64 / source_total as f64;
+            while j < BINS - 1 && (target[j] as f64 / target_total as f64) < share {
+                j += 1;
+            }
+            *out = j as f32 / (BINS - 1) as f32;
+        }
+    }
+    curves
+}
+
+/// Stretches each colour channel so the `clip` fraction of its darkest pixels
+/// turn black and the `clip` fraction of its brightest turn white.
+pub(crate) fn levels_curve(histogram: &Histogram, clip: f32) -> [Curve; 3] {
+    let mut curves = [identity(); 3];
+    for (curve, bins) in curves.iter_mut().zip(histogram.colours().iter()) {
+        let (low, high) = (percentile(bins, clip), percentile(bins, 1.0 - clip));
+        if high <= low {
+            continue;
+        }
+        for (i, out) in curve.iter_mut().enumerate() {
+            *out = ((i as f32 - low as f32) / (high - low) as f32).clamp(0.0, 1.0);
+        }
+    }
+    curves
+}
+
+/// Passes red, green and blue of every pixel through their curves. Alpha is kept.
+pub(crate) fn apply_curves<P: PixelType>(view: &mut StridedViewMut<'_, P>, curves: &[Curve; 3]) {
+    view.for_each_row(|_, row| {
+        for chunk in row.chunks_mut(P::CHANNELS) {
+            let [r, g, b, alpha] = P::from_channels(chunk).to_rgba();
+            let [r, g, b] = [lookup(&curves[0], r), lookup(&curves[1], g), lookup(&curves[2], b)];
+            P::from_rgba([r, g, b, alpha]).write_channels(chunk);
+        }
+    });
+}
+
+/// `curve` at `value` (0.0-1.0), interpolated between bins.
+fn lookup(curve: &Curve, value: f32) -> f32 {
+    let position = value.clamp(0.0, 1.0) * (BINS - 1) as f32;
+    let i = position.floor() as usize;
+    match curve.get(i + 1) {
+        Some(&next) => curve[i] + (next - curve[i]) * (position - i as f32),
+        None => curve[BINS - 1],
+    }
+}
+
+/********************************* Adaptive Equalization **********************************************/
+/******************************************************************************************************/
+
+/// Which values `Clahe` equalizes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum ClaheChannels {
+    /// Rec.709 luma. Red, green and blue shift alike, so colours keep their chroma.
+    #[default]
+    Luma,
+    /// Red, green and blue, each by its own histograms.
+    PerChannel,
+}
+
+/// Settings for contrast-limited adaptive histogram equalization (CLAHE): each
+/// tile of a grid is equalized by its own histogram, and pixels blend the
+/// curves of the four nearest tiles so no seams show. Clipping each histogram
+/// first keeps flat areas from turning into noise and highlights from blowing out.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Clahe {
+    /// Number of tiles across and down, capped at the image's width and height.
+    pub tiles: (u32, u32),
+    /// Most pixels a bin may hold, as a multiple of the tile's average per bin,
+    /// before the excess is spread over every bin. 0.0 leaves the image as it
+    /// is, higher limits allow more contrast, and infinity equalizes each tile fully.
+    pub clip_limit: f32,
+    pub channels: ClaheChannels,
+}
+
+impl Default for Clahe {
+    fn default() -> Self {
+        Clahe { tiles: (8, 8), clip_limit: 2.0, channels: ClaheChannels::Luma }
+    }
+}
+
+/// Applies CLAHE to `view` in place. Alpha is kept.
+pub(crate) fn clahe<P: PixelType>(view: &mut StridedViewMut<'_, P>, settings: Clahe) {
+    let Dimensions { width, height } = *view.dimensions();
+    if width == 0 || height == 0 {
+        return;
+    }
+    let cols = tile_edges(width, settings.tiles.0);
+    let rows = tile_edges(height, settings.tiles.1);
+    let tiles: Vec<(usize, usize)> = (0..rows.len() - 1)
+        .flat_map(|ty| (0..cols.len() - 1).map(move |tx| (tx, ty)))
+        .collect();
+
+    // one curve per equalized channel, for each tile in row-major order
+    let curves: Vec<Vec<Curve>> = {
+        let source = view.as_view();
+        parallel::map_each(&tiles, |&(tx, ty)| {
+            let dims = Dimensions { width: cols[tx + 1] - cols[tx], height: rows[ty + 1] - rows[ty] };
+            let histogram = histogram(&source.sub_view(cols[tx], rows[ty], dims));
+            let curve = |bins: &Bins| equalizing(&clipped(bins, settings.clip_limit));
+            match settings.channels {
+                ClaheChannels::Luma => vec![curve(&histogram.luma)],
+                ClaheChannels::PerChannel => histogram.colours().iter().map(|bins| curve(bins)).collect(),
+            }
+        })
+    };
+
+    let across = blend_weights(width, &cols);
+    let down = blend_weights(height, &rows);
+    let stride = cols.len() - 1;
+    view.for_each_row(|y, row| {
+        let (top, bottom, fy) = down[y as usize];
+        for (x, chunk) in row.chunks_mut(P::CHANNELS).enumerate() {
+            let (left, right, fx) = across[x];
+            let map = |channel: usize, value: f32| {
+                let at = |ty: usize, tx: usize| lookup(&curves[ty * stride + tx][channel], value);
+                let upper = at(top, left) + (at(top, right) - at(top, left)) * fx;
+                let lower = at(bottom, left) + (at(bottom, right) - at(bottom, left)) * fx;
+                upper + (lower - upper) * fy
+            };
+
+            let [r, g, b, alpha] = P::from_channels(chunk).to_rgba();
+            let [r, g, b] = match settings.channels {
+                ClaheChannels::Luma => {
+                    let luma = GreyscaleMethod::Rec709.grey([r, g, b]);
+                    let shift = map(0, luma) - luma;
+                    [r + shift, g + shift, b + shift].map(|v| v.clamp(0.0, 1.0))
+                },
+                ClaheChannels::PerChannel => [map(0, r), map(1, g), map(2, b)],
+            };
+            P::from_rgba([r, g, b, alpha]).write_channels(chunk);
+        }
+    });
+}
+
+/// `bins` with every count capped at `limit` times their average, and the
+/// excess spread evenly over all of them.
+fn clipped(bins: &Bins, limit: f32) -> [f64; BINS] {
+    let total: u64 = bins.iter().sum();
+    let cap = limit.max(0.0) as f64 * total as f64 / BINS as f64;
+    let excess: f64 = bins.iter().map(|&count| (count as f64 - cap).max(0.0)).sum();
+    let mut counts = [0.0; BINS];
+    for (out, &count) in counts.iter_mut().zip(bins.iter()) {
+        *out = (count as f64).min(cap) + excess / BINS as f64;
+    }
+    counts
+}
+
+/// Where `count` tiles start along `length` pixels, followed by the end.
+fn tile_edges(length: u32, count: u32) -> Vec<u32> {
+    let count = count.clamp(1, length) as u64;
+    (0..=count).map(|i| (i * length as u64 / count) as u32).collect()
+}
+
+/// For every pixel along `length`, the tiles whose centres lie either side of
+/// it and how far it is from the first towards the second. Pixels beyond the
+/// outermost centres take that tile alone.
+fn blend_weights(length: u32, edges: &[u32]) -> Vec<(usize, usize, f32)> {
+    let centres: Vec<f32> = edges.windows(2).map(|pair| (pair[0] + pair[1]) as f32 / 2.0).collect();
+    let last = centres.len() - 1;
+    (0..length).map(|i| {
+        let position = i as f32 + 0.5;
+        match centres.iter().rposition(|&centre| centre <= position) {
+            None => (0, 0, 0.0),
+            Some(tile) if tile == last => (last, last, 0.0),
+            Some(tile) => (tile, tile + 1, (position - centres[tile]) / (centres[tile + 1] - centres[tile])),
+        }
+    }).collect()
+}
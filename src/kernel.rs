@@ -0,0 +1,366 @@
+use super::OwnedImage::*;
+use super::utils::*;
+use super::pixel::*;
+use super::parallel;
+use super::view::StridedView;
+
+/*************************************** Kernel Struct and Presets ************************************/
+/******************************************************************************************************/
+
+/// How a neighbourhood operation reads pixels that fall outside the image.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum EdgeMode<P: PixelType = Rgba8> {
+    /// Repeats the nearest edge pixel.
+    Clamp,
+    /// Reflects the image about its edges, repeating the edge pixel (`ba|abcd|dc`).
+    Mirror,
+    /// Tiles the image, reading from the opposite edge.
+    Wrap,
+    /// Reads every outside pixel as this colour.
+    Constant(P),
+    /// Leaves outside pixels out, rescaling the remaining weights so the
+    /// total weight is unchanged. Kernels whose weights sum to zero (edge
+    /// detectors) aren't rescaled, so they give the inside pixels' response.
+    Skip,
+    /// Only produces pixels whose whole neighbourhood lies inside the image,
+    /// so the result shrinks by the kernel's radius on every side. Slices
+    /// can't shrink, so in-place operations on them fail with this mode.
+    Crop,
+}
+
+/// What a convolution does with the alpha channel.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum AlphaMode {
+    /// Keeps each pixel's original alpha and convolves the colour only.
+    Ignore,
+    /// Convolves alpha like any colour channel.
+    Convolve,
+    /// Weights colour by alpha while convolving, so transparent pixels don't
+    /// bleed their (invisible) colour into their neighbours.
+    Premultiply,
+}
+
+/// A convolution kernel of odd width and height, anchored at its centre.
+/// Kernels are applied as a correlation, i.e. without flipping them.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Kernel {
+    width: usize,
+    height: usize,
+    weights: Vec<f32>,
+    // horizontal and vertical factors, when the kernel is their outer product
+    factors: Option<(Vec<f32>, Vec<f32>)>,
+    bias: f32,
+    alpha: AlphaMode,
+}
+
+impl Kernel {
+    /// Builds a kernel from row-major `weights`.
+    /// Fails unless `width` and `height` are odd and `weights` has `width * height` entries.
+    pub fn new(width: usize, height: usize, weights: Vec<f32>) -> Result<Kernel, ImageError> {
+        if width.is_multiple_of(2) || height.is_multiple_of(2) || weights.len() != width * height {
+            return InvalidKernel { width, height, len: weights.len() }.fail();
+        }
+        Ok(Kernel { width, height, weights, factors: None, bias: 0.0, alpha: AlphaMode::Ignore })
+    }
+
+    /// Builds the kernel that is the outer product of a `horizontal` row and a
+    /// `vertical` column. Convolving with it takes two cheap 1D passes.
+    pub fn separable(horizontal: Vec<f32>, vertical: Vec<f32>) -> Result<Kernel, ImageError> {
+        let (width, height) = (horizontal.len(), vertical.len());
+        let weights = vertical.iter()
+            .flat_map(|v| horizontal.iter().map(move |h| h * v))
+            .collect();
+        let mut kernel = Kernel::new(width, height, weights)?;
+        kernel.factors = Some((horizontal, vertical));
+        Ok(kernel)
+    }
+
+    /// Scales the weights so they sum to one, keeping overall brightness.
+    /// Kernels summing to zero (edge detectors) are left as they are.
+    pub fn normalized(mut self) -> Kernel {
+        let sum: f32 = self.weights.iter().sum();
+        if sum.abs() > f32::EPSILON {
+            self.weights.iter_mut().for_each(|w| *w /= sum);
+            if let Some((horizontal, vertical)) = &mut self.factors {
+                let (h_sum, v_sum): (f32, f32) = (horizontal.iter().sum(), vertical.iter().sum());
+                horizontal.iter_mut().for_each(|w| *w /= h_sum);
+                vertical.iter_mut().for_each(|w| *w /= v_sum);
+            }
+        }
+        self
+    }
+
+    /// Adds `bias` (on a 0.0-1.0 scale) to every colour channel of the result.
+    pub fn with_bias(mut self, bias: f32) -> Kernel {
+        self.bias = bias;
+        self
+    }
+
+    /// Sets how the alpha channel is treated. The default is `AlphaMode::Ignore`.
+    pub fn with_alpha(mut self, alpha: AlphaMode) -> Kernel {
+        self.alpha = alpha;
+        self
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// The row-major weights.
+    pub fn weights(&self) -> &[f32] {
+        &self.weights
+    }
+
+    /// Whether the kernel is applied as two 1D passes.
+    pub fn is_separable(&self) -> bool {
+        self.factors.is_some()
+    }
+
+    /// Averages a square of side `2 * radius + 1`.
+    pub fn box_filter(radius: usize) -> Kernel {
+        let row = vec![1.0; 2 * radius + 1];
+        Kernel::separable(row.clone(), row)
+            .expect("box kernels have odd sides")
+            .normalized()
+    }
+
+    /// Sharpens by subtracting the 4-connected neighbours.
+    pub fn sharpen() -> Kernel {
+        Kernel::new(3, 3, vec![
+             0.0, -1.0,  0.0,
+            -1.0,  5.0, -1.0,
+             0.0, -1.0,  0.0,
+        ]).expect("preset kernels are valid")
+    }
+
+    /// Lights the image from the top left, as if pressed into relief.
+    pub fn emboss() -> Kernel {
+        Kernel::new(3, 3, vec![
+            -2.0, -1.0, 0.0,
+            -1.0,  1.0, 1.0,
+             0.0,  1.0, 2.0,
+        ]).expect("preset kernels are valid")
+    }
+
+    /// Laplacian edge detector: flat areas go black, edges light up.
+    pub fn edge_detect() -> Kernel {
+        Kernel::new(3, 3, vec![
+            -1.0, -1.0, -1.0,
+            -1.0,  8.0, -1.0,
+            -1.0, -1.0, -1.0,
+        ]).expect("preset kernels are valid")
+    }
+
+    /// Sobel operator for horizontal gradients (vertical edges), biased to mid grey.
+    pub fn sobel_x() -> Kernel {
+        Kernel::separable(vec![-1.0, 0.0, 1.0], vec![1.0, 2.0, 1.0])
+            .expect("preset kernels are valid")
+            .with_bias(0.5)
+    }
+
+    /// Sobel operator for vertical gradients (horizontal edges), biased to mid grey.
+    pub fn sobel_y() -> Kernel {
+        Kernel::separable(vec![1.0, 2.0, 1.0], vec![-1.0, 0.0, 1.0])
+            .expect("preset kernels are valid")
+            .with_bias(0.5)
+    }
+}
+
+/************************************** Convolution Implementation ************************************/
+/******************************************************************************************************/
+
+/// Maps a possibly out-of-range coordinate onto the image along one axis.
+/// Returns `None` when the edge mode doesn't read from the image there.
+pub(crate) fn edge_index<P: PixelType>(i: i64, len: usize, edge: &EdgeMode<P>) -> Option<usize> {
+    let n = len as i64;
+    if (0..n).contains(&i) {
+        return Some(i as usize);
+    }
+    let mapped = match edge {
+        EdgeMode::Clamp => i.max(0).min(n - 1),
+        EdgeMode::Wrap => i.rem_euclid(n),
+        EdgeMode::Mirror => {
+            let period = 2 * n;
+            let m = i.rem_euclid(period);
+            if m < n { m } else { period - 1 - m }
+        }
+        EdgeMode::Constant(_) | EdgeMode::Skip | EdgeMode::Crop => return None,
+    };
+    Some(mapped as usize)
+}
+
+/// Convolves interleaved channels with `kernel`.
+pub(crate) fn convolve<P: PixelType>(view: &StridedView<'_, P>, kernel: &Kernel, edge: &EdgeMode<P>)
+                                     -> OwnedImage<P> {
+    let (w, h) = (view.dimensions().width as usize, view.dimensions().height as usize);
+    let (rx, ry) = (kernel.width / 2, kernel.height / 2);
+    let crop = *edge == EdgeMode::Crop;
+    let (out_w, out_h) = if crop {
+        (w.saturating_sub(2 * rx), h.saturating_sub(2 * ry))
+    } else {
+        (w, h)
+    };
+    let out_dims = Dimensions { width: out_w as u32, height: out_h as u32 };
+    if out_w == 0 || out_h == 0 {
+        return OwnedImage::from_raw(out_dims, Vec::new().into_boxed_slice());
+    }
+
+    let c = P::CHANNELS;
+    let premultiply = kernel.alpha == AlphaMode::Premultiply && P::ALPHA.is_some();
+    let to_input = |channels: &[P::Channel]| -> Vec<f32> {
+        let mut values: Vec<f32> = channels.iter().map(|v| v.to_unit()).collect();
+        if let (Some(a), true) = (P::ALPHA, premultiply) {
+            for pixel in values.chunks_mut(c) {
+                let alpha = pixel[a];
+                for (ch, value) in pixel.iter_mut().enumerate() {
+                    if ch != a {
+                        *value *= alpha;
+                    }
+                }
+            }
+        }
+        values
+    };
+    let input: Vec<f32> = view.rows().flat_map(&to_input).collect();
+    let constant = match edge {
+        EdgeMode::Constant(colour) => {
+            let mut channels = [P::Channel::default(); 4];
+            colour.write_channels(&mut channels);
+            to_input(&channels[..c])
+        }
+        _ => vec![0.0; c],
+    };
+    // with nothing to rescale to, skipping outside pixels is reading them as zeros
+    let zero_sum = kernel.weights.iter().sum::<f32>().abs() <= f32::EPSILON;
+    let edge = &match edge {
+        EdgeMode::Skip if zero_sum => EdgeMode::Constant(P::default()),
+        edge => *edge,
+    };
+
+    let output = match &kernel.factors {
+        Some((horizontal, vertical)) => {
+            let (pass_w, pass) = convolve_axis(&input, (w, h), c, horizontal, true, edge, &constant);
+            // rows outside the image hold what the first pass makes of a constant row
+            let scale: f32 = horizontal.iter().sum();
+            let constant: Vec<f32> = constant.iter().map(|value| value * scale).collect();
+            convolve_axis(&pass, (pass_w, h), c, vertical, false, edge, &constant).1
+        }
+        None => convolve_2d(&input, (w, h), c, kernel, edge, &constant),
+    };
+
+    // output pixel (x, y) is centred on source pixel (x + ox, y + oy)
+    let (ox, oy) = if crop { (rx, ry) } else { (0, 0) };
+    let mut out = vec![P::Channel::default(); out_w * out_h * c];
+    parallel::for_each_band(&mut out, out_w * c, |first_row, band| {
+        let start = first_row * out_w;
+        let results = output[start * c..].chunks(c);
+        for (i, (dst, result)) in (start..).zip(band.chunks_mut(c).zip(results)) {
+            let src = view.at(i % out_w + ox, i / out_w + oy);
+            let alpha = match (P::ALPHA, kernel.alpha) {
+                (Some(a), AlphaMode::Ignore) => src[a].to_unit(),
+                (Some(a), _) => result[a].max(0.0),
+                (None, _) => 1.0,
+            };
+            for (ch, (dst, value)) in dst.iter_mut().zip(result).enumerate() {
+                *dst = if Some(ch) == P::ALPHA {
+                    P::Channel::from_unit(alpha)
+                } else if premultiply {
+                    let value = if alpha > 0.0 { value / alpha } else { 0.0 };
+                    P::Channel::from_unit(value + kernel.bias)
+                } else {
+                    P::Channel::from_unit(value + kernel.bias)
+                };
+            }
+        }
+    });
+
+    OwnedImage::from_raw(out_dims, out.into_boxed_slice())
+}
+
+/// Accumulates one output pixel from `(source pixel, weight)` taps. Taps with
+/// no source pixel read `constant`, or are left out when skipping.
+fn accumulate<P: PixelType>(dst: &mut [f32], input: &[f32], taps: impl Iterator<Item = (Option<usize>, f32)>,
+                            edge: &EdgeMode<P>, constant: &[f32]) {
+    let (mut total, mut used) = (0.0, 0.0);
+    for (src, weight) in taps {
+        total += weight;
+        let values = match src {
+            Some(src) => &input[src..src + dst.len()],
+            None if *edge == EdgeMode::Skip => continue,
+            None => constant,
+        };
+        used += weight;
+        for (value, sample) in dst.iter_mut().zip(values) {
+            *value += sample * weight;
+        }
+    }
+    if used != total && f32::abs(used) > f32::EPSILON {
+        dst.iter_mut().for_each(|value| *value *= total / used);
+    }
+}
+
+/// One 1D pass along rows (`horizontal`) or columns. Returns the new extent
+/// along the pass axis, which only changes when cropping, and the values.
+pub(crate) fn convolve_axis<P: PixelType>(input: &[f32], (w, h): (usize, usize), c: usize, taps: &[f32],
+                               horizontal: bool, edge: &EdgeMode<P>, constant: &[f32]) -> (usize, Vec<f32>) {
+    let radius = taps.len() / 2;
+    let (len, other) = if horizontal { (w, h) } else { (h, w) };
+    let (out_len, shift) = if *edge == EdgeMode::Crop {
+        (len.saturating_sub(2 * radius), radius)
+    } else {
+        (len, 0)
+    };
+    let out_w = if horizontal { out_len } else { w };
+
+    let mut out = vec![0.0; out_len * other * c];
+    parallel::for_each_band(&mut out, out_w * c, |first_row, band| {
+        for (y, row) in (first_row..).zip(band.chunks_mut(out_w * c)) {
+            for (x, dst) in row.chunks_mut(c).enumerate() {
+                // `o` indexes the line, `p` the position along it
+                let (o, p) = if horizontal { (y, x) } else { (x, y) };
+                let centre = (p + shift) as i64 - radius as i64;
+                let sources = taps.iter().enumerate().map(|(k, &weight)| {
+                    let src = edge_index(centre + k as i64, len, edge)
+                        .map(|s| if horizontal { (o * w + s) * c } else { (s * w + o) * c });
+                    (src, weight)
+                });
+                accumulate(dst, input, sources, edge, constant);
+            }
+        }
+    });
+    (out_len, out)
+}
+
+fn convolve_2d<P: PixelType>(input: &[f32], (w, h): (usize, usize), c: usize, kernel: &Kernel,
+                             edge: &EdgeMode<P>, constant: &[f32]) -> Vec<f32> {
+    let (rx, ry) = (kernel.width / 2, kernel.height / 2);
+    let (out_w, out_h, shift_x, shift_y) = if *edge == EdgeMode::Crop {
+        (w.saturating_sub(2 * rx), h.saturating_sub(2 * ry), rx, ry)
+    } else {
+        (w, h, 0, 0)
+    };
+
+    let mut out = vec![0.0; out_w * out_h * c];
+    parallel::for_each_band(&mut out, out_w * c, |first_row, band| {
+        for (y, row) in (first_row..).zip(band.chunks_mut(out_w * c)) {
+            for (x, dst) in row.chunks_mut(c).enumerate() {
+                let (left, top) = ((x + shift_x) as i64 - rx as i64, (y + shift_y) as i64 - ry as i64);
+                let sources = kernel.weights.iter().enumerate().map(|(k, &weight)| {
+                    let sx = edge_index(left + (k % kernel.width) as i64, w, edge);
+                    let sy = edge_index(top + (k / kernel.width) as i64, h, edge);
+                    let src = match (sx, sy) {
+                        (Some(sx), Some(sy)) => Some((sy * w + sx) * c),
+                        _ => None,
+                    };
+                    (src, weight)
+                });
+                accumulate(dst, input, sources, edge, constant);
+            }
+        }
+    });
+    out
+}
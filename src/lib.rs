@@ -1,4 +1,19 @@
 pub mod traits;
+#[allow(non_snake_case)]
 pub mod OwnedImage;
+#[allow(non_snake_case)]
 pub mod ImageSlice;
-pub mod utils;
\ No newline at end of file
+pub mod utils;
+pub mod view;
+pub mod pixel;
+pub mod geometry;
+pub mod resize;
+pub mod kernel;
+pub mod blur;
+pub mod adjust;
+pub mod colour;
+pub mod linear;
+pub mod histogram;
+pub mod threshold;
+pub mod quantize;
+pub mod parallel;
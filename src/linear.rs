@@ -0,0 +1,92 @@
+use super::OwnedImage::*;
+use super::ImageSlice::*;
+use super::traits::*;
+use super::utils::*;
+use super::pixel::*;
+use super::colour::{srgb_to_linear, linear_to_srgb};
+use super::view::{StridedView, StridedViewMut};
+
+/**************************************** Linear Images ***********************************************/
+/******************************************************************************************************/
+
+/// An image decoded from sRGB into linear light, where blurs, resizes and
+/// blends mix colours the way light does instead of darkening edges and
+/// gradients. Values are stored as floats, so nothing is lost in between;
+/// alpha is never encoded and passes through unchanged.
+///
+/// It is an image like any other: operations in place keep it linear, while
+/// those returning a new image return plain `Rgba32F` values, which
+/// `LinearImage::from_linear` wraps again.
+pub struct LinearImage {
+    image: OwnedImage<Rgba32F>,
+}
+
+impl LinearImage {
+
+    /// Decodes `image` from sRGB. 8-bit channels are looked up in a table,
+    /// others are decoded exactly.
+    pub fn from_srgb<I: Image + ?Sized>(image: &I) -> LinearImage {
+        let max = <I::Pixel as PixelType>::Channel::MAX;
+        let table: Vec<f32> = (0..<I::Pixel as PixelType>::Channel::LUT_SIZE)
+            .map(|i| srgb_to_linear(i as f32 / max))
+            .collect();
+        // scaled 8-bit values land exactly back on their table index
+        let to_linear = |value: f32| match table.get((value * max).round() as usize) {
+            Some(&linear) => linear,
+            None => srgb_to_linear(value),
+        };
+
+        let image = image.map_pixels(|pixel| {
+            let [r, g, b, alpha] = pixel.to_rgba();
+            Rgba { r: to_linear(r), g: to_linear(g), b: to_linear(b), alpha }
+        });
+        LinearImage { image }
+    }
+
+    /// Wraps values that are already linear.
+    pub fn from_linear(image: OwnedImage<Rgba32F>) -> LinearImage {
+        LinearImage { image }
+    }
+
+    /// Encodes back into sRGB, as layout `Q`.
+    pub fn to_srgb<Q: PixelType>(&self) -> OwnedImage<Q> {
+        self.image.map_pixels(|Rgba { r, g, b, alpha }| {
+            Q::from_rgba([linear_to_srgb(r), linear_to_srgb(g), linear_to_srgb(b), alpha])
+        })
+    }
+
+    /// The linear values, unwrapped.
+    pub fn into_linear(self) -> OwnedImage<Rgba32F> {
+        self.image
+    }
+}
+
+impl Image for LinearImage {
+    type Pixel = Rgba32F;
+
+    fn dimensions(&self) -> &Dimensions {
+        self.image.dimensions()
+    }
+
+    fn view(&self) -> StridedView<'_, Rgba32F> {
+        self.image.view()
+    }
+
+    fn crop(&self, x: u32, y: u32, dims: Dimensions) -> Result<ImageSlice<'_, Rgba32F>, ImageError> {
+        self.image.crop(x, y, dims)
+    }
+}
+
+impl ImageMut for LinearImage {
+    fn crop_mut(&mut self, x: u32, y: u32, dims: Dimensions) -> Result<ImageSliceMut<'_, Rgba32F>, ImageError> {
+        self.image.crop_mut(x, y, dims)
+    }
+
+    fn view_mut(&mut self) -> StridedViewMut<'_, Rgba32F> {
+        self.image.view_mut()
+    }
+
+    fn copy_from(&mut self, source: &impl Image<Pixel = Rgba32F>) -> Result<(), ImageError> {
+        self.image.copy_from(source)
+    }
+}
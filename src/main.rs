@@ -2,79 +2,188 @@ use image_processor::{
     utils,
     traits::*,
     OwnedImage::OwnedImage,
-    ImageSlice
+    utils::ImageError,
+    pixel::*,
+    geometry::{Interpolation, Canvas},
+    resize::{Filter, Sizing, Anchor},
+    kernel::{Kernel, EdgeMode},
+    colour::{self, GreyscaleMethod, DeltaE},
+    histogram::{self, Clahe},
+    threshold::Threshold,
+    quantize::{Palette, PaletteMethod, Dither},
 };
 
-fn main() {
+fn main() -> Result<(), ImageError> {
 
-    /// example use of our image processor library
-    let image = match utils::import("test.png") {
-        Ok(image) => image,
-        Err(err) => return utils::dispatch_error(err)
-    };
+    // example use of our image processor library
+    // the format is sniffed from the file contents and reported back
+    let (image, format): (OwnedImage, _) = utils::load("test.png")?;
+    println!("Loaded {:?} image", format);
 
     /****** Immutable Operations ******/
 
-    /// Operation 1: Immutable crop, returns new cropped image
-    /// If input dimensions out of bound, will use the image's own width/height to perform cropping
-    let slice = image.crop(100, 100, utils::Dimensions{width: 500, height: 400});
-    utils::save_image(&slice, "cropped.png");
+    // Operation 1: Immutable crop, returns new cropped image
+    // If input dimensions out of bound, will use the image's own width/height to perform cropping
+    let slice = image.crop(100, 100, utils::Dimensions{width: 500, height: 400})?;
+    utils::save_image(&slice, "cropped.png")?;
 
-    /// Operation 2: Immutable flip, horizontally, vertically, and both
+    // Operation 2: Immutable flip, horizontally, vertically, and both
     let horiz = image.flipped(true, false);
     let verti = image.flipped(true, false);
     let both = image.flipped(true, true);
-    utils::save_image(&horiz, "horiz_flipped.png");
-    utils::save_image(&verti, "verti_flipped.png");
-    utils::save_image(&both, "horiz_verti_flipped.png");
+    utils::save_image(&horiz, "horiz_flipped.png")?;
+    utils::save_image(&verti, "verti_flipped.png")?;
+    utils::save_image(&both, "horiz_verti_flipped.png")?;
 
-    /// Operation 3: Immutable greyscale
+    // Operation 3: Immutable greyscale
     let grey = image.greyscale();
-    utils::save_image(&grey, "greyscale.png");
-
-    /// Operation 4: Immutable blur
-    /// amount parameter should be between 0-10
-    let blurred = image.blurred(3);
-    utils::save_image(&blurred, "blurred.png");
+    utils::save_image(&grey, "greyscale.png")?;
+
+    // Save with an explicit output format instead of the file extension,
+    // converted to a single luma channel since colour isn't needed
+    utils::save_image_with_format(&grey.converted::<Luma8>(), "greyscale.jpg",
+                                  utils::OutputFormat::Jpeg { quality: 90 })?;
+
+    // Operation 4: Immutable blur
+    // amount parameter should be between 0-10
+    let blurred = image.blurred(3, EdgeMode::Clamp);
+    utils::save_image(&blurred, "blurred.png")?;
+
+    // Gaussian blur takes any sigma, per axis; blurring one axis only gives a motion streak
+    let soft = image.gaussian_blurred(2.5, 2.5, EdgeMode::Mirror);
+    utils::save_image(&soft, "gaussian.png")?;
+    let streak = image.gaussian_blurred(12.0, 0.0, EdgeMode::Clamp);
+    utils::save_image(&streak, "motion_streak.png")?;
+
+    // Operation 5: Immutable rotation, lossless by quarter turns or by any angle
+    let quarter = image.rotated90();
+    utils::save_image(&quarter, "rotated90.png")?;
+    let tilted = image.rotated(15.0, Interpolation::Bicubic, Canvas::Expand,
+                               utils::Pixel{r: 0, g: 0, b: 0, alpha: 0});
+    utils::save_image(&tilted, "rotated15.png")?;
+
+    // Operation 6: Immutable resize, here a square thumbnail cropped around the centre
+    let thumb = image.resized_to(utils::Dimensions{width: 200, height: 200}, Filter::Lanczos3,
+                                 Sizing::Cover(Anchor::Centre));
+    utils::save_image(&thumb, "thumbnail.png")?;
+
+    // Operation 7: Immutable convolution with a preset or custom kernel
+    let sharpened = image.convolved(&Kernel::sharpen(), EdgeMode::Clamp);
+    utils::save_image(&sharpened, "sharpened.png")?;
+
+    // Operation 8: Custom per-pixel effect, here swapping the red and blue channels
+    let swapped = image.map_pixels(|p| utils::Pixel{r: p.b, b: p.r, ..p});
+    utils::save_image(&swapped, "channels_swapped.png")?;
+
+    // Operation 9: Tone corrections: brighter midtones, a touch more contrast, half a stop up
+    let corrected = image.adjust_gamma(1.4).adjust_contrast(1.1).adjust_exposure(0.5);
+    utils::save_image(&corrected, "tone_corrected.png")?;
+
+    // Operation 10: Colour variants: a hue-shifted theme, a punchier copy and a sepia tint
+    utils::save_image(&image.hue_rotate(150.0), "hue_rotated.png")?;
+    utils::save_image(&image.saturate(1.5), "saturated.png")?;
+    utils::save_image(&image.colorize(35.0, 0.4), "colorized.png")?;
+
+    // Operation 11: Single-channel greyscale weighed by perceived brightness
+    let luma = image.luma::<Luma8>(GreyscaleMethod::LinearLuminance);
+    utils::save_image(&luma, "luma.png")?;
+
+    // Operation 12: How far a lossy round trip drifts from the original, in CIEDE2000
+    let mut jpeg = Vec::new();
+    utils::write_image(&image, &mut jpeg, utils::OutputFormat::Jpeg { quality: 60 })?;
+    let (reloaded, _): (OwnedImage, _) = utils::load_from_memory(&jpeg)?;
+    println!("Mean colour difference after JPEG: {:.2}", colour::mean_delta_e(&image, &reloaded, DeltaE::Ciede2000)?);
+
+    // Operation 13: Contrast normalisation: equalized, auto-levelled, and matched to the sepia tint
+    let histogram = image.histogram();
+    println!("Median luma: {}", histogram::percentile(&histogram.luma, 0.5));
+    utils::save_image(&image.equalize(), "equalized.png")?;
+    utils::save_image(&image.auto_levels(0.005), "auto_levels.png")?;
+    let sepia = image.colorize(35.0, 0.4).histogram();
+    utils::save_image(&image.match_histogram(&sepia), "histogram_matched.png")?;
+
+    // Operation 14: Local contrast for dim shots, without blowing out the highlights
+    utils::save_image(&image.clahe(Clahe { clip_limit: 3.0, ..Clahe::default() }), "clahe.png")?;
+
+    // Operation 15: Binary masks, here Sauvola's document threshold, then used to cut out the bright parts
+    let mask = image.threshold(Threshold::Sauvola { radius: 15, k: 0.3, edge: EdgeMode::Skip });
+    utils::save_image(&mask, "threshold_mask.png")?;
+    let cut_out = image.zip_map(&mask, |pixel, Luma { luma }| utils::Pixel { alpha: luma, ..pixel })?;
+    utils::save_image(&cut_out, "cut_out.png")?;
+
+    // Operation 16: Colour reduction: a 16-colour PNG8 and GIF, and a dithered e-ink preview
+    let palette = Palette::from_image(&image, 16, PaletteMethod::KMeans { iterations: 8 })?;
+    let indexed = image.to_indexed(&palette, Dither::FloydSteinberg);
+    utils::save_indexed(&indexed, "indexed16.png")?;
+    utils::save_indexed(&indexed, "indexed16.gif")?;
+    let e_ink = image.to_indexed(&Palette::greys(2)?, Dither::Atkinson);
+    utils::save_indexed(&e_ink, "e_ink.png")?;
 
     /****** Mutable Operations ******/
 
-    /// Can make an empty image
+    // Can make an empty image
     let mut image_copy = OwnedImage::new(
-        utils::Dimensions{width: 0, height: 0}, Vec::new().into_boxed_slice());
+        utils::Dimensions{width: 0, height: 0}, Vec::new().into_boxed_slice())?;
 
-    /// Copy image into image_copy
-    image_copy.copy_from(&image);
+    // Copy image into image_copy
+    image_copy.copy_from(&image)?;
 
-    /// Operation 1: Mutable flip
+    // Operation 1: Mutable flip
     image_copy.flip(true, false);
-    utils::save_image(&image_copy, "horiz_flipped_mut.png");
+    utils::save_image(&image_copy, "horiz_flipped_mut.png")?;
 
-    /// Operation 2: Mutable greyscale
+    // Operation 2: Mutable greyscale
     image_copy.greyscale_mut();
-    utils::save_image(&image_copy, "greyscale_mut.png");
+    utils::save_image(&image_copy, "greyscale_mut.png")?;
+
+    // Operation 3: Mutable blur
+    image_copy.blur(2, EdgeMode::Mirror)?;
+    utils::save_image(&image_copy, "blurred_mut.png")?;
 
-    /// Operation 3: Mutable blur
-    image_copy.blur(2);
-    utils::save_image(&image_copy, "blurred_mut.png");
+    // Operation 4: Mutable blur in linear light, which keeps colour edges from darkening
+    image_copy.copy_from(&image)?;
+    image_copy.in_linear_light(|linear| linear.gaussian_blur(4.0, 4.0, EdgeMode::Mirror))?;
+    utils::save_image(&image_copy, "blurred_linear.png")?;
 
 
     /****** Slice Operations ******/
 
-    /// Perform crop and then greyscale
-    let crop_grey = image.crop(100, 100, utils::Dimensions{width: 500, height: 400})
+    // Perform crop and then greyscale
+    let crop_grey = image.crop(100, 100, utils::Dimensions{width: 500, height: 400})?
         .greyscale();
-    utils::save_image(&crop_grey, "cropped_greyscale.png");
+    utils::save_image(&crop_grey, "cropped_greyscale.png")?;
 
-    /// Perform crop and then blur
-    let crop_blur = image.crop(100, 100, utils::Dimensions{width: 500, height: 400})
-        .blurred(3);
-    utils::save_image(&crop_blur, "cropped_blurred.png");
+    // Perform crop and then blur
+    let crop_blur = image.crop(100, 100, utils::Dimensions{width: 500, height: 400})?
+        .blurred(3, EdgeMode::Clamp);
+    utils::save_image(&crop_blur, "cropped_blurred.png")?;
 
-    /// Perform crop and then flip
-    let crop_flip = image.crop(100, 100, utils::Dimensions{width: 500, height: 400})
-        .flipped(true,false);
-    utils::save_image(&crop_flip, "cropped_flipped.png");
+    // Read a crop's rows in place, without copying its pixels
+    let crop = image.crop(100, 100, utils::Dimensions{width: 500, height: 400})?;
+    let total: u64 = crop.rows().flat_map(|row| row.iter()).map(|&v| v as u64).sum();
+    println!("Mean channel value in crop: {}", total / crop.row(0).len() as u64 / 400);
 
+    // Perform crop and then flip
+    let crop_flip = image.crop(100, 100, utils::Dimensions{width: 500, height: 400})?
+        .flipped(true,false);
+    utils::save_image(&crop_flip, "cropped_flipped.png")?;
+
+    // Edit a region in place: only that rectangle of image_copy changes
+    image_copy.copy_from(&image)?;
+    image_copy.crop_mut(100, 100, utils::Dimensions{width: 200, height: 150})?
+        .gaussian_blur(8.0, 8.0, EdgeMode::Clamp)?;
+    image_copy.crop_mut(400, 100, utils::Dimensions{width: 200, height: 150})?
+        .greyscale_mut();
+    utils::save_image(&image_copy, "region_edits.png")?;
+
+    // Split into tiles that can be edited at once, here greyscaling a checkerboard
+    image_copy.copy_from(&image)?;
+    for (i, mut tile) in image_copy.tiles_mut(100, 100).into_iter().enumerate() {
+        if i % 2 == 0 {
+            tile.greyscale_mut();
+        }
+    }
+    utils::save_image(&image_copy, "checkerboard.png")?;
+
+    Ok(())
 }
-
@@ -0,0 +1,81 @@
+//! Splitting work into row bands, run concurrently with the `parallel` feature.
+//!
+//! Operations hand each band to the same closure whichever way they run, and
+//! every output value only depends on the input, so the threaded and serial
+//! paths give bit-identical results.
+//!
+//! Threaded work runs in rayon's current pool. To cap CPU use, configure the
+//! global pool once with `set_threads`, run a batch under `with_threads`, or
+//! call operations inside your own pool's `rayon::ThreadPool::install`.
+
+use std::ops::Range;
+#[cfg(feature = "parallel")]
+use super::utils::*;
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+#[cfg(feature = "parallel")]
+use snafu::ResultExt;
+
+/// Rows handed to a thread at a time.
+const BAND_ROWS: usize = 16;
+
+/// Calls `f` with the index of the first row and the rows of each band of
+/// `buffer`, whose rows are `row_len` values long.
+pub(crate) fn for_each_band<T: Send>(buffer: &mut [T], row_len: usize, f: impl Fn(usize, &mut [T]) + Sync) {
+    if row_len == 0 {
+        return;
+    }
+    let band_len = BAND_ROWS * row_len;
+
+    #[cfg(feature = "parallel")]
+    buffer.par_chunks_mut(band_len).enumerate().for_each(|(i, band)| f(i * BAND_ROWS, band));
+
+    #[cfg(not(feature = "parallel"))]
+    buffer.chunks_mut(band_len).enumerate().for_each(|(i, band)| f(i * BAND_ROWS, band));
+}
+
+/// Calls `f` on pairs of rows from `top` and `bottom`, pairing the first row
+/// of `top` with the last row of `bottom` and so on inwards.
+pub(crate) fn for_each_row_pair<T: Send>(top: &mut [T], bottom: &mut [T], row_len: usize,
+                                         f: impl Fn(&mut [T], &mut [T]) + Sync) {
+    if row_len == 0 {
+        return;
+    }
+
+    #[cfg(feature = "parallel")]
+    top.par_chunks_mut(row_len).zip(bottom.par_chunks_mut(row_len).rev()).for_each(|(a, b)| f(a, b));
+
+    #[cfg(not(feature = "parallel"))]
+    top.chunks_mut(row_len).zip(bottom.chunks_mut(row_len).rev()).for_each(|(a, b)| f(a, b));
+}
+
+/// Calls `f` with the range of rows in each band of an image `height` rows
+/// high, and returns the results in order.
+pub(crate) fn map_bands<R: Send>(height: usize, f: impl Fn(Range<usize>) -> R + Sync + Send) -> Vec<R> {
+    let starts: Vec<usize> = (0..height).step_by(BAND_ROWS).collect();
+    map_each(&starts, |&start| f(start..height.min(start + BAND_ROWS)))
+}
+
+/// Maps every item in `items`, keeping their order.
+pub(crate) fn map_each<I: Sync, R: Send>(items: &[I], f: impl Fn(&I) -> R + Sync + Send) -> Vec<R> {
+    #[cfg(feature = "parallel")]
+    return items.par_iter().map(f).collect();
+
+    #[cfg(not(feature = "parallel"))]
+    return items.iter().map(f).collect();
+}
+
+/// Limits the global pool to `threads` threads (0 picks one per CPU).
+/// Only works before anything has used the pool.
+#[cfg(feature = "parallel")]
+pub fn set_threads(threads: usize) -> Result<(), ImageError> {
+    rayon::ThreadPoolBuilder::new().num_threads(threads).build_global().context(ThreadPool)?;
+    Ok(())
+}
+
+/// Runs `op` with image operations limited to `threads` threads.
+#[cfg(feature = "parallel")]
+pub fn with_threads<R: Send>(threads: usize, op: impl FnOnce() -> R + Send) -> Result<R, ImageError> {
+    let pool = rayon::ThreadPoolBuilder::new().num_threads(threads).build().context(ThreadPool)?;
+    Ok(pool.install(op))
+}
@@ -0,0 +1,351 @@
+use image::{DynamicImage, ImageBuffer};
+use std::fmt::Debug;
+
+/************************************** Channel Primitive Types ***************************************/
+/******************************************************************************************************/
+
+/// A single channel value: `u8`, `u16` or `f32`.
+pub trait Primitive: Copy + Default + PartialEq + PartialOrd + Debug + Send + Sync + 'static {
+    /// Value of a fully saturated channel (1.0 for floats).
+    const MAX: f32;
+
+    /// Number of distinct values, when few enough that per-value operations
+    /// are cheaper through a lookup table (`u8`'s 256). Zero otherwise.
+    const LUT_SIZE: usize = 0;
+
+    /// This value as a float, on the channel's own scale.
+    fn to_f32(self) -> f32;
+
+    /// Rounds and clamps `value` into this channel type. Floats are stored as they are.
+    fn from_f32(value: f32) -> Self;
+
+    /// This value scaled to 0.0-1.0.
+    fn to_unit(self) -> f32 {
+        self.to_f32() / Self::MAX
+    }
+
+    /// Builds a channel value from one scaled to 0.0-1.0.
+    fn from_unit(value: f32) -> Self {
+        Self::from_f32(value * Self::MAX)
+    }
+}
+
+impl Primitive for u8 {
+    const MAX: f32 = 255.0;
+    const LUT_SIZE: usize = 256;
+
+    fn to_f32(self) -> f32 {
+        self as f32
+    }
+
+    fn from_f32(value: f32) -> Self {
+        // float to int casts saturate, and NaN becomes 0
+        value.round() as u8
+    }
+}
+
+impl Primitive for u16 {
+    const MAX: f32 = 65535.0;
+
+    fn to_f32(self) -> f32 {
+        self as f32
+    }
+
+    fn from_f32(value: f32) -> Self {
+        value.round() as u16
+    }
+}
+
+impl Primitive for f32 {
+    const MAX: f32 = 1.0;
+
+    fn to_f32(self) -> f32 {
+        self
+    }
+
+    fn from_f32(value: f32) -> Self {
+        value
+    }
+}
+
+/******************************************* Pixel Types **********************************************/
+/******************************************************************************************************/
+
+/// A pixel layout: how many channels it has, of which primitive type, and how
+/// it converts to and from other layouts.
+pub trait PixelType: Copy + Default + PartialEq + Debug + Send + Sync + 'static {
+    /// The type of each channel.
+    type Channel: Primitive;
+
+    /// Number of channels per pixel.
+    const CHANNELS: usize;
+
+    /// Index of the alpha channel, if there is one.
+    const ALPHA: Option<usize>;
+
+    /// Reads a pixel from its first `CHANNELS` channel values.
+    fn from_channels(channels: &[Self::Channel]) -> Self;
+
+    /// Writes this pixel into the first `CHANNELS` channel values of `out`.
+    fn write_channels(&self, out: &mut [Self::Channel]);
+
+    /// This pixel as RGBA scaled to 0.0-1.0. Luma is spread over the colour
+    /// channels and a missing alpha is opaque.
+    fn to_rgba(&self) -> [f32; 4];
+
+    /// Builds a pixel from RGBA scaled to 0.0-1.0. Colour collapses to Rec.709
+    /// luma and alpha is dropped when this layout has no room for them.
+    fn from_rgba(rgba: [f32; 4]) -> Self;
+
+    /// Converts this pixel into another layout.
+    fn convert<Q: PixelType>(&self) -> Q {
+        Q::from_rgba(self.to_rgba())
+    }
+
+    /// Converts a decoded image of any colour type into this layout's channels.
+    fn from_dynamic(image: DynamicImage) -> Vec<Self::Channel>;
+
+    /// Wraps channels of this layout into a `DynamicImage` of the closest colour type.
+    fn to_dynamic(width: u32, height: u32, channels: Vec<Self::Channel>) -> DynamicImage;
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Luma<T> {
+    pub luma: T,
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct LumaA<T> {
+    pub luma: T,
+    pub alpha: T,
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Rgb<T> {
+    pub r: T,
+    pub g: T,
+    pub b: T,
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Rgba<T> {
+    pub r: T,
+    pub g: T,
+    pub b: T,
+    pub alpha: T,
+}
+
+pub type Luma8 = Luma<u8>;
+pub type Luma16 = Luma<u16>;
+pub type Luma32F = Luma<f32>;
+pub type LumaA8 = LumaA<u8>;
+pub type LumaA16 = LumaA<u16>;
+pub type LumaA32F = LumaA<f32>;
+pub type Rgb8 = Rgb<u8>;
+pub type Rgb16 = Rgb<u16>;
+pub type Rgb32F = Rgb<f32>;
+pub type Rgba8 = Rgba<u8>;
+pub type Rgba16 = Rgba<u16>;
+pub type Rgba32F = Rgba<f32>;
+
+/// Rec.709 luma of scaled RGB. Greys keep their exact value.
+pub(crate) fn rgb_luma(r: f32, g: f32, b: f32) -> f32 {
+    if r == g && g == b {
+        return r;
+    }
+    0.2126 * r + 0.7152 * g + 0.0722 * b
+}
+
+// Builds the `DynamicImage` variant `$variant` from a channel vector.
+macro_rules! dynamic {
+    ($variant:ident, $width:expr, $height:expr, $channels:expr) => {
+        DynamicImage::$variant(ImageBuffer::from_raw($width, $height, $channels)
+            .expect("channel buffer matches its dimensions"))
+    };
+}
+
+macro_rules! impl_luma {
+    ($t:ty, $from:expr, $to:expr) => {
+        impl PixelType for Luma<$t> {
+            type Channel = $t;
+            const CHANNELS: usize = 1;
+            const ALPHA: Option<usize> = None;
+
+            fn from_channels(channels: &[$t]) -> Self {
+                Luma { luma: channels[0] }
+            }
+
+            fn write_channels(&self, out: &mut [$t]) {
+                out[0] = self.luma;
+            }
+
+            fn to_rgba(&self) -> [f32; 4] {
+                let l = self.luma.to_unit();
+                [l, l, l, 1.0]
+            }
+
+            fn from_rgba(rgba: [f32; 4]) -> Self {
+                Luma { luma: <$t>::from_unit(rgb_luma(rgba[0], rgba[1], rgba[2])) }
+            }
+
+            fn from_dynamic(image: DynamicImage) -> Vec<$t> {
+                $from(image)
+            }
+
+            fn to_dynamic(width: u32, height: u32, channels: Vec<$t>) -> DynamicImage {
+                $to(width, height, channels)
+            }
+        }
+    };
+}
+
+macro_rules! impl_luma_alpha {
+    ($t:ty, $from:expr, $to:expr) => {
+        impl PixelType for LumaA<$t> {
+            type Channel = $t;
+            const CHANNELS: usize = 2;
+            const ALPHA: Option<usize> = Some(1);
+
+            fn from_channels(channels: &[$t]) -> Self {
+                LumaA { luma: channels[0], alpha: channels[1] }
+            }
+
+            fn write_channels(&self, out: &mut [$t]) {
+                out[0] = self.luma;
+                out[1] = self.alpha;
+            }
+
+            fn to_rgba(&self) -> [f32; 4] {
+                let l = self.luma.to_unit();
+                [l, l, l, self.alpha.to_unit()]
+            }
+
+            fn from_rgba(rgba: [f32; 4]) -> Self {
+                LumaA {
+                    luma: <$t>::from_unit(rgb_luma(rgba[0], rgba[1], rgba[2])),
+                    alpha: <$t>::from_unit(rgba[3]),
+                }
+            }
+
+            fn from_dynamic(image: DynamicImage) -> Vec<$t> {
+                $from(image)
+            }
+
+            fn to_dynamic(width: u32, height: u32, channels: Vec<$t>) -> DynamicImage {
+                $to(width, height, channels)
+            }
+        }
+    };
+}
+
+macro_rules! impl_rgb {
+    ($t:ty, $from:expr, $to:expr) => {
+        impl PixelType for Rgb<$t> {
+            type Channel = $t;
+            const CHANNELS: usize = 3;
+            const ALPHA: Option<usize> = None;
+
+            fn from_channels(channels: &[$t]) -> Self {
+                Rgb { r: channels[0], g: channels[1], b: channels[2] }
+            }
+
+            fn write_channels(&self, out: &mut [$t]) {
+                out[0] = self.r;
+                out[1] = self.g;
+                out[2] = self.b;
+            }
+
+            fn to_rgba(&self) -> [f32; 4] {
+                [self.r.to_unit(), self.g.to_unit(), self.b.to_unit(), 1.0]
+            }
+
+            fn from_rgba(rgba: [f32; 4]) -> Self {
+                Rgb {
+                    r: <$t>::from_unit(rgba[0]),
+                    g: <$t>::from_unit(rgba[1]),
+                    b: <$t>::from_unit(rgba[2]),
+                }
+            }
+
+            fn from_dynamic(image: DynamicImage) -> Vec<$t> {
+                $from(image)
+            }
+
+            fn to_dynamic(width: u32, height: u32, channels: Vec<$t>) -> DynamicImage {
+                $to(width, height, channels)
+            }
+        }
+    };
+}
+
+macro_rules! impl_rgba {
+    ($t:ty, $from:expr, $to:expr) => {
+        impl PixelType for Rgba<$t> {
+            type Channel = $t;
+            const CHANNELS: usize = 4;
+            const ALPHA: Option<usize> = Some(3);
+
+            fn from_channels(channels: &[$t]) -> Self {
+                Rgba { r: channels[0], g: channels[1], b: channels[2], alpha: channels[3] }
+            }
+
+            fn write_channels(&self, out: &mut [$t]) {
+                out[0] = self.r;
+                out[1] = self.g;
+                out[2] = self.b;
+                out[3] = self.alpha;
+            }
+
+            fn to_rgba(&self) -> [f32; 4] {
+                [self.r.to_unit(), self.g.to_unit(), self.b.to_unit(), self.alpha.to_unit()]
+            }
+
+            fn from_rgba(rgba: [f32; 4]) -> Self {
+                Rgba {
+                    r: <$t>::from_unit(rgba[0]),
+                    g: <$t>::from_unit(rgba[1]),
+                    b: <$t>::from_unit(rgba[2]),
+                    alpha: <$t>::from_unit(rgba[3]),
+                }
+            }
+
+            fn from_dynamic(image: DynamicImage) -> Vec<$t> {
+                $from(image)
+            }
+
+            fn to_dynamic(width: u32, height: u32, channels: Vec<$t>) -> DynamicImage {
+                $to(width, height, channels)
+            }
+        }
+    };
+}
+
+impl_luma!(u8, |img: DynamicImage| img.into_luma8().into_raw(),
+           |w, h, c| dynamic!(ImageLuma8, w, h, c));
+impl_luma!(u16, |img: DynamicImage| img.into_luma16().into_raw(),
+           |w, h, c| dynamic!(ImageLuma16, w, h, c));
+// there is no float luma colour type, so float luma is exported as float RGB
+impl_luma!(f32, |img: DynamicImage| img.to_luma32f().into_raw(),
+           |w, h, c: Vec<f32>| dynamic!(ImageRgb32F, w, h, c.iter().flat_map(|&l| vec![l; 3]).collect()));
+
+impl_luma_alpha!(u8, |img: DynamicImage| img.into_luma_alpha8().into_raw(),
+                 |w, h, c| dynamic!(ImageLumaA8, w, h, c));
+impl_luma_alpha!(u16, |img: DynamicImage| img.into_luma_alpha16().into_raw(),
+                 |w, h, c| dynamic!(ImageLumaA16, w, h, c));
+impl_luma_alpha!(f32, |img: DynamicImage| img.to_luma_alpha32f().into_raw(),
+                 |w, h, c: Vec<f32>| dynamic!(ImageRgba32F, w, h,
+                     c.chunks(2).flat_map(|p| vec![p[0], p[0], p[0], p[1]]).collect()));
+
+impl_rgb!(u8, |img: DynamicImage| img.into_rgb8().into_raw(),
+          |w, h, c| dynamic!(ImageRgb8, w, h, c));
+impl_rgb!(u16, |img: DynamicImage| img.into_rgb16().into_raw(),
+          |w, h, c| dynamic!(ImageRgb16, w, h, c));
+impl_rgb!(f32, |img: DynamicImage| img.into_rgb32f().into_raw(),
+          |w, h, c| dynamic!(ImageRgb32F, w, h, c));
+
+impl_rgba!(u8, |img: DynamicImage| img.into_rgba8().into_raw(),
+           |w, h, c| dynamic!(ImageRgba8, w, h, c));
+impl_rgba!(u16, |img: DynamicImage| img.into_rgba16().into_raw(),
+           |w, h, c| dynamic!(ImageRgba16, w, h, c));
+impl_rgba!(f32, |img: DynamicImage| img.into_rgba32f().into_raw(),
+           |w, h, c| dynamic!(ImageRgba32F, w, h, c));
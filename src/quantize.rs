@@ -0,0 +1,497 @@
+use super::OwnedImage::*;
+use super::traits::*;
+use super::utils::*;
+use super::pixel::*;
+use super::parallel;
+use super::view::{StridedView, StridedViewMut};
+use std::collections::HashMap;
+
+/***************************************** Palettes ***************************************************/
+/******************************************************************************************************/
+
+/// Up to 256 colours an image can be reduced to. Colours are matched by
+/// distance in RGB; alpha plays no part.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Palette {
+    colours: Vec<Rgb8>,
+    units: Vec<[f32; 3]>,
+}
+
+/// How `Palette::from_image` picks its colours.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum PaletteMethod {
+    /// Splits the image's colours at the median of their widest channel until
+    /// there are enough boxes, and takes each box's mean.
+    #[default]
+    MedianCut,
+    /// Sorts colours into an octree by their bits and merges its least used
+    /// branches until few enough leaves are left.
+    Octree,
+    /// Refines the median-cut colours by up to `iterations` rounds of k-means,
+    /// moving each to the mean of the colours nearest it. Slower, but closer.
+    KMeans { iterations: u32 },
+}
+
+impl Palette {
+
+    /// A palette of the given colours. Fails unless there are 1 to 256.
+    pub fn new(colours: Vec<Rgb8>) -> Result<Palette, ImageError> {
+        if colours.is_empty() || colours.len() > 256 {
+            return PaletteSize { count: colours.len() }.fail();
+        }
+        let units = colours.iter().map(|&Rgb { r, g, b }| [r.to_unit(), g.to_unit(), b.to_unit()]).collect();
+        Ok(Palette { colours, units })
+    }
+
+    /// `levels` (2 to 256) evenly spaced greys from black to white, as on
+    /// greyscale e-ink displays.
+    pub fn greys(levels: usize) -> Result<Palette, ImageError> {
+        if !(2..=256).contains(&levels) {
+            return PaletteSize { count: levels }.fail();
+        }
+        Palette::new((0..levels).map(|i| {
+            let v = u8::from_unit(i as f32 / (levels - 1) as f32);
+            Rgb { r: v, g: v, b: v }
+        }).collect())
+    }
+
+    /// Up to `count` (1 to 256) colours that best represent `image`, picked by
+    /// `method`. Fewer come back when the image has fewer colours; an empty
+    /// image gives black alone.
+    pub fn from_image<I: Image + ?Sized>(image: &I, count: usize, method: PaletteMethod) -> Result<Palette, ImageError> {
+        if count == 0 || count > 256 {
+            return PaletteSize { count }.fail();
+        }
+        let cells = colour_cells(&image.view());
+        let colours = match method {
+            PaletteMethod::MedianCut => median_cut(&cells, count),
+            PaletteMethod::Octree => octree(&cells, count),
+            PaletteMethod::KMeans { iterations } => k_means(&cells, median_cut(&cells, count), iterations),
+        };
+        let colours: Vec<Rgb8> = colours.iter()
+            .map(|&[r, g, b]| Rgb { r: u8::from_unit(r), g: u8::from_unit(g), b: u8::from_unit(b) })
+            .collect();
+        match colours.is_empty() {
+            true => Palette::new(vec![Rgb { r: 0, g: 0, b: 0 }]),
+            false => Palette::new(colours),
+        }
+    }
+
+    pub fn colours(&self) -> &[Rgb8] {
+        &self.colours
+    }
+
+    /// Index of the colour nearest `rgb` (scaled to 0.0-1.0).
+    pub fn nearest(&self, rgb: [f32; 3]) -> usize {
+        nearest(&self.units, rgb)
+    }
+
+    /// How far apart neighbouring colours lie, as the mean over every colour of
+    /// the largest channel difference to its nearest other colour. Sets how
+    /// strongly ordered dithering shakes values.
+    fn spacing(&self) -> f32 {
+        if self.units.len() < 2 {
+            return 0.0;
+        }
+        let gaps = self.units.iter().enumerate().map(|(i, a)| {
+            self.units.iter().enumerate()
+                .filter(|&(j, _)| j != i)
+                .map(|(_, b)| (0..3).map(|c| (a[c] - b[c]).abs()).fold(0.0, f32::max))
+                .fold(f32::INFINITY, f32::min)
+        });
+        gaps.sum::<f32>() / self.units.len() as f32
+    }
+}
+
+fn nearest(colours: &[[f32; 3]], [r, g, b]: [f32; 3]) -> usize {
+    let distance = |&[cr, cg, cb]: &[f32; 3]| (cr - r) * (cr - r) + (cg - g) * (cg - g) + (cb - b) * (cb - b);
+    let mut best = (0, f32::INFINITY);
+    for (i, colour) in colours.iter().enumerate() {
+        let d = distance(colour);
+        if d < best.1 {
+            best = (i, d);
+        }
+    }
+    best.0
+}
+
+/// A group of similar 8-bit colours: their mean (0.0-1.0) and how many pixels share it.
+#[derive(Clone, Copy, Debug)]
+struct Cell {
+    colour: [f32; 3],
+    count: f64,
+}
+
+/// The colours of `view`, gathered into cells of 5 bits per channel so the
+/// palette searches stay quick however many colours the image has.
+fn colour_cells<P: PixelType>(view: &StridedView<'_, P>) -> Vec<Cell> {
+    let mut sums: HashMap<u16, ([f64; 3], f64)> = HashMap::new();
+    for (_, _, pixel) in view.pixels_iter() {
+        let [r, g, b, _] = pixel.to_rgba();
+        let [r, g, b] = [u8::from_unit(r), u8::from_unit(g), u8::from_unit(b)];
+        let key = (r as u16 >> 3) << 10 | (g as u16 >> 3) << 5 | b as u16 >> 3;
+        let (sum, count) = sums.entry(key).or_insert(([0.0; 3], 0.0));
+        for (s, v) in sum.iter_mut().zip(&[r, g, b]) {
+            *s += *v as f64;
+        }
+        *count += 1.0;
+    }
+
+    // sorted so palettes don't depend on the map's order
+    let mut cells: Vec<(u16, Cell)> = sums.into_iter().map(|(key, (sum, count))| {
+        let colour = [0, 1, 2].map(|c| (sum[c] / count / u8::MAX as f64) as f32);
+        (key, Cell { colour, count })
+    }).collect();
+    cells.sort_by_key(|&(key, _)| key);
+    cells.into_iter().map(|(_, cell)| cell).collect()
+}
+
+/// Mean colour of `cells`, weighted by their counts.
+fn mean(cells: &[Cell]) -> [f32; 3] {
+    let total: f64 = cells.iter().map(|cell| cell.count).sum();
+    [0, 1, 2].map(|c| (cells.iter().map(|cell| cell.colour[c] as f64 * cell.count).sum::<f64>() / total) as f32)
+}
+
+/// The channel along which `cells` spread furthest, and how far.
+fn widest_channel(cells: &[Cell]) -> (usize, f32) {
+    (0..3).map(|c| {
+        let values = cells.iter().map(|cell| cell.colour[c]);
+        let (low, high) = values.fold((f32::INFINITY, f32::NEG_INFINITY), |(low, high), v| (low.min(v), high.max(v)));
+        (c, high - low)
+    }).max_by(|a, b| a.1.total_cmp(&b.1)).unwrap_or((0, 0.0))
+}
+
+fn median_cut(cells: &[Cell], count: usize) -> Vec<[f32; 3]> {
+    if cells.is_empty() {
+        return Vec::new();
+    }
+    let mut boxes = vec![cells.to_vec()];
+    while boxes.len() < count {
+        // split the box spreading furthest along any channel
+        let widest = boxes.iter().enumerate()
+            .filter(|(_, cells)| cells.len() > 1)
+            .map(|(i, cells)| {
+                let (channel, spread) = widest_channel(cells);
+                (i, channel, spread)
+            })
+            .max_by(|a, b| a.2.total_cmp(&b.2));
+        let (i, channel) = match widest {
+            Some((i, channel, _)) => (i, channel),
+            None => break,
+        };
+
+        let mut lower = boxes.swap_remove(i);
+        lower.sort_by(|a, b| a.colour[channel].total_cmp(&b.colour[channel]));
+        let half = lower.iter().map(|cell| cell.count).sum::<f64>() / 2.0;
+        let mut seen = 0.0;
+        let split = lower.iter().position(|cell| {
+            seen += cell.count;
+            seen >= half
+        }).unwrap_or(0);
+        // the median cell goes below, and each side keeps at least one
+        let upper = lower.split_off((split + 1).min(lower.len() - 1));
+        boxes.push(lower);
+        boxes.push(upper);
+    }
+    boxes.iter().map(|cells| mean(cells)).collect()
+}
+
+/// A node of an octree over 8-bit colours, one level per bit. Child 0 never
+/// points anywhere, as the root is no one's child.
+struct Node {
+    children: [usize; 8],
+    count: f64,
+    sum: [f64; 3],
+    // whether the node is already listed in its level
+    queued: bool,
+}
+
+fn octree(cells: &[Cell], count: usize) -> Vec<[f32; 3]> {
+    const DEPTH: usize = 8;
+    let mut nodes = vec![Node { children: [0; 8], count: 0.0, sum: [0.0; 3], queued: false }];
+    let mut levels: Vec<Vec<usize>> = vec![Vec::new(); DEPTH];
+    let mut leaves = 0;
+
+    for cell in cells {
+        let rgb = cell.colour.map(u8::from_unit);
+        let mut node = 0;
+        for (depth, level) in levels.iter_mut().enumerate() {
+            let bit = DEPTH - 1 - depth;
+            let child = ((rgb[0] >> bit) & 1) << 2 | ((rgb[1] >> bit) & 1) << 1 | (rgb[2] >> bit) & 1;
+            if nodes[node].children[child as usize] == 0 {
+                nodes.push(Node { children: [0; 8], count: 0.0, sum: [0.0; 3], queued: false });
+                nodes[node].children[child as usize] = nodes.len() - 1;
+                if depth == DEPTH - 1 {
+                    leaves += 1;
+                }
+            }
+            if !nodes[node].queued {
+                nodes[node].queued = true;
+                level.push(node);
+            }
+            node = nodes[node].children[child as usize];
+        }
+        nodes[node].count += cell.count;
+        for c in 0..3 {
+            nodes[node].sum[c] += cell.colour[c] as f64 * cell.count;
+        }
+    }
+
+    // fold the least used branches into their parents, deepest first, so
+    // every branch merged has only leaves below it
+    for level in levels.iter().rev() {
+        let mut branches: Vec<(usize, f64)> = level.iter().map(|&node| {
+            let count = nodes[node].children.iter().filter(|&&child| child != 0).map(|&child| nodes[child].count).sum();
+            (node, count)
+        }).collect();
+        branches.sort_by(|a, b| a.1.total_cmp(&b.1));
+        for (node, _) in branches {
+            if leaves <= count {
+                break;
+            }
+            let children: Vec<usize> = nodes[node].children.iter().copied().filter(|&child| child != 0).collect();
+            for &child in &children {
+                nodes[node].count += nodes[child].count;
+                for c in 0..3 {
+                    nodes[node].sum[c] += nodes[child].sum[c];
+                }
+            }
+            nodes[node].children = [0; 8];
+            leaves -= children.len() - 1;
+        }
+    }
+
+    let mut colours = Vec::new();
+    let mut stack = vec![0];
+    while let Some(node) = stack.pop() {
+        let node = &nodes[node];
+        match node.children.iter().any(|&child| child != 0) {
+            true => stack.extend(node.children.iter().rev().filter(|&&child| child != 0)),
+            false if node.count > 0.0 => colours.push(node.sum.map(|s| (s / node.count) as f32)),
+            false => {},
+        }
+    }
+    colours
+}
+
+fn k_means(cells: &[Cell], mut centres: Vec<[f32; 3]>, iterations: u32) -> Vec<[f32; 3]> {
+    let mut assigned: Vec<usize> = Vec::new();
+    for _ in 0..iterations {
+        let nearest: Vec<usize> = parallel::map_each(cells, |cell| nearest(&centres, cell.colour));
+        if nearest == assigned {
+            break;
+        }
+        assigned = nearest;
+
+        let mut sums = vec![([0.0f64; 3], 0.0f64); centres.len()];
+        for (cell, &centre) in cells.iter().zip(&assigned) {
+            let (sum, count) = &mut sums[centre];
+            for (s, &v) in sum.iter_mut().zip(&cell.colour) {
+                *s += v as f64 * cell.count;
+            }
+            *count += cell.count;
+        }
+        // centres left without colours stay where they are
+        for (centre, (sum, count)) in centres.iter_mut().zip(sums) {
+            if count > 0.0 {
+                *centre = sum.map(|s| (s / count) as f32);
+            }
+        }
+    }
+    centres
+}
+
+/**************************************** Dithering ***************************************************/
+/******************************************************************************************************/
+
+/// How values between palette colours are spread over neighbouring pixels.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum Dither {
+    /// Every pixel takes its nearest colour, leaving flat bands.
+    #[default]
+    None,
+    /// Ordered dithering with a Bayer matrix `size` pixels square (2 to 16,
+    /// rounded up to a power of two). Repeating patterns, but stable and
+    /// computed pixel by pixel.
+    Bayer { size: u32 },
+    /// Error diffusion, passing each pixel's error to four neighbours.
+    FloydSteinberg,
+    /// Error diffusion passing on only three quarters of the error, for
+    /// crisper, higher-contrast results.
+    Atkinson,
+    /// Error diffusion over ten neighbours across three rows, for smoother results.
+    Sierra,
+}
+
+/// Error diffusion weights: offset across, offset down, and share of the error.
+const FLOYD_STEINBERG: &[(i32, usize, f32)] = &[
+    (1, 0, 7.0 / 16.0), (-1, 1, 3.0 / 16.0), (0, 1, 5.0 / 16.0), (1, 1, 1.0 / 16.0),
+];
+const ATKINSON: &[(i32, usize, f32)] = &[
+    (1, 0, 1.0 / 8.0), (2, 0, 1.0 / 8.0), (-1, 1, 1.0 / 8.0), (0, 1, 1.0 / 8.0), (1, 1, 1.0 / 8.0), (0, 2, 1.0 / 8.0),
+];
+const SIERRA: &[(i32, usize, f32)] = &[
+    (1, 0, 5.0 / 32.0), (2, 0, 3.0 / 32.0),
+    (-2, 1, 2.0 / 32.0), (-1, 1, 4.0 / 32.0), (0, 1, 5.0 / 32.0), (1, 1, 4.0 / 32.0), (2, 1, 2.0 / 32.0),
+    (-1, 2, 2.0 / 32.0), (0, 2, 3.0 / 32.0), (1, 2, 2.0 / 32.0),
+];
+
+/// Palette index for every pixel of `view`, row by row.
+pub(crate) fn indices<P: PixelType>(view: &StridedView<'_, P>, palette: &Palette, dither: Dither) -> Vec<u8> {
+    let rgb = |pixel: &[P::Channel]| {
+        let [r, g, b, _] = P::from_channels(pixel).to_rgba();
+        [r, g, b]
+    };
+    match dither {
+        Dither::None => per_pixel(view, |_, _, pixel| palette.nearest(rgb(pixel))),
+        Dither::Bayer { size } => {
+            let matrix = bayer(size.clamp(2, 16).next_power_of_two() as usize);
+            let spread = palette.spacing();
+            per_pixel(view, |x, y, pixel| {
+                let shift = (matrix[y % matrix.len()][x % matrix.len()] - 0.5) * spread;
+                palette.nearest(rgb(pixel).map(|v| v + shift))
+            })
+        },
+        Dither::FloydSteinberg => diffuse(view, palette, FLOYD_STEINBERG),
+        Dither::Atkinson => diffuse(view, palette, ATKINSON),
+        Dither::Sierra => diffuse(view, palette, SIERRA),
+    }
+}
+
+/// Indices picked for each pixel on its own, band by band.
+fn per_pixel<P: PixelType>(view: &StridedView<'_, P>, index: impl Fn(usize, usize, &[P::Channel]) -> usize + Sync)
+                           -> Vec<u8> {
+    let bands = parallel::map_bands(view.dimensions().height as usize, |rows| {
+        rows.flat_map(|y| {
+            view.row(y as u32).chunks(P::CHANNELS).enumerate().map(move |(x, pixel)| (x, y, pixel))
+        }).map(|(x, y, pixel)| index(x, y, pixel) as u8).collect::<Vec<u8>>()
+    });
+    bands.concat()
+}
+
+/// Thresholds (0.0-1.0) of a Bayer matrix of side `size`, a power of two.
+fn bayer(size: usize) -> Vec<Vec<f32>> {
+    let mut matrix = vec![vec![0usize]];
+    while matrix.len() < size {
+        let n = matrix.len();
+        let mut next = vec![vec![0; 2 * n]; 2 * n];
+        for (y, row) in next.iter_mut().enumerate() {
+            for (x, cell) in row.iter_mut().enumerate() {
+                let quadrant = [[0, 2], [3, 1]][y / n][x / n];
+                *cell = 4 * matrix[y % n][x % n] + quadrant;
+            }
+        }
+        matrix = next;
+    }
+    let cells = (size * size) as f32;
+    matrix.iter().map(|row| row.iter().map(|&v| (v as f32 + 0.5) / cells).collect()).collect()
+}
+
+/// Indices picked left to right and top to bottom, each pixel's error from
+/// its colour passed on to the pixels after it by `weights`.
+fn diffuse<P: PixelType>(view: &StridedView<'_, P>, palette: &Palette, weights: &[(i32, usize, f32)]) -> Vec<u8> {
+    let Dimensions { width, height } = *view.dimensions();
+    let (width, height) = (width as usize, height as usize);
+    // errors waiting for this row and the two below, with room either side
+    let pad = 2;
+    let mut errors = vec![vec![[0.0f32; 3]; width + 2 * pad]; 3];
+    let mut indices = Vec::with_capacity(width * height);
+
+    for y in 0..height {
+        for (x, pixel) in view.row(y as u32).chunks(P::CHANNELS).enumerate() {
+            let [r, g, b, _] = P::from_channels(pixel).to_rgba();
+            let error = errors[0][x + pad];
+            let wanted = [r + error[0], g + error[1], b + error[2]].map(|v| v.clamp(0.0, 1.0));
+            let index = palette.nearest(wanted);
+            indices.push(index as u8);
+
+            let got = palette.units[index];
+            for &(dx, dy, share) in weights {
+                let target = &mut errors[dy][(x as i32 + pad as i32 + dx) as usize];
+                for c in 0..3 {
+                    target[c] += (wanted[c] - got[c]) * share;
+                }
+            }
+        }
+        errors.rotate_left(1);
+        errors[2].iter_mut().for_each(|error| *error = [0.0; 3]);
+    }
+    indices
+}
+
+/*************************************** Indexed Images ***********************************************/
+/******************************************************************************************************/
+
+/// An image stored as one palette index per pixel, as GIF and 8-bit PNG
+/// keep them. Save it with `utils::save_indexed`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct IndexedImage {
+    dims: Dimensions,
+    indices: Vec<u8>,
+    palette: Palette,
+}
+
+impl IndexedImage {
+
+    /// An image of `indices` into `palette`, row by row. Fails if there aren't
+    /// as many indices as pixels, or one lies past the end of the palette.
+    pub fn new(dims: Dimensions, indices: Vec<u8>, palette: Palette) -> Result<IndexedImage, ImageError> {
+        let expected = dims.width as usize * dims.height as usize;
+        if indices.len() != expected {
+            return BufferSize { width: dims.width, height: dims.height, expected, actual: indices.len() }.fail();
+        }
+        if let Some(&index) = indices.iter().find(|&&index| index as usize >= palette.colours.len()) {
+            return PaletteIndex { index, count: palette.colours.len() }.fail();
+        }
+        Ok(IndexedImage { dims, indices, palette })
+    }
+
+    pub fn dimensions(&self) -> &Dimensions {
+        &self.dims
+    }
+
+    /// Palette indices, row by row.
+    pub fn indices(&self) -> &[u8] {
+        &self.indices
+    }
+
+    pub fn palette(&self) -> &Palette {
+        &self.palette
+    }
+
+    /// Palette index of the pixel at (`x`, `y`).
+    pub fn get_index(&self, x: u32, y: u32) -> Result<u8, ImageError> {
+        check_bounds(x, y, &self.dims)?;
+        Ok(self.indices[y as usize * self.dims.width as usize + x as usize])
+    }
+
+    /// The palette colours, opaque, as layout `Q`.
+    pub fn to_image<Q: PixelType>(&self) -> OwnedImage<Q> {
+        let mut channels = vec![Q::Channel::default(); self.indices.len() * Q::CHANNELS];
+        for (pixel, &index) in channels.chunks_mut(Q::CHANNELS).zip(&self.indices) {
+            let Rgb { r, g, b } = self.palette.colours[index as usize];
+            Q::from_rgba([r.to_unit(), g.to_unit(), b.to_unit(), 1.0]).write_channels(pixel);
+        }
+        OwnedImage::from_raw(self.dims, channels.into_boxed_slice())
+    }
+}
+
+/// Indexes `image` against `palette`.
+pub(crate) fn to_indexed<I: Image + ?Sized>(image: &I, palette: &Palette, dither: Dither) -> IndexedImage {
+    let indices = indices(&image.view(), palette, dither);
+    IndexedImage { dims: *image.dimensions(), indices, palette: palette.clone() }
+}
+
+/// Replaces every pixel of `view` with its palette colour, keeping alpha.
+pub(crate) fn quantize<P: PixelType>(view: &mut StridedViewMut<'_, P>, palette: &Palette, dither: Dither) {
+    let indices = indices(&view.as_view(), palette, dither);
+    let width = view.dimensions().width as usize;
+    view.for_each_row(|y, row| {
+        let row_indices = &indices[y as usize * width..][..width];
+        for (pixel, &index) in row.chunks_mut(P::CHANNELS).zip(row_indices) {
+            let [_, _, _, alpha] = P::from_channels(pixel).to_rgba();
+            let [r, g, b] = palette.units[index as usize];
+            P::from_rgba([r, g, b, alpha]).write_channels(pixel);
+        }
+    });
+}
@@ -0,0 +1,241 @@
+use super::OwnedImage::*;
+use super::utils::*;
+use super::pixel::*;
+use super::parallel;
+use super::view::StridedView;
+use std::f32::consts::PI;
+
+/************************************** Resampling Filters ********************************************/
+/******************************************************************************************************/
+
+/// The reconstruction filter used when resizing.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Filter {
+    /// Box filter: nearest pixel when enlarging, plain area average when shrinking.
+    Nearest,
+    /// Triangle filter.
+    Bilinear,
+    /// Cubic with B = 0, C = 0.5. Sharp, with slight ringing.
+    CatmullRom,
+    /// Cubic with B = C = 1/3. Softer than Catmull-Rom, with almost no ringing.
+    Mitchell,
+    /// Windowed sinc with three lobes. Sharpest, at the cost of some ringing.
+    Lanczos3,
+}
+
+impl Filter {
+    /// Radius of the filter, in source pixels, when not shrinking.
+    fn support(&self) -> f32 {
+        match self {
+            Filter::Nearest => 0.5,
+            Filter::Bilinear => 1.0,
+            Filter::CatmullRom | Filter::Mitchell => 2.0,
+            Filter::Lanczos3 => 3.0,
+        }
+    }
+
+    fn weight(&self, x: f32) -> f32 {
+        let x = x.abs();
+        match self {
+            Filter::Nearest => if x <= 0.5 { 1.0 } else { 0.0 },
+            Filter::Bilinear => (1.0 - x).max(0.0),
+            Filter::CatmullRom => cubic(x, 0.0, 0.5),
+            Filter::Mitchell => cubic(x, 1.0 / 3.0, 1.0 / 3.0),
+            Filter::Lanczos3 => if x < 3.0 { sinc(x) * sinc(x / 3.0) } else { 0.0 },
+        }
+    }
+}
+
+/// Mitchell-Netravali cubic family.
+fn cubic(x: f32, b: f32, c: f32) -> f32 {
+    let x2 = x * x;
+    let x3 = x2 * x;
+    if x < 1.0 {
+        ((12.0 - 9.0 * b - 6.0 * c) * x3 + (-18.0 + 12.0 * b + 6.0 * c) * x2 + (6.0 - 2.0 * b)) / 6.0
+    } else if x < 2.0 {
+        ((-b - 6.0 * c) * x3 + (6.0 * b + 30.0 * c) * x2 + (-12.0 * b - 48.0 * c) * x + (8.0 * b + 24.0 * c)) / 6.0
+    } else {
+        0.0
+    }
+}
+
+fn sinc(x: f32) -> f32 {
+    if x == 0.0 {
+        1.0
+    } else {
+        (PI * x).sin() / (PI * x)
+    }
+}
+
+/*************************************** Sizing Modes *************************************************/
+/******************************************************************************************************/
+
+/// Where to keep the image when `Sizing::Cover` crops away the overflow.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Anchor {
+    TopLeft,
+    Top,
+    TopRight,
+    Left,
+    Centre,
+    Right,
+    BottomLeft,
+    Bottom,
+    BottomRight,
+}
+
+impl Anchor {
+    /// Horizontal and vertical position as fractions of the overflow to crop before the image.
+    fn fractions(&self) -> (f32, f32) {
+        match self {
+            Anchor::TopLeft => (0.0, 0.0),
+            Anchor::Top => (0.5, 0.0),
+            Anchor::TopRight => (1.0, 0.0),
+            Anchor::Left => (0.0, 0.5),
+            Anchor::Centre => (0.5, 0.5),
+            Anchor::Right => (1.0, 0.5),
+            Anchor::BottomLeft => (0.0, 1.0),
+            Anchor::Bottom => (0.5, 1.0),
+            Anchor::BottomRight => (1.0, 1.0),
+        }
+    }
+}
+
+/// How the target dimensions of a resize are interpreted.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Sizing {
+    /// Stretches to exactly the target size, ignoring aspect ratio.
+    Exact,
+    /// Keeps aspect ratio; the largest size that fits inside the target.
+    Fit,
+    /// Keeps aspect ratio; the smallest size that covers the target. One side may overflow it.
+    Fill,
+    /// Keeps aspect ratio, covers the target and crops the overflow, keeping the anchored part.
+    Cover(Anchor),
+}
+
+/************************************** Resize Implementation *****************************************/
+/******************************************************************************************************/
+
+/// Resizes to `target`, interpreted according to `sizing`.
+pub(crate) fn resize_to<P: PixelType>(view: &StridedView<'_, P>, target: Dimensions, filter: Filter,
+                                      sizing: Sizing) -> OwnedImage<P> {
+    let dims = *view.dimensions();
+    let scale_x = target.width as f32 / dims.width.max(1) as f32;
+    let scale_y = target.height as f32 / dims.height.max(1) as f32;
+    let scaled = |scale: f32| Dimensions {
+        width: ((dims.width as f32 * scale).round() as u32).max(1),
+        height: ((dims.height as f32 * scale).round() as u32).max(1),
+    };
+
+    match sizing {
+        Sizing::Exact => resize(view, target, filter),
+        Sizing::Fit => resize(view, scaled(scale_x.min(scale_y)), filter),
+        Sizing::Fill => resize(view, scaled(scale_x.max(scale_y)), filter),
+        Sizing::Cover(anchor) => {
+            let mut filled = scaled(scale_x.max(scale_y));
+            filled.width = filled.width.max(target.width);
+            filled.height = filled.height.max(target.height);
+            let full = resize(view, filled, filter);
+
+            let (fx, fy) = anchor.fractions();
+            let x = ((filled.width - target.width) as f32 * fx).round() as usize;
+            let y = ((filled.height - target.height) as f32 * fy).round() as usize;
+            let c = P::CHANNELS;
+            let row = target.width as usize * c;
+
+            let mut out = Vec::with_capacity(row * target.height as usize);
+            for j in y..y + target.height as usize {
+                let start = (j * filled.width as usize + x) * c;
+                out.extend_from_slice(&full.get_pixels()[start..start + row]);
+            }
+            OwnedImage::from_raw(target, out.into_boxed_slice())
+        }
+    }
+}
+
+/// Resizes to exactly `target` with a separable two-pass filter. When
+/// shrinking, the filter is widened by the scale factor so every source pixel
+/// contributes, which averages the area instead of aliasing.
+pub(crate) fn resize<P: PixelType>(view: &StridedView<'_, P>, target: Dimensions, filter: Filter) -> OwnedImage<P> {
+    let dims = *view.dimensions();
+    // smoothing filters like Mitchell would still soften an unscaled image
+    if target == dims {
+        return OwnedImage::from_view(view);
+    }
+
+    let c = P::CHANNELS;
+    let (w, h) = (dims.width as usize, dims.height as usize);
+    let (new_w, new_h) = (target.width as usize, target.height as usize);
+
+    // horizontal pass into a float buffer, so rounding only happens once
+    let columns = weights(w, new_w, filter);
+    let mut horiz = vec![0.0f32; new_w * h * c];
+    parallel::for_each_band(&mut horiz, new_w * c, |first_row, band| {
+        for (y, dst_row) in (first_row..).zip(band.chunks_mut(new_w * c)) {
+            let src_row = view.row(y as u32);
+            for (dst, (first, taps)) in dst_row.chunks_mut(c).zip(columns.iter()) {
+                for (k, weight) in taps.iter().enumerate() {
+                    let src = (first + k) * c;
+                    for (value, channel) in dst.iter_mut().zip(&src_row[src..src + c]) {
+                        *value += channel.to_f32() * weight;
+                    }
+                }
+            }
+        }
+    });
+
+    // vertical pass into the output
+    let rows = weights(h, new_h, filter);
+    let row_len = new_w * c;
+    let mut out = vec![P::Channel::default(); row_len * new_h];
+    parallel::for_each_band(&mut out, row_len, |first_row, band| {
+        let mut totals = vec![0.0f32; row_len];
+        for (dst_row, (first, taps)) in band.chunks_mut(row_len).zip(rows[first_row..].iter()) {
+            totals.iter_mut().for_each(|total| *total = 0.0);
+            for (k, weight) in taps.iter().enumerate() {
+                let src_row = &horiz[(first + k) * row_len..(first + k + 1) * row_len];
+                for (total, value) in totals.iter_mut().zip(src_row) {
+                    *total += value * weight;
+                }
+            }
+            for (dst, total) in dst_row.iter_mut().zip(&totals) {
+                *dst = P::Channel::from_f32(*total);
+            }
+        }
+    });
+
+    OwnedImage::from_raw(target, out.into_boxed_slice())
+}
+
+/// For each output position, the first contributing source position and the
+/// normalised weights of the source positions from there on.
+fn weights(src_len: usize, dst_len: usize, filter: Filter) -> Vec<(usize, Vec<f32>)> {
+    if src_len == 0 {
+        return vec![(0, Vec::new()); dst_len];
+    }
+
+    let scale = src_len as f32 / dst_len.max(1) as f32;
+    let filter_scale = scale.max(1.0);
+    let support = filter.support() * filter_scale;
+
+    (0..dst_len).map(|i| {
+        let centre = (i as f32 + 0.5) * scale;
+        let first = ((centre - support).floor().max(0.0) as usize).min(src_len - 1);
+        let last = ((centre + support).ceil() as usize).clamp(first + 1, src_len);
+
+        let mut taps: Vec<f32> = (first..last)
+            .map(|j| filter.weight((j as f32 + 0.5 - centre) / filter_scale))
+            .collect();
+        let sum: f32 = taps.iter().sum();
+        if sum != 0.0 {
+            taps.iter_mut().for_each(|weight| *weight /= sum);
+        } else {
+            // the filter missed every pixel (tiny box filters can); take the closest
+            let nearest = ((centre - 0.5).round().max(0.0) as usize).clamp(first, last - 1);
+            taps.iter_mut().for_each(|weight| *weight = 0.0);
+            taps[nearest - first] = 1.0;
+        }
+        (first, taps)
+    }).collect()
+}
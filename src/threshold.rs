@@ -0,0 +1,135 @@
+use super::OwnedImage::*;
+use super::traits::*;
+use super::pixel::*;
+use super::colour::GreyscaleMethod;
+use super::histogram::{Bins, BINS};
+use super::kernel::EdgeMode;
+use super::blur;
+
+/*************************************** Thresholding *************************************************/
+/******************************************************************************************************/
+
+/// A binary mask: 255 where a pixel is set and 0 elsewhere. It is a plain
+/// one-channel image, so it can be cropped, blurred, saved or combined with
+/// others through `zip_map` like any other.
+pub type Mask = OwnedImage<Luma8>;
+
+/// How to pick the level each pixel's Rec.709 luma (0.0-1.0) is compared
+/// against. Pixels brighter than their level are set, so dark text on a light
+/// page comes out black on white.
+///
+/// The adaptive methods read their windows past the image's edges by `edge`.
+/// `EdgeMode::Skip` only counts the pixels inside; `EdgeMode::Crop` would
+/// shrink the levels, so it is read as `Skip` too.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Threshold {
+    /// The same level everywhere.
+    Fixed(f32),
+    /// The level that best splits the luma histogram into a dark and a light
+    /// class, by Otsu's method. See `otsu_level`.
+    Otsu,
+    /// The mean luma of the `2 * radius + 1` square window round each pixel,
+    /// less `offset`. Copes with uneven lighting.
+    AdaptiveMean { radius: u32, offset: f32, edge: EdgeMode<Luma32F> },
+    /// The mean luma round each pixel weighted by a Gaussian of `sigma`, less
+    /// `offset`.
+    AdaptiveGaussian { sigma: f32, offset: f32, edge: EdgeMode<Luma32F> },
+    /// Niblack's method: the window's mean plus `k` times its standard
+    /// deviation. A `k` around -0.2 suits dark text.
+    Niblack { radius: u32, k: f32, edge: EdgeMode<Luma32F> },
+    /// Sauvola's method: the window's mean scaled by `1 + k * (deviation / 0.5 - 1)`,
+    /// which keeps flat backgrounds clean where Niblack turns them noisy.
+    /// A `k` of 0.2 to 0.5 suits documents.
+    Sauvola { radius: u32, k: f32, edge: EdgeMode<Luma32F> },
+}
+
+/// The last bin of the darker class when `bins` are split in two by Otsu's
+/// method, which maximises the variance between the classes. 0 for bins
+/// holding fewer than two distinct values.
+pub fn otsu_level(bins: &Bins) -> usize {
+    let total: f64 = bins.iter().map(|&count| count as f64).sum();
+    let sum_all: f64 = bins.iter().enumerate().map(|(i, &count)| i as f64 * count as f64).sum();
+
+    let (mut weight, mut sum) = (0.0, 0.0);
+    let (mut best, mut best_variance) = (0, 0.0);
+    for (i, &count) in bins.iter().enumerate() {
+        weight += count as f64;
+        sum += i as f64 * count as f64;
+        let rest = total - weight;
+        if weight == 0.0 || rest == 0.0 {
+            continue;
+        }
+        let (dark, light) = (sum / weight, (sum_all - sum) / rest);
+        let variance = weight * rest * (dark - light) * (dark - light);
+        if variance > best_variance {
+            best = i;
+            best_variance = variance;
+        }
+    }
+    best
+}
+
+/// Binarizes `image` by `method`.
+pub(crate) fn threshold<I: Image + ?Sized>(image: &I, method: Threshold) -> Mask {
+    let luma: OwnedImage<Luma32F> = image.luma(GreyscaleMethod::Rec709);
+
+    let levels = match method {
+        Threshold::Fixed(level) => return mask(&luma, level),
+        Threshold::Otsu => {
+            // halfway to the next bin, so the whole darker class stays unset
+            let level = (otsu_level(&luma.histogram().luma) as f32 + 0.5) / (BINS - 1) as f32;
+            return mask(&luma, level);
+        },
+        Threshold::AdaptiveMean { radius, offset, edge } => {
+            let means = blur::box_blur(&luma.view(), &[radius as usize], &same_size(edge));
+            means.map_pixels(|Luma { luma }| Luma { luma: luma - offset })
+        },
+        Threshold::AdaptiveGaussian { sigma, offset, edge } => {
+            let means = luma.gaussian_blurred(sigma, sigma, same_size(edge));
+            means.map_pixels(|Luma { luma }| Luma { luma: luma - offset })
+        },
+        Threshold::Niblack { radius, k, edge } => {
+            local_statistics(&luma, radius, edge, |mean, deviation| mean + k * deviation)
+        },
+        Threshold::Sauvola { radius, k, edge } => {
+            local_statistics(&luma, radius, edge, |mean, deviation| mean * (1.0 + k * (deviation / 0.5 - 1.0)))
+        },
+    };
+    luma.zip_map(&levels, |Luma { luma }, Luma { luma: level }| Luma { luma: set(luma > level) })
+        .expect("levels are as large as the image")
+}
+
+/// Sets the pixels of `luma` brighter than `level`.
+fn mask(luma: &OwnedImage<Luma32F>, level: f32) -> Mask {
+    luma.map_pixels(|Luma { luma }| Luma { luma: set(luma > level) })
+}
+
+fn set(on: bool) -> u8 {
+    if on { u8::MAX } else { 0 }
+}
+
+/// `edge`, except that cropping (which would shrink the levels) skips instead.
+fn same_size(edge: EdgeMode<Luma32F>) -> EdgeMode<Luma32F> {
+    if edge == EdgeMode::Crop { EdgeMode::Skip } else { edge }
+}
+
+/// Levels from the mean and standard deviation of the square window of
+/// `radius` round each pixel, reading past the edges by `edge`.
+fn local_statistics(luma: &OwnedImage<Luma32F>, radius: u32, edge: EdgeMode<Luma32F>,
+                    level: impl Fn(f32, f32) -> f32 + Sync) -> OwnedImage<Luma32F> {
+    let radii = [radius as usize];
+    let edge = same_size(edge);
+    let means = blur::box_blur(&luma.view(), &radii, &edge);
+    let squares = luma.map_pixels(|Luma { luma }| Luma { luma: luma * luma });
+    // a constant colour outside squares too
+    let square_edge = match edge {
+        EdgeMode::Constant(Luma { luma }) => EdgeMode::Constant(Luma { luma: luma * luma }),
+        edge => edge,
+    };
+    let square_means = blur::box_blur(&squares.view(), &radii, &square_edge);
+
+    means.zip_map(&square_means, |Luma { luma: mean }, Luma { luma: square }| {
+        // rounding can leave the variance of a flat window just below zero
+        Luma { luma: level(mean, (square - mean * mean).max(0.0).sqrt()) }
+    }).expect("means are as large as the image")
+}
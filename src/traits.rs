@@ -1,59 +1,547 @@
 use super::OwnedImage::*;
 use super::ImageSlice::*;
 use super::utils::*;
+use super::pixel::*;
+use super::geometry::{self, Interpolation, Canvas};
+use super::resize::{self, Filter, Sizing};
+use super::kernel::{self, Kernel, EdgeMode};
+use super::blur;
+use super::adjust;
+use super::colour::{self, GreyscaleMethod};
+use super::linear::LinearImage;
+use super::histogram::{self, Histogram, Clahe};
+use super::threshold::{self, Threshold, Mask};
+use super::quantize::{self, Palette, Dither, IndexedImage};
+use super::parallel;
+use super::view::{StridedView, StridedViewMut, Rows, RowsMut, PixelsIter, PixelsMut};
 
-type Matrix<T> = Box<[T]>;
+/// The channel type of an image's pixels.
+pub type Channel<I> = <<I as Image>::Pixel as PixelType>::Channel;
 
 /// Immutable image operations.
 pub trait Image {
+    /// The layout of this image's pixels.
+    type Pixel: PixelType;
+
     /// This image's width and height.
     fn dimensions(&self) -> &Dimensions;
 
-    /// This image's pixels.
-    fn pixels(&self) -> Matrix<u8>;
+    /// Borrows this image's pixels in place.
+    fn view(&self) -> StridedView<'_, Self::Pixel>;
+
+    /// Borrows row `y` of this image. Panics if `y` is out of bounds.
+    fn row(&self, y: u32) -> &[Channel<Self>] {
+        self.view().row(y)
+    }
+
+    /// Iterates over this image's rows, borrowed in place.
+    fn rows(&self) -> Rows<'_, Self::Pixel> {
+        self.view().rows()
+    }
+
+    /// Iterates over this image's pixels row by row, as `(x, y, pixel)`.
+    fn pixels_iter(&self) -> PixelsIter<'_, Self::Pixel> {
+        self.view().pixels_iter()
+    }
+
+    /// Copies this image's pixels into a new, tightly packed buffer.
+    /// `view`, `rows` and `row` read them without copying.
+    fn pixels(&self) -> Matrix<Channel<Self>> {
+        self.view().to_vec().into_boxed_slice()
+    }
 
     /// Borrows a sub-image.
-    fn crop(&self, x: u32, y: u32, dims: Dimensions) -> ImageSlice;
+    /// Fails if (`x`, `y`) lies outside this image; the size is clamped to fit.
+    fn crop(&self, x: u32, y: u32, dims: Dimensions) -> Result<ImageSlice<'_, Self::Pixel>, ImageError>;
 
-    /// Returns a new image that is this one blurred.
-    fn blurred(&self, amount: u32) -> OwnedImage;
+    /// Returns a new image that is this one blurred, reading beyond the edges as `edge` says.
+    fn blurred(&self, amount: u32, edge: EdgeMode<Self::Pixel>) -> OwnedImage<Self::Pixel> {
+        blur::blurred(&self.view(), amount, &edge)
+    }
 
     /// Returns a new image that is this one flipped.
-    fn flipped(&self, horiz: bool, vert: bool) -> OwnedImage;
+    fn flipped(&self, horiz: bool, vert: bool) -> OwnedImage<Self::Pixel> {
+        geometry::flip(&self.view(), horiz, vert)
+    }
 
     /// Return s new image that is this one in grayscale
-    fn greyscale(&self) -> OwnedImage;
+    fn greyscale(&self) -> OwnedImage<Self::Pixel> {
+        self.greyscale_by(GreyscaleMethod::Rec601)
+    }
+
+    /// Returns a new image that is this one in greyscale, weighing the
+    /// channels by `method`. The layout is kept, so RGB stays RGB.
+    fn greyscale_by(&self, method: GreyscaleMethod) -> OwnedImage<Self::Pixel> {
+        self.map_pixels(|pixel| colour::grey_pixel(pixel, method))
+    }
+
+    /// Returns this image in greyscale as layout `Q`, usually a single-channel
+    /// one like `Luma8`. Alpha is kept if `Q` has room for it.
+    fn luma<Q: PixelType>(&self, method: GreyscaleMethod) -> OwnedImage<Q> {
+        self.map_pixels(|pixel| colour::grey_pixel(pixel, method))
+    }
+
+    /// Returns a new image that is this one rotated 90 degrees clockwise.
+    fn rotated90(&self) -> OwnedImage<Self::Pixel> {
+        geometry::rotate_quarters(&self.view(), 1)
+    }
+
+    /// Returns a new image that is this one rotated 180 degrees.
+    fn rotated180(&self) -> OwnedImage<Self::Pixel> {
+        geometry::rotate_quarters(&self.view(), 2)
+    }
+
+    /// Returns a new image that is this one rotated 270 degrees clockwise.
+    fn rotated270(&self) -> OwnedImage<Self::Pixel> {
+        geometry::rotate_quarters(&self.view(), 3)
+    }
+
+    /// Returns a new image that is this one rotated clockwise by `degrees`
+    /// around its centre. Areas not covered by the source are `background`.
+    fn rotated(&self, degrees: f32, interpolation: Interpolation, canvas: Canvas,
+               background: Self::Pixel) -> OwnedImage<Self::Pixel> {
+        geometry::rotate(&self.view(), degrees, interpolation, canvas, background)
+    }
+
+    /// Returns a new image that is this one resized to exactly `dims`.
+    fn resized(&self, dims: Dimensions, filter: Filter) -> OwnedImage<Self::Pixel> {
+        resize::resize(&self.view(), dims, filter)
+    }
+
+    /// Returns a new image that is this one resized towards `dims`, keeping or
+    /// ignoring the aspect ratio as `sizing` says.
+    fn resized_to(&self, dims: Dimensions, filter: Filter, sizing: Sizing) -> OwnedImage<Self::Pixel> {
+        resize::resize_to(&self.view(), dims, filter, sizing)
+    }
+
+    /// Returns a new image that is this one convolved with `kernel`, reading
+    /// beyond the edges as `edge` says.
+    fn convolved(&self, kernel: &Kernel, edge: EdgeMode<Self::Pixel>) -> OwnedImage<Self::Pixel> {
+        kernel::convolve(&self.view(), kernel, &edge)
+    }
+
+    /// Returns a new image blurred by a Gaussian with separate horizontal and
+    /// vertical sigmas, so one axis can be left sharp for streak effects.
+    /// Small sigmas use an exact kernel, large ones a fast approximation.
+    fn gaussian_blurred(&self, sigma_x: f32, sigma_y: f32, edge: EdgeMode<Self::Pixel>) -> OwnedImage<Self::Pixel> {
+        blur::gaussian_blur(&self.view(), sigma_x, sigma_y, &edge)
+    }
+
+    /// Returns a new image with `amount` added to every colour value, on a
+    /// 0.0-1.0 scale. -1.0 turns it black and 1.0 white; alpha is kept.
+    fn adjust_brightness(&self, amount: f32) -> OwnedImage<Self::Pixel> {
+        let mut image = OwnedImage::from_view(&self.view());
+        image.adjust_brightness_mut(amount);
+        image
+    }
+
+    /// Returns a new image with contrast scaled by `factor` around mid-grey:
+    /// 1.0 keeps it, 0.0 turns it flat grey.
+    fn adjust_contrast(&self, factor: f32) -> OwnedImage<Self::Pixel> {
+        let mut image = OwnedImage::from_view(&self.view());
+        image.adjust_contrast_mut(factor);
+        image
+    }
+
+    /// Returns a new image with gamma correction: gammas above 1.0 brighten
+    /// the midtones, below 1.0 darken them.
+    fn adjust_gamma(&self, gamma: f32) -> OwnedImage<Self::Pixel> {
+        let mut image = OwnedImage::from_view(&self.view());
+        image.adjust_gamma_mut(gamma);
+        image
+    }
+
+    /// Returns a new image exposed by `stops`: each stop doubles (or, when
+    /// negative, halves) every colour value.
+    fn adjust_exposure(&self, stops: f32) -> OwnedImage<Self::Pixel> {
+        let mut image = OwnedImage::from_view(&self.view());
+        image.adjust_exposure_mut(stops);
+        image
+    }
+
+    /// Returns a new image with every hue turned `degrees` round the colour wheel.
+    fn hue_rotate(&self, degrees: f32) -> OwnedImage<Self::Pixel> {
+        let mut image = OwnedImage::from_view(&self.view());
+        image.hue_rotate_mut(degrees);
+        image
+    }
+
+    /// Returns a new image with saturation scaled by `factor` (in HSL), capped
+    /// at full saturation. Lightness and alpha are kept.
+    fn saturate(&self, factor: f32) -> OwnedImage<Self::Pixel> {
+        let mut image = OwnedImage::from_view(&self.view());
+        image.saturate_mut(factor);
+        image
+    }
+
+    /// Returns a new image with `amount` of its saturation taken away:
+    /// 0.0 keeps it, 1.0 turns it grey.
+    fn desaturate(&self, amount: f32) -> OwnedImage<Self::Pixel> {
+        self.saturate(1.0 - amount)
+    }
+
+    /// Returns a new image with every pixel given `hue` (in degrees) and
+    /// `saturation`, keeping its lightness.
+    fn colorize(&self, hue: f32, saturation: f32) -> OwnedImage<Self::Pixel> {
+        let mut image = OwnedImage::from_view(&self.view());
+        image.colorize_mut(hue, saturation);
+        image
+    }
+
+    /// Counts how many pixels have each value, per channel and in luma.
+    fn histogram(&self) -> Histogram {
+        histogram::histogram(&self.view())
+    }
+
+    /// Returns a new image with its histogram equalized: luma values are
+    /// spread evenly over the whole range, through one curve shared by every
+    /// colour channel. Alpha is kept.
+    fn equalize(&self) -> OwnedImage<Self::Pixel> {
+        let mut image = OwnedImage::from_view(&self.view());
+        image.equalize_mut();
+        image
+    }
+
+    /// Returns a new image whose red, green and blue histograms take the shape
+    /// of `target`'s, such as `reference.histogram()` of another image.
+    fn match_histogram(&self, target: &Histogram) -> OwnedImage<Self::Pixel> {
+        let mut image = OwnedImage::from_view(&self.view());
+        image.match_histogram_mut(target);
+        image
+    }
+
+    /// Returns a new image with each colour channel stretched to the full
+    /// range, after clipping the `clip` fraction (e.g. 0.005) of its darkest
+    /// and brightest values.
+    fn auto_levels(&self, clip: f32) -> OwnedImage<Self::Pixel> {
+        let mut image = OwnedImage::from_view(&self.view());
+        image.auto_levels_mut(clip);
+        image
+    }
+
+    /// Returns a new image with contrast-limited adaptive histogram
+    /// equalization, which brings out detail in dark and bright areas alike.
+    /// See `Clahe` for the settings.
+    fn clahe(&self, settings: Clahe) -> OwnedImage<Self::Pixel> {
+        let mut image = OwnedImage::from_view(&self.view());
+        image.clahe_mut(settings);
+        image
+    }
+
+    /// Returns a binary mask of the pixels brighter than the level `method`
+    /// picks for them. See `Threshold`.
+    fn threshold(&self, method: Threshold) -> Mask {
+        threshold::threshold(self, method)
+    }
+
+    /// Returns this image reduced to `palette`, as one palette index per
+    /// pixel, dithered as `dither` says. Alpha is dropped.
+    fn to_indexed(&self, palette: &Palette, dither: Dither) -> IndexedImage {
+        quantize::to_indexed(self, palette, dither)
+    }
+
+    /// Returns a new image with every colour replaced by one from `palette`,
+    /// dithered as `dither` says. Alpha is kept.
+    fn quantize(&self, palette: &Palette, dither: Dither) -> OwnedImage<Self::Pixel> {
+        let mut image = OwnedImage::from_view(&self.view());
+        image.quantize_mut(palette, dither);
+        image
+    }
+
+    /// Decodes this image from sRGB into linear light, for operations that
+    /// should mix light rather than encoded values. See `LinearImage`.
+    fn to_linear(&self) -> LinearImage {
+        LinearImage::from_srgb(self)
+    }
+
+    /// Returns a new image that is this one converted to another pixel layout.
+    fn converted<Q: PixelType>(&self) -> OwnedImage<Q> {
+        self.map_pixels(|pixel| pixel.convert::<Q>())
+    }
+
+    /// Returns a new image with `f` applied to every pixel.
+    fn map_pixels<Q: PixelType>(&self, f: impl Fn(Self::Pixel) -> Q + Sync) -> OwnedImage<Q> {
+        let channels = <Self::Pixel as PixelType>::CHANNELS;
+        let source = self.view();
+        let row_len = self.dimensions().width as usize * Q::CHANNELS;
+        let mut pixels = vec![Q::Channel::default(); row_len * self.dimensions().height as usize];
+
+        parallel::for_each_band(&mut pixels, row_len, |first_row, band| {
+            for (y, row) in (first_row as u32..).zip(band.chunks_mut(row_len)) {
+                for (src, dst) in source.row(y).chunks(channels).zip(row.chunks_mut(Q::CHANNELS)) {
+                    f(Self::Pixel::from_channels(src)).write_channels(dst);
+                }
+            }
+        });
+
+        OwnedImage::from_raw(*self.dimensions(), pixels.into_boxed_slice())
+    }
+
+    /// Returns a new image with `f` applied to each pair of pixels at the same
+    /// place in this image and `other`. Fails if their sizes differ.
+    fn zip_map<I: Image, Q: PixelType>(&self, other: &I, f: impl Fn(Self::Pixel, I::Pixel) -> Q + Sync)
+        -> Result<OwnedImage<Q>, ImageError>
+    {
+        let (dims, other_dims) = (*self.dimensions(), *other.dimensions());
+        if dims != other_dims {
+            return SizeMismatch {
+                width: dims.width,
+                height: dims.height,
+                other_width: other_dims.width,
+                other_height: other_dims.height,
+            }.fail();
+        }
+
+        let (source, other) = (self.view(), other.view());
+        let other_channels = <I::Pixel as PixelType>::CHANNELS;
+        let row_len = dims.width as usize * Q::CHANNELS;
+        let mut pixels = vec![Q::Channel::default(); row_len * dims.height as usize];
+
+        parallel::for_each_band(&mut pixels, row_len, |first_row, band| {
+            for (y, row) in (first_row as u32..).zip(band.chunks_mut(row_len)) {
+                let pairs = source.row(y).chunks(Self::Pixel::CHANNELS).zip(other.row(y).chunks(other_channels));
+                for ((a, b), dst) in pairs.zip(row.chunks_mut(Q::CHANNELS)) {
+                    f(Self::Pixel::from_channels(a), I::Pixel::from_channels(b)).write_channels(dst);
+                }
+            }
+        });
+
+        Ok(OwnedImage::from_raw(dims, pixels.into_boxed_slice()))
+    }
 }
 
 pub trait ImageMut: Image {
     /// Mutable borrows a sub-image.
-    fn crop_mut(&mut self, x: u32, y: u32, dims: Dimensions) -> ImageSliceMut;
+    /// Fails if (`x`, `y`) lies outside this image; the size is clamped to fit.
+    fn crop_mut(&mut self, x: u32, y: u32, dims: Dimensions)
+        -> Result<ImageSliceMut<'_, Self::Pixel>, ImageError>;
+
+    /// Mutably borrows this image's pixels in place.
+    fn view_mut(&mut self) -> StridedViewMut<'_, Self::Pixel>;
+
+    /// Iterates mutably over this image's rows, borrowed in place.
+    fn rows_mut(&mut self) -> RowsMut<'_, Self::Pixel> {
+        self.view_mut().into_rows_mut()
+    }
 
-    /// Blurs this image in place.
-    fn blur(&mut self, amount: u32);
+    /// Iterates mutably over this image's pixels row by row, as
+    /// `(x, y, channels)`. Read and write a pixel with `PixelType::from_channels`
+    /// and `write_channels`.
+    fn pixels_mut(&mut self) -> PixelsMut<'_, Self::Pixel> {
+        self.view_mut().into_pixels_mut()
+    }
+
+    /// Applies `f` to every pixel in place.
+    fn map_pixels_in_place(&mut self, f: impl Fn(Self::Pixel) -> Self::Pixel + Sync) {
+        self.view_mut().for_each_row(|_, row| {
+            for chunk in row.chunks_mut(Self::Pixel::CHANNELS) {
+                f(Self::Pixel::from_channels(chunk)).write_channels(chunk);
+            }
+        });
+    }
+
+    /// Splits into the rows above `y` and the rest, which can be edited at the
+    /// same time (on different threads, say). `y` is clamped to the height.
+    fn split_at_row_mut(&mut self, y: u32) -> (ImageSliceMut<'_, Self::Pixel>, ImageSliceMut<'_, Self::Pixel>) {
+        self.whole_mut().split_at_row(y)
+    }
+
+    /// Splits into the columns left of `x` and the rest, which can be edited
+    /// at the same time. `x` is clamped to the width.
+    fn split_at_col_mut(&mut self, x: u32) -> (ImageSliceMut<'_, Self::Pixel>, ImageSliceMut<'_, Self::Pixel>) {
+        self.whole_mut().split_at_col(x)
+    }
+
+    /// Cuts into tiles of `tile_w` x `tile_h`, row by row, which can be edited
+    /// at the same time. Tiles along the right and bottom edges may be smaller.
+    /// Panics if either tile size is zero.
+    fn tiles_mut(&mut self, tile_w: u32, tile_h: u32) -> Vec<ImageSliceMut<'_, Self::Pixel>> {
+        self.whole_mut().into_tiles(tile_w, tile_h)
+    }
+
+    /// Mutably borrows the whole image as a slice.
+    fn whole_mut(&mut self) -> ImageSliceMut<'_, Self::Pixel> {
+        let dims = *self.dimensions();
+        self.crop_mut(0, 0, dims).expect("(0, 0) lies in every image")
+    }
+
+    /// Blurs this image in place, reading beyond the edges as `edge` says.
+    /// `EdgeMode::Crop` shrinks owned images; slices can't shrink, so they
+    /// fail with `DimensionMismatch` and are left as they were.
+    fn blur(&mut self, amount: u32, edge: EdgeMode<Self::Pixel>) -> Result<(), ImageError> {
+        let blurred = self.blurred(amount, edge);
+        self.copy_from(&blurred)
+    }
 
     /// Flips this image in place.
-    fn flip(&mut self, horiz: bool, vert: bool);
+    fn flip(&mut self, horiz: bool, vert: bool) {
+        let mut view = self.view_mut();
+        if horiz {
+            view.for_each_row(|_, row| {
+                row.reverse();
+                // reversing the row also reversed each pixel's channels
+                for pixel in row.chunks_mut(Self::Pixel::CHANNELS) {
+                    pixel.reverse();
+                }
+            });
+        }
+        if vert {
+            view.swap_rows_vertically();
+        }
+    }
 
     /// Turn this image into greyscale in place.
-    fn greyscale_mut(&mut self);
+    fn greyscale_mut(&mut self) {
+        self.greyscale_by_mut(GreyscaleMethod::Rec601);
+    }
+
+    /// Turns this image into greyscale in place. See `Image::greyscale_by`.
+    fn greyscale_by_mut(&mut self, method: GreyscaleMethod) {
+        self.map_pixels_in_place(|pixel| colour::grey_pixel(pixel, method));
+    }
+
+    /// Adds `amount` to every colour value in place. See `Image::adjust_brightness`.
+    fn adjust_brightness_mut(&mut self, amount: f32) {
+        adjust::apply_curve(&mut self.view_mut(), adjust::brightness(amount));
+    }
+
+    /// Scales contrast around mid-grey in place. See `Image::adjust_contrast`.
+    fn adjust_contrast_mut(&mut self, factor: f32) {
+        adjust::apply_curve(&mut self.view_mut(), adjust::contrast(factor));
+    }
+
+    /// Gamma-corrects this image in place. See `Image::adjust_gamma`.
+    fn adjust_gamma_mut(&mut self, gamma: f32) {
+        adjust::apply_curve(&mut self.view_mut(), adjust::gamma(gamma));
+    }
+
+    /// Changes exposure by `stops` in place. See `Image::adjust_exposure`.
+    fn adjust_exposure_mut(&mut self, stops: f32) {
+        adjust::apply_curve(&mut self.view_mut(), adjust::exposure(stops));
+    }
+
+    /// Turns every hue in place. See `Image::hue_rotate`.
+    fn hue_rotate_mut(&mut self, degrees: f32) {
+        colour::apply_hsl(&mut self.view_mut(), colour::hue_rotate(degrees));
+    }
+
+    /// Scales saturation in place. See `Image::saturate`.
+    fn saturate_mut(&mut self, factor: f32) {
+        colour::apply_hsl(&mut self.view_mut(), colour::saturate(factor));
+    }
+
+    /// Takes saturation away in place. See `Image::desaturate`.
+    fn desaturate_mut(&mut self, amount: f32) {
+        self.saturate_mut(1.0 - amount);
+    }
+
+    /// Tints this image in place. See `Image::colorize`.
+    fn colorize_mut(&mut self, hue: f32, saturation: f32) {
+        colour::apply_hsl(&mut self.view_mut(), colour::colorize(hue, saturation));
+    }
+
+    /// Equalizes the histogram in place. See `Image::equalize`.
+    fn equalize_mut(&mut self) {
+        let curves = histogram::equalize_curve(&self.histogram());
+        histogram::apply_curves(&mut self.view_mut(), &curves);
+    }
+
+    /// Matches `target`'s histogram in place. See `Image::match_histogram`.
+    fn match_histogram_mut(&mut self, target: &Histogram) {
+        let curves = histogram::match_curve(&self.histogram(), target);
+        histogram::apply_curves(&mut self.view_mut(), &curves);
+    }
+
+    /// Stretches every colour channel in place. See `Image::auto_levels`.
+    fn auto_levels_mut(&mut self, clip: f32) {
+        let curves = histogram::levels_curve(&self.histogram(), clip);
+        histogram::apply_curves(&mut self.view_mut(), &curves);
+    }
+
+    /// Applies CLAHE in place. See `Image::clahe`.
+    fn clahe_mut(&mut self, settings: Clahe) {
+        histogram::clahe(&mut self.view_mut(), settings);
+    }
+
+    /// Reduces this image to `palette` in place. See `Image::quantize`.
+    fn quantize_mut(&mut self, palette: &Palette, dither: Dither) {
+        quantize::quantize(&mut self.view_mut(), palette, dither);
+    }
+
+    /// Runs `op` on this image in linear light: decodes it from sRGB, lets
+    /// `op` edit it, then encodes the result back in place. Blurs done this
+    /// way keep edges between colours from darkening.
+    /// Fails if `op` does, or if it changes the size of a slice, which can't follow.
+    fn in_linear_light(&mut self, op: impl FnOnce(&mut LinearImage) -> Result<(), ImageError>)
+                       -> Result<(), ImageError> {
+        let mut linear = LinearImage::from_srgb(self);
+        op(&mut linear)?;
+        let encoded = linear.to_srgb::<Self::Pixel>();
+        self.copy_from(&encoded)
+    }
+
+    /// Rotates this image 90 degrees clockwise in place, swapping width and height.
+    /// Slices can't change shape, so only square ones can be rotated; others
+    /// fail with `DimensionMismatch` and are left as they were.
+    fn rotate90(&mut self) -> Result<(), ImageError> {
+        let rotated = self.rotated90();
+        self.copy_from(&rotated)
+    }
+
+    /// Rotates this image 180 degrees in place.
+    fn rotate180(&mut self) {
+        self.flip(true, true);
+    }
+
+    /// Rotates this image 270 degrees clockwise in place, swapping width and height.
+    /// Only square slices can be rotated, as with `rotate90`.
+    fn rotate270(&mut self) -> Result<(), ImageError> {
+        let rotated = self.rotated270();
+        self.copy_from(&rotated)
+    }
+
+    /// Rotates this image clockwise by `degrees` in place. See `Image::rotated`.
+    /// Slices keep their size, so they fail with `DimensionMismatch` whenever
+    /// the rotated image wouldn't fit exactly, as with `Canvas::Expand`.
+    fn rotate(&mut self, degrees: f32, interpolation: Interpolation, canvas: Canvas, background: Self::Pixel)
+              -> Result<(), ImageError> {
+        let rotated = self.rotated(degrees, interpolation, canvas, background);
+        self.copy_from(&rotated)
+    }
+
+    /// Convolves this image with `kernel` in place. See `Image::convolved`.
+    /// As with `blur`, slices fail with `EdgeMode::Crop`.
+    fn convolve(&mut self, kernel: &Kernel, edge: EdgeMode<Self::Pixel>) -> Result<(), ImageError> {
+        let convolved = self.convolved(kernel, edge);
+        self.copy_from(&convolved)
+    }
+
+    /// Gaussian-blurs this image in place. See `Image::gaussian_blurred`.
+    /// As with `blur`, slices fail with `EdgeMode::Crop`.
+    fn gaussian_blur(&mut self, sigma_x: f32, sigma_y: f32, edge: EdgeMode<Self::Pixel>) -> Result<(), ImageError> {
+        let blurred = self.gaussian_blurred(sigma_x, sigma_y, edge);
+        self.copy_from(&blurred)
+    }
 
     /// Blurs this image into another, existing image.
     /// (You can probably override this with something faster.)
-    fn blur_from(&mut self, amount: u32, source: impl Image) {
-        self.copy_from(&source);
-        self.blur(amount);
+    fn blur_from(&mut self, amount: u32, edge: EdgeMode<Self::Pixel>, source: impl Image<Pixel = Self::Pixel>)
+                 -> Result<(), ImageError> {
+        self.copy_from(&source)?;
+        self.blur(amount, edge)
     }
 
     /// Flips this image into another, existing image.
     /// (You can probably override this with something faster.)
-    fn flip_from(&mut self, horiz: bool, vert: bool, source: impl Image) {
-
-        self.copy_from(&source);
+    fn flip_from(&mut self, horiz: bool, vert: bool, source: impl Image<Pixel = Self::Pixel>)
+                 -> Result<(), ImageError> {
+        self.copy_from(&source)?;
         self.flip(horiz, vert);
+        Ok(())
     }
 
-    /// Copies into this image from another, existing image.
-    /// Dimensions don't need to match, and follow those of the new one
-    fn copy_from(&mut self, source: &impl Image);
-}
\ No newline at end of file
+    /// Copies into this image from another, existing image. Owned images take
+    /// on the source's dimensions; slices can't change size, so they fail with
+    /// `DimensionMismatch` unless the source matches theirs, and are left as they were.
+    fn copy_from(&mut self, source: &impl Image<Pixel = Self::Pixel>) -> Result<(), ImageError>;
+}
@@ -1,64 +1,437 @@
 use super::OwnedImage::*;
 use super::traits::*;
-use snafu::{Snafu};
+use super::pixel::*;
+use super::quantize::IndexedImage;
+use snafu::{Snafu, ResultExt};
 use image as rust_image;
+use image::{ColorType, DynamicImage, ImageReader};
+use image::codecs::jpeg::JpegEncoder;
+use std::fs::File;
+use std::io::{BufWriter, Cursor, Read, Seek, Write};
+use std::path::{Path, PathBuf};
+use std::cmp;
+use std::convert::TryFrom;
+
+pub use image::ImageFormat;
 
 /************************************ Shared Struct and Functions *************************************/
 /******************************************************************************************************/
 
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub struct Dimensions {
     pub width: u32,
     pub height: u32,
 }
 
-#[derive(Copy, Clone)]
-pub struct Pixel {
-    pub r: u8,
-    pub g: u8,
-    pub b: u8,
-    pub alpha: u8
-}
+/// The default pixel type: 8-bit RGBA.
+pub type Pixel = Rgba8;
 
+/// Where a slice's pixels lie in the buffer it borrows. Positions count
+/// channel values, not pixels.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub struct SliceSpec {
-    pub offset: (u32, u32),     // x and y offsets
-    pub size: (u32, u32),       // this is the dimension of original owned image
+    pub origin: usize,          // index of the top-left pixel's first channel
+    pub stride: usize,          // distance from the start of one row to the next
     pub dims: Dimensions,       // this is the dimension of the slice
 }
 
+impl SliceSpec {
+
+    /// The whole of a tightly packed image with `channels` values per pixel.
+    pub fn whole(dims: Dimensions, channels: usize) -> SliceSpec {
+        SliceSpec { origin: 0, stride: dims.width as usize * channels, dims }
+    }
+
+    /// The part of this slice starting at (`x`, `y`), relative to its top-left corner.
+    /// Fails if (`x`, `y`) lies outside this slice; the size is clamped to fit.
+    pub fn crop(&self, x: u32, y: u32, dims: Dimensions, channels: usize) -> Result<SliceSpec, ImageError> {
+        check_crop(x, y, &self.dims)?;
+        Ok(SliceSpec {
+            origin: self.index(x, y, channels),
+            stride: self.stride,
+            dims: Dimensions {
+                width: cmp::min(dims.width, self.dims.width - x),
+                height: cmp::min(dims.height, self.dims.height - y),
+            },
+        })
+    }
+
+    /// Buffer index of the first channel of the pixel at (`x`, `y`).
+    pub fn index(&self, x: u32, y: u32, channels: usize) -> usize {
+        self.origin + y as usize * self.stride + x as usize * channels
+    }
+
+    /// Checks that the slice's rows don't overlap and fit in a buffer of `len` values.
+    pub(crate) fn check_fits(&self, len: usize, channels: usize) -> Result<(), ImageError> {
+        let row_len = self.dims.width as usize * channels;
+        let end = match self.dims.height as usize {
+            0 => 0,
+            h => self.origin + (h - 1) * self.stride + row_len,
+        };
+        if self.stride < row_len || end > len {
+            return BufferSize {
+                width: self.dims.width,
+                height: self.dims.height,
+                expected: end,
+                actual: len,
+            }.fail();
+        }
+        Ok(())
+    }
+}
+
+
+/// Output encodings accepted by the `save_*` and `write_image` functions.
+/// Images are converted to a colour type each format can store first, e.g.
+/// to 8 bits for JPEG, GIF and QOI, or to 16 bits for float PNGs.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum OutputFormat {
+    Png,
+    /// JPEG at the given quality (1-100). Alpha is dropped.
+    Jpeg { quality: u8 },
+    Bmp,
+    Gif,
+    Tiff,
+    Tga,
+    /// Lossless WebP.
+    WebP,
+    Qoi,
+    Pnm,
+    Ico,
+    Farbfeld,
+}
+
+impl OutputFormat {
+    /// Quality used for JPEG when only the container format is known.
+    pub const DEFAULT_JPEG_QUALITY: u8 = 75;
+
+    /// Picks the output format matching a decoder format, if we can encode it.
+    pub fn from_image_format(format: ImageFormat) -> Option<OutputFormat> {
+        match format {
+            ImageFormat::Png => Some(OutputFormat::Png),
+            ImageFormat::Jpeg => Some(OutputFormat::Jpeg { quality: Self::DEFAULT_JPEG_QUALITY }),
+            ImageFormat::Bmp => Some(OutputFormat::Bmp),
+            ImageFormat::Gif => Some(OutputFormat::Gif),
+            ImageFormat::Tiff => Some(OutputFormat::Tiff),
+            ImageFormat::Tga => Some(OutputFormat::Tga),
+            ImageFormat::WebP => Some(OutputFormat::WebP),
+            ImageFormat::Qoi => Some(OutputFormat::Qoi),
+            ImageFormat::Pnm => Some(OutputFormat::Pnm),
+            ImageFormat::Ico => Some(OutputFormat::Ico),
+            ImageFormat::Farbfeld => Some(OutputFormat::Farbfeld),
+            _ => None,
+        }
+    }
+
+    /// Picks the output format from the extension of `path`.
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Result<OutputFormat, ImageError> {
+        let path = path.as_ref();
+        match ImageFormat::from_path(path).ok().and_then(OutputFormat::from_image_format) {
+            Some(format) => Ok(format),
+            None => UnknownExtension { path }.fail(),
+        }
+    }
+
+    /// The container format this encodes to.
+    pub fn image_format(&self) -> ImageFormat {
+        match self {
+            OutputFormat::Png => ImageFormat::Png,
+            OutputFormat::Jpeg { .. } => ImageFormat::Jpeg,
+            OutputFormat::Bmp => ImageFormat::Bmp,
+            OutputFormat::Gif => ImageFormat::Gif,
+            OutputFormat::Tiff => ImageFormat::Tiff,
+            OutputFormat::Tga => ImageFormat::Tga,
+            OutputFormat::WebP => ImageFormat::WebP,
+            OutputFormat::Qoi => ImageFormat::Qoi,
+            OutputFormat::Pnm => ImageFormat::Pnm,
+            OutputFormat::Ico => ImageFormat::Ico,
+            OutputFormat::Farbfeld => ImageFormat::Farbfeld,
+        }
+    }
+}
+
+/// Opens the image at `path` and decodes it into an `OwnedImage` of pixel type `Px`.
+pub fn import<Px: PixelType, P: AsRef<Path>>(path: P) -> Result<OwnedImage<Px>, ImageError> {
+    let (image, _) = load(path)?;
+    Ok(image)
+}
 
-pub fn import(name: &str) -> Result<OwnedImage, ImageError> {
-    match rust_image::open("test.png") {
-        Ok(img) => return Ok(OwnedImage::import(img)),
-        Err(err) => return Err(ImageError::InvalidFormat)
+/// Opens the image at `path`, sniffing its format from the file contents
+/// (falling back to the extension), and reports which format was decoded.
+pub fn load<Px: PixelType, P: AsRef<Path>>(path: P) -> Result<(OwnedImage<Px>, ImageFormat), ImageError> {
+    let path = path.as_ref();
+    let reader = ImageReader::open(path)
+        .and_then(|reader| reader.with_guessed_format())
+        .context(ReadFile { path })?;
+    let format = match reader.format() {
+        Some(format) => format,
+        None => return UnknownFileFormat { path }.fail(),
     };
+    let img = reader.decode().context(Open { path })?;
+    Ok((OwnedImage::import(img), format))
 }
 
-pub fn save_image(source: &impl Image, name: &str) {
-    let pixels = source.pixels();
+/// Reads an encoded image from `reader` and decodes it, sniffing the format.
+pub fn load_from_reader<Px: PixelType, R: Read>(mut reader: R)
+    -> Result<(OwnedImage<Px>, ImageFormat), ImageError> {
+    let mut bytes = Vec::new();
+    reader.read_to_end(&mut bytes).context(ReadStream)?;
+    load_from_memory(&bytes)
+}
 
-    match image::save_buffer(name, &*pixels, source.dimensions().width, source.dimensions().height,image::RGBA(8)) {
-        Ok(img) => {img}
-        Err(err) => return dispatch_error(ImageError::ImageOperationFailed)
+/// Decodes an in-memory encoded image, sniffing the format from its header.
+pub fn load_from_memory<Px: PixelType>(bytes: &[u8]) -> Result<(OwnedImage<Px>, ImageFormat), ImageError> {
+    let format = match rust_image::guess_format(bytes) {
+        Ok(format) => format,
+        Err(_) => return UnknownFormat.fail(),
     };
+    let image = load_from_memory_with_format(bytes, format)?;
+    Ok((image, format))
+}
+
+/// Decodes an in-memory encoded image of a known format.
+pub fn load_from_memory_with_format<Px: PixelType>(bytes: &[u8], format: ImageFormat)
+    -> Result<OwnedImage<Px>, ImageError> {
+    let img = rust_image::load_from_memory_with_format(bytes, format).context(Decode { format })?;
+    Ok(OwnedImage::import(img))
+}
+
+/// Encodes `source` to `path`, picking the format from the file extension,
+/// and reports which format was used.
+pub fn save_image<P: AsRef<Path>>(source: &impl Image, path: P) -> Result<OutputFormat, ImageError> {
+    let format = OutputFormat::from_path(&path)?;
+    save_image_with_format(source, path, format)?;
+    Ok(format)
 }
 
+/// Encodes `source` to `path` as `format`, whatever the extension says.
+/// To store fewer channels, convert first, e.g. with `converted::<Rgb8>()`.
+pub fn save_image_with_format<P: AsRef<Path>>(source: &impl Image, path: P, format: OutputFormat)
+    -> Result<(), ImageError> {
+    let path = path.as_ref();
+    let file = File::create(path).context(WriteFile { path })?;
+    encode(source, BufWriter::new(file), format).context(Save { path })?;
+    Ok(())
+}
 
-pub fn dispatch_error(err: ImageError) {
-    println!("{}", err);
+/// Encodes `source` as `format` into any writer.
+pub fn write_image<W: Write>(source: &impl Image, mut writer: W, format: OutputFormat)
+    -> Result<(), ImageError> {
+    // Some encoders (TIFF) need to seek, so encode in memory first.
+    let mut buffer = Cursor::new(Vec::new());
+    encode(source, &mut buffer, format).context(Encode { format })?;
+    writer.write_all(buffer.get_ref()).context(WriteStream)?;
+    Ok(())
+}
+
+/// Encodes `source` to `path` with its palette, picking PNG or GIF from the
+/// file extension, and reports which format was used. Other extensions fail
+/// without touching the file.
+pub fn save_indexed<P: AsRef<Path>>(source: &IndexedImage, path: P) -> Result<OutputFormat, ImageError> {
+    let path = path.as_ref();
+    let format = OutputFormat::from_path(path)?;
+    if !matches!(format, OutputFormat::Png | OutputFormat::Gif) {
+        return IndexedFormat { format }.fail();
+    }
+    let file = File::create(path).context(WriteFile { path })?;
+    write_indexed(source, BufWriter::new(file), format)?;
+    Ok(format)
+}
+
+/// Encodes `source` with its palette into any writer. PNGs take the fewest
+/// bits per pixel their palette allows, down to 1; only PNG and GIF can be
+/// written this way.
+pub fn write_indexed<W: Write>(source: &IndexedImage, writer: W, format: OutputFormat) -> Result<(), ImageError> {
+    let Dimensions { width, height } = *source.dimensions();
+    let palette: Vec<u8> = source.palette().colours().iter().flat_map(|&Rgb { r, g, b }| [r, g, b]).collect();
+    match format {
+        OutputFormat::Png => {
+            let (depth, bits) = match source.palette().colours().len() {
+                0..=2 => (png::BitDepth::One, 1),
+                3..=4 => (png::BitDepth::Two, 2),
+                5..=16 => (png::BitDepth::Four, 4),
+                _ => (png::BitDepth::Eight, 8),
+            };
+            let mut encoder = png::Encoder::new(writer, width, height);
+            encoder.set_color(png::ColorType::Indexed);
+            encoder.set_depth(depth);
+            encoder.set_palette(palette);
+            let mut writer = encoder.write_header().context(IndexedPng)?;
+            writer.write_image_data(&pack_indices(source.indices(), width as usize, bits)).context(IndexedPng)?;
+            writer.finish().context(IndexedPng)?;
+        },
+        OutputFormat::Gif => {
+            let (w, h) = match (u16::try_from(width), u16::try_from(height)) {
+                (Ok(w), Ok(h)) => (w, h),
+                _ => return GifSize { width, height }.fail(),
+            };
+            let mut encoder = gif::Encoder::new(writer, w, h, &palette).context(IndexedGif)?;
+            let frame = gif::Frame::from_indexed_pixels(w, h, source.indices(), None);
+            encoder.write_frame(&frame).context(IndexedGif)?;
+        },
+        _ => return IndexedFormat { format }.fail(),
+    }
+    Ok(())
+}
+
+/// Packs each row of `indices` into bytes of `bits`-wide values, leftmost in
+/// the high bits, as PNG lays them out.
+fn pack_indices(indices: &[u8], width: usize, bits: usize) -> Vec<u8> {
+    if bits == 8 || width == 0 {
+        return indices.to_vec();
+    }
+    let per_byte = 8 / bits;
+    indices.chunks(width).flat_map(|row| {
+        row.chunks(per_byte).map(|values| {
+            values.iter().enumerate().fold(0, |byte, (i, &v)| byte | v << (8 - bits * (i + 1)))
+        })
+    }).collect()
+}
 
+/// Converts `source` into a `DynamicImage` with the same channel layout and depth.
+pub fn export<S: Image>(source: &S) -> DynamicImage {
+    let dims = source.dimensions();
+    S::Pixel::to_dynamic(dims.width, dims.height, source.pixels().into_vec())
+}
+
+fn encode<W: Write + Seek>(source: &impl Image, mut writer: W, format: OutputFormat)
+    -> Result<(), rust_image::ImageError> {
+    let img = encodable(export(source), format);
+    match format {
+        OutputFormat::Jpeg { quality } =>
+            img.write_with_encoder(JpegEncoder::new_with_quality(&mut writer, quality)),
+        _ => img.write_to(&mut writer, format.image_format()),
+    }
+}
+
+/// Converts `img` to the closest colour type `format`'s encoder accepts: bytes
+/// for 8-bit formats, 16 bits for floats, and colour or alpha added where a
+/// format can't store greys or drop alpha. The image crate's encoders refuse
+/// most other colour types rather than converting.
+fn encodable(img: DynamicImage, format: OutputFormat) -> DynamicImage {
+    let colour = img.color();
+    let (grey, alpha) = (!colour.has_color(), colour.has_alpha());
+    let float = colour == ColorType::Rgb32F || colour == ColorType::Rgba32F;
+    match format {
+        // alpha is dropped
+        OutputFormat::Jpeg { .. } if grey => DynamicImage::ImageLuma8(img.into_luma8()),
+        OutputFormat::Jpeg { .. } => DynamicImage::ImageRgb8(img.into_rgb8()),
+        // BMP has no grey with alpha
+        OutputFormat::Bmp if alpha => DynamicImage::ImageRgba8(img.into_rgba8()),
+        OutputFormat::Bmp | OutputFormat::Tga | OutputFormat::WebP => match (grey, alpha) {
+            (true, false) => DynamicImage::ImageLuma8(img.into_luma8()),
+            (true, true) => DynamicImage::ImageLumaA8(img.into_luma_alpha8()),
+            (false, false) => DynamicImage::ImageRgb8(img.into_rgb8()),
+            (false, true) => DynamicImage::ImageRgba8(img.into_rgba8()),
+        },
+        OutputFormat::Gif | OutputFormat::Qoi if alpha => DynamicImage::ImageRgba8(img.into_rgba8()),
+        OutputFormat::Gif | OutputFormat::Qoi => DynamicImage::ImageRgb8(img.into_rgb8()),
+        // icons are read back as 8-bit RGBA only
+        OutputFormat::Ico => DynamicImage::ImageRgba8(img.into_rgba8()),
+        OutputFormat::Farbfeld => DynamicImage::ImageRgba16(img.into_rgba16()),
+        // no grey with alpha
+        OutputFormat::Tiff if colour == ColorType::La8 => DynamicImage::ImageRgba8(img.into_rgba8()),
+        OutputFormat::Tiff if colour == ColorType::La16 => DynamicImage::ImageRgba16(img.into_rgba16()),
+        OutputFormat::Tiff => img,
+        OutputFormat::Pnm if colour == ColorType::La16 => DynamicImage::ImageRgba16(img.into_rgba16()),
+        OutputFormat::Png | OutputFormat::Pnm if float && alpha =>
+            DynamicImage::ImageRgba16(img.into_rgba16()),
+        OutputFormat::Png | OutputFormat::Pnm if float => DynamicImage::ImageRgb16(img.into_rgb16()),
+        OutputFormat::Png | OutputFormat::Pnm => img,
+    }
+}
+
+/// Checks that (`x`, `y`) lies inside an image of size `dims`.
+pub fn check_bounds(x: u32, y: u32, dims: &Dimensions) -> Result<(), ImageError> {
+    if x >= dims.width || y >= dims.height {
+        return IndexOutOfBound { x, y, width: dims.width, height: dims.height }.fail();
+    }
+    Ok(())
+}
+
+/// Checks that a crop starting at (`x`, `y`) begins inside an image of size `dims`.
+pub fn check_crop(x: u32, y: u32, dims: &Dimensions) -> Result<(), ImageError> {
+    if x > dims.width || y > dims.height {
+        return CropOutOfBound { x, y, width: dims.width, height: dims.height }.fail();
+    }
+    Ok(())
 }
 
 #[derive(Debug, Snafu)]
+#[snafu(visibility = "pub(crate)")]
 pub enum ImageError {
-    #[snafu(display("Can't open image due to error"))]
-    InvalidFormat,
+    #[snafu(display("Can't open image {}: {}", path.display(), source))]
+    Open { path: PathBuf, source: rust_image::ImageError },
+
+    #[snafu(display("Can't read {}: {}", path.display(), source))]
+    ReadFile { path: PathBuf, source: std::io::Error },
+
+    #[snafu(display("Can't read image stream: {}", source))]
+    ReadStream { source: std::io::Error },
+
+    #[snafu(display("Can't recognise the format of image {}", path.display()))]
+    UnknownFileFormat { path: PathBuf },
+
+    #[snafu(display("Can't recognise the image format"))]
+    UnknownFormat,
+
+    #[snafu(display("Can't decode {:?} image: {}", format, source))]
+    Decode { format: ImageFormat, source: rust_image::ImageError },
+
+    #[snafu(display("Can't save image {}: {}", path.display(), source))]
+    Save { path: PathBuf, source: rust_image::ImageError },
+
+    #[snafu(display("Can't write {}: {}", path.display(), source))]
+    WriteFile { path: PathBuf, source: std::io::Error },
 
-    #[snafu(display("Can't parse the raw pixel vector into a matrix"))]
-    ParseError,
+    #[snafu(display("Can't pick an output format from the extension of {}", path.display()))]
+    UnknownExtension { path: PathBuf },
 
-    #[snafu(display("Index out of bound"))]
-    IndexOutOfBound,
+    #[snafu(display("Can't encode image as {:?}: {}", format, source))]
+    Encode { format: OutputFormat, source: rust_image::ImageError },
 
-    #[snafu(display("Can not perform image operation"))]
-    ImageOperationFailed,
-}
\ No newline at end of file
+    #[snafu(display("Can't write image stream: {}", source))]
+    WriteStream { source: std::io::Error },
+
+    #[snafu(display("Pixel buffer of {} channel values doesn't match a {}x{} image ({} expected)",
+                    actual, width, height, expected))]
+    BufferSize { width: u32, height: u32, expected: usize, actual: usize },
+
+    #[snafu(display("Index ({}, {}) out of bound for {}x{} image", x, y, width, height))]
+    IndexOutOfBound { x: u32, y: u32, width: u32, height: u32 },
+
+    #[snafu(display("Kernel of {}x{} with {} weights must have odd sides and one weight per cell",
+                    width, height, len))]
+    InvalidKernel { width: usize, height: usize, len: usize },
+
+    #[snafu(display("Crop origin ({}, {}) out of bound for {}x{} image", x, y, width, height))]
+    CropOutOfBound { x: u32, y: u32, width: u32, height: u32 },
+
+    #[snafu(display("Can't combine a {}x{} image with a {}x{} one", width, height, other_width, other_height))]
+    SizeMismatch { width: u32, height: u32, other_width: u32, other_height: u32 },
+
+    #[snafu(display("Can't write a {}x{} image into a {}x{} slice", source_width, source_height, width, height))]
+    DimensionMismatch { width: u32, height: u32, source_width: u32, source_height: u32 },
+
+    #[snafu(display("A palette needs 1 to 256 colours, not {}", count))]
+    PaletteSize { count: usize },
+
+    #[snafu(display("Palette index {} out of bound for a palette of {} colours", index, count))]
+    PaletteIndex { index: u8, count: usize },
+
+    #[snafu(display("Can't write indexed images as {:?}, only as PNG or GIF", format))]
+    IndexedFormat { format: OutputFormat },
+
+    #[snafu(display("Can't encode indexed PNG: {}", source))]
+    IndexedPng { source: png::EncodingError },
+
+    #[snafu(display("Can't encode indexed GIF: {}", source))]
+    IndexedGif { source: gif::EncodingError },
+
+    #[snafu(display("GIF can't hold a {}x{} image, only up to 65535 pixels either way", width, height))]
+    GifSize { width: u32, height: u32 },
+
+    #[cfg(feature = "parallel")]
+    #[snafu(display("Can't build thread pool: {}", source))]
+    ThreadPool { source: rayon::ThreadPoolBuildError },
+}
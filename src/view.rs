@@ -0,0 +1,414 @@
+use super::utils::*;
+use super::pixel::*;
+use super::parallel;
+use std::marker::PhantomData;
+use std::slice;
+
+// Views keep a pointer to their first pixel rather than a slice, so that
+// disjoint views can share rows of one buffer: a slice spanning several rows
+// would also cover the gaps between them, which may belong to another view.
+//
+// Every view upholds one invariant: for each row `y` < height, the `row_len`
+// values starting `y * stride` past the pointer lie inside the buffer it
+// borrows. References are only ever made to those rows (or parts of them).
+
+/************************************** Strided View Struct *******************************************/
+/******************************************************************************************************/
+
+/// A borrowed, read-only view of pixels stored in rows that may lie further
+/// apart than the view is wide, like a crop of a larger image.
+pub struct StridedView<'a, P: PixelType = Rgba8> {
+    ptr: *const P::Channel,
+    dims: Dimensions,
+    stride: usize,
+    _pixels: PhantomData<&'a [P::Channel]>,
+}
+
+// derived impls would require `P: Clone`, which the view doesn't store
+impl<'a, P: PixelType> Clone for StridedView<'a, P> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<'a, P: PixelType> Copy for StridedView<'a, P> {}
+
+// a view only reads its rows, like the `&[P::Channel]` it stands in for
+unsafe impl<'a, P: PixelType> Send for StridedView<'a, P> {}
+unsafe impl<'a, P: PixelType> Sync for StridedView<'a, P> {}
+
+impl<'a, P: PixelType> StridedView<'a, P> {
+
+    /// Views `pixels` as an image of `dims` whose rows start `stride` channel
+    /// values apart. Fails if a row would overlap the next or run off the buffer.
+    pub fn new(pixels: &'a [P::Channel], dims: Dimensions, stride: usize) -> Result<Self, ImageError> {
+        SliceSpec { origin: 0, stride, dims }.check_fits(pixels.len(), P::CHANNELS)?;
+        Ok(StridedView::from_raw(pixels, dims, stride))
+    }
+
+    pub fn dimensions(&self) -> &Dimensions {
+        &self.dims
+    }
+
+    /// Channel values from the start of one row to the start of the next.
+    pub fn stride(&self) -> usize {
+        self.stride
+    }
+
+    /// Borrows row `y`. Panics if `y` is out of bounds.
+    pub fn row(&self, y: u32) -> &'a [P::Channel] {
+        assert!(y < self.dims.height, "row {} out of bound for {}-row image", y, self.dims.height);
+        unsafe { slice::from_raw_parts(self.ptr.add(y as usize * self.stride), self.row_len()) }
+    }
+
+    /// Iterates over the rows from top to bottom.
+    pub fn rows(&self) -> Rows<'a, P> {
+        Rows { view: *self, next: 0 }
+    }
+
+    /// Iterates over the pixels row by row, with their coordinates.
+    pub fn pixels_iter(&self) -> PixelsIter<'a, P> {
+        PixelsIter { view: *self, x: 0, y: 0 }
+    }
+
+    /// The whole buffer as one slice, if the rows follow each other without gaps.
+    pub fn as_contiguous(&self) -> Option<&'a [P::Channel]> {
+        let len = self.row_len() * self.dims.height as usize;
+        if self.stride == self.row_len() || self.dims.height <= 1 {
+            // the rows are back to back, so together they are one run of the buffer
+            Some(unsafe { slice::from_raw_parts(self.ptr, len) })
+        } else {
+            None
+        }
+    }
+
+    /// Reads the pixel at (`x`, `y`).
+    pub fn get_pixel(&self, x: u32, y: u32) -> Result<P, ImageError> {
+        check_bounds(x, y, &self.dims)?;
+        Ok(P::from_channels(self.at(x as usize, y as usize)))
+    }
+
+    /// Copies the pixels into a new, tightly packed buffer.
+    pub fn to_vec(&self) -> Vec<P::Channel> {
+        match self.as_contiguous() {
+            Some(pixels) => pixels.to_vec(),
+            None => {
+                let mut buffer = Vec::with_capacity(self.row_len() * self.dims.height as usize);
+                self.rows().for_each(|row| buffer.extend_from_slice(row));
+                buffer
+            }
+        }
+    }
+
+    /*************** Crate Functions ***************/
+
+    /// Builds a view whose layout is already known to fit `pixels`.
+    pub(crate) fn from_raw(pixels: &'a [P::Channel], dims: Dimensions, stride: usize) -> Self {
+        StridedView::from_raw_at(pixels, 0, dims, stride)
+    }
+
+    /// Like `from_raw`, for rows starting at `pixels[origin]`.
+    pub(crate) fn from_raw_at(pixels: &'a [P::Channel], origin: usize, dims: Dimensions, stride: usize) -> Self {
+        // only an empty view at the far corner can start past the buffer
+        let pixels = pixels.get(origin..).unwrap_or(&[]);
+        StridedView { ptr: pixels.as_ptr(), dims, stride, _pixels: PhantomData }
+    }
+
+    /// The part of this view starting at (`x`, `y`), whose `dims` are already
+    /// known to fit.
+    pub(crate) fn sub_view(&self, x: u32, y: u32, dims: Dimensions) -> Self {
+        let ptr = self.ptr.wrapping_add(y as usize * self.stride + x as usize * P::CHANNELS);
+        StridedView { ptr, dims, stride: self.stride, _pixels: PhantomData }
+    }
+
+    /// Channels of the pixel at (`x`, `y`), without bounds checks against the view.
+    pub(crate) fn at(&self, x: usize, y: usize) -> &'a [P::Channel] {
+        &self.row(y as u32)[x * P::CHANNELS..(x + 1) * P::CHANNELS]
+    }
+
+    /*************** Private Functions **************/
+
+    fn row_len(&self) -> usize {
+        self.dims.width as usize * P::CHANNELS
+    }
+}
+
+/// Iterator over the rows of a `StridedView`.
+pub struct Rows<'a, P: PixelType = Rgba8> {
+    view: StridedView<'a, P>,
+    next: u32,
+}
+
+impl<'a, P: PixelType> Iterator for Rows<'a, P> {
+    type Item = &'a [P::Channel];
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.next >= self.view.dims.height {
+            return None;
+        }
+        self.next += 1;
+        Some(self.view.row(self.next - 1))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let left = (self.view.dims.height - self.next) as usize;
+        (left, Some(left))
+    }
+}
+
+impl<'a, P: PixelType> ExactSizeIterator for Rows<'a, P> {}
+
+/************************************ Mutable Strided View Struct *************************************/
+/******************************************************************************************************/
+
+/// A borrowed, writable view of pixels stored in rows that may lie further
+/// apart than the view is wide. Values between the rows are never touched.
+pub struct StridedViewMut<'a, P: PixelType = Rgba8> {
+    ptr: *mut P::Channel,
+    dims: Dimensions,
+    stride: usize,
+    _pixels: PhantomData<&'a mut [P::Channel]>,
+}
+
+// a view owns its rows exclusively, like the `&mut [P::Channel]` it stands in for
+unsafe impl<'a, P: PixelType> Send for StridedViewMut<'a, P> {}
+unsafe impl<'a, P: PixelType> Sync for StridedViewMut<'a, P> {}
+
+impl<'a, P: PixelType> StridedViewMut<'a, P> {
+
+    /// Views `pixels` as an image of `dims` whose rows start `stride` channel
+    /// values apart. Fails if a row would overlap the next or run off the buffer.
+    pub fn new(pixels: &'a mut [P::Channel], dims: Dimensions, stride: usize) -> Result<Self, ImageError> {
+        SliceSpec { origin: 0, stride, dims }.check_fits(pixels.len(), P::CHANNELS)?;
+        Ok(StridedViewMut::from_raw(pixels, dims, stride))
+    }
+
+    pub fn dimensions(&self) -> &Dimensions {
+        &self.dims
+    }
+
+    /// Channel values from the start of one row to the start of the next.
+    pub fn stride(&self) -> usize {
+        self.stride
+    }
+
+    /// Borrows the view read-only.
+    pub fn as_view(&self) -> StridedView<'_, P> {
+        StridedView { ptr: self.ptr, dims: self.dims, stride: self.stride, _pixels: PhantomData }
+    }
+
+    /// Mutably borrows row `y`. Panics if `y` is out of bounds.
+    pub fn row_mut(&mut self, y: u32) -> &mut [P::Channel] {
+        assert!(y < self.dims.height, "row {} out of bound for {}-row image", y, self.dims.height);
+        unsafe { slice::from_raw_parts_mut(self.ptr.add(y as usize * self.stride), self.row_len()) }
+    }
+
+    /// Iterates mutably over the rows from top to bottom.
+    pub fn rows_mut(&mut self) -> RowsMut<'_, P> {
+        self.reborrow().into_rows_mut()
+    }
+
+    /// Iterates mutably over the rows, keeping the whole borrow.
+    pub fn into_rows_mut(self) -> RowsMut<'a, P> {
+        RowsMut { view: self }
+    }
+
+    /// Iterates mutably over the pixels row by row, with their coordinates.
+    /// Each pixel is lent as its channel values.
+    pub fn into_pixels_mut(self) -> PixelsMut<'a, P> {
+        PixelsMut { rows: self.into_rows_mut(), row: &mut [], x: 0, next_y: 0 }
+    }
+
+    /// Overwrites the pixel at (`x`, `y`).
+    pub fn set_pixel(&mut self, x: u32, y: u32, pix: P) -> Result<(), ImageError> {
+        check_bounds(x, y, &self.dims)?;
+        let start = x as usize * P::CHANNELS;
+        pix.write_channels(&mut self.row_mut(y)[start..start + P::CHANNELS]);
+        Ok(())
+    }
+
+    /// Copies `source` into the top-left of this view, clipping whichever is larger.
+    pub fn copy_from_view(&mut self, source: &StridedView<'_, P>) {
+        let height = self.dims.height.min(source.dimensions().height);
+        let len = self.dims.width.min(source.dimensions().width) as usize * P::CHANNELS;
+        for (dst, src) in self.rows_mut().zip(source.rows()).take(height as usize) {
+            dst[..len].copy_from_slice(&src[..len]);
+        }
+    }
+
+    /*************** Crate Functions ***************/
+
+    /// Views the rows of `dims` starting at `pixels[origin]`, which are already
+    /// known to fit.
+    pub(crate) fn from_raw_at(pixels: &'a mut [P::Channel], origin: usize, dims: Dimensions, stride: usize) -> Self {
+        let pixels = match pixels.get_mut(origin..) {
+            Some(pixels) => pixels,
+            // only an empty view at the far corner can start past the buffer
+            None => &mut [],
+        };
+        StridedViewMut { ptr: pixels.as_mut_ptr(), dims, stride, _pixels: PhantomData }
+    }
+
+    pub(crate) fn from_raw(pixels: &'a mut [P::Channel], dims: Dimensions, stride: usize) -> Self {
+        StridedViewMut::from_raw_at(pixels, 0, dims, stride)
+    }
+
+    /// Borrows this view again for a shorter time.
+    pub(crate) fn reborrow(&mut self) -> StridedViewMut<'_, P> {
+        StridedViewMut { ptr: self.ptr, dims: self.dims, stride: self.stride, _pixels: PhantomData }
+    }
+
+    /// The part of this view starting at (`x`, `y`), whose `dims` are already
+    /// known to fit.
+    pub(crate) fn sub_view(self, x: u32, y: u32, dims: Dimensions) -> Self {
+        let ptr = self.ptr.wrapping_add(y as usize * self.stride + x as usize * P::CHANNELS);
+        StridedViewMut { ptr, dims, stride: self.stride, _pixels: PhantomData }
+    }
+
+    /// Splits into the rows above `y` and the rest. `y` is clamped to the height.
+    pub(crate) fn split_at_row(self, y: u32) -> (Self, Self) {
+        let Dimensions { width, height } = self.dims;
+        let y = y.min(height);
+        // the halves share no rows
+        let bottom = self.alias().sub_view(0, y, Dimensions { width, height: height - y });
+        (self.sub_view(0, 0, Dimensions { width, height: y }), bottom)
+    }
+
+    /// Splits into the columns left of `x` and the rest. `x` is clamped to the width.
+    pub(crate) fn split_at_col(self, x: u32) -> (Self, Self) {
+        let Dimensions { width, height } = self.dims;
+        let x = x.min(width);
+        // every row is cut in two, and each half keeps one side
+        let right = self.alias().sub_view(x, 0, Dimensions { width: width - x, height });
+        (self.sub_view(0, 0, Dimensions { width: x, height }), right)
+    }
+
+    /// Calls `f` with every row and its index, in row bands that may run
+    /// concurrently.
+    pub(crate) fn for_each_row(&mut self, f: impl Fn(u32, &mut [P::Channel]) + Sync) {
+        let mut rows: Vec<_> = self.rows_mut().collect();
+        parallel::for_each_band(&mut rows, 1, |first_row, band| {
+            for (y, row) in (first_row as u32..).zip(band) {
+                f(y, row);
+            }
+        });
+    }
+
+    /// Swaps each row in the top half with its mirror in the bottom half.
+    pub(crate) fn swap_rows_vertically(&mut self) {
+        let height = self.dims.height as usize;
+        let mut rows: Vec<_> = self.rows_mut().collect();
+        // an odd middle row stays put
+        let (top, rest) = rows.split_at_mut(height / 2);
+        let bottom = &mut rest[height % 2..];
+        parallel::for_each_row_pair(top, bottom, 1, |a, b| a[0].swap_with_slice(b[0]));
+    }
+
+    /*************** Private Functions **************/
+
+    /// A second handle on the same rows. Callers must cut the two down to
+    /// disjoint parts before either is used.
+    fn alias(&self) -> Self {
+        StridedViewMut { ptr: self.ptr, dims: self.dims, stride: self.stride, _pixels: PhantomData }
+    }
+
+    fn row_len(&self) -> usize {
+        self.dims.width as usize * P::CHANNELS
+    }
+}
+
+/// Mutable iterator over the rows of a `StridedViewMut`.
+pub struct RowsMut<'a, P: PixelType = Rgba8> {
+    view: StridedViewMut<'a, P>,
+}
+
+impl<'a, P: PixelType> Iterator for RowsMut<'a, P> {
+    type Item = &'a mut [P::Channel];
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.view.dims.height == 0 {
+            return None;
+        }
+        // hand out the first row and shrink the view past it, so no row is lent twice
+        let row = unsafe { slice::from_raw_parts_mut(self.view.ptr, self.view.row_len()) };
+        self.view.ptr = self.view.ptr.wrapping_add(self.view.stride);
+        self.view.dims.height -= 1;
+        Some(row)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let left = self.view.dims.height as usize;
+        (left, Some(left))
+    }
+}
+
+impl<'a, P: PixelType> ExactSizeIterator for RowsMut<'a, P> {}
+
+/*************************************** Pixel Iterators **********************************************/
+/******************************************************************************************************/
+
+/// Iterator over the pixels of a `StridedView` and their coordinates.
+pub struct PixelsIter<'a, P: PixelType = Rgba8> {
+    view: StridedView<'a, P>,
+    x: u32,
+    y: u32,
+}
+
+impl<'a, P: PixelType> Iterator for PixelsIter<'a, P> {
+    type Item = (u32, u32, P);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let Dimensions { width, height } = self.view.dims;
+        if self.y >= height || width == 0 {
+            return None;
+        }
+        let (x, y) = (self.x, self.y);
+        self.x += 1;
+        if self.x == width {
+            self.x = 0;
+            self.y += 1;
+        }
+        Some((x, y, P::from_channels(self.view.at(x as usize, y as usize))))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let Dimensions { width, height } = self.view.dims;
+        let left = (height.saturating_sub(self.y) as usize * width as usize).saturating_sub(self.x as usize);
+        (left, Some(left))
+    }
+}
+
+impl<'a, P: PixelType> ExactSizeIterator for PixelsIter<'a, P> {}
+
+/// Mutable iterator over the pixels of a `StridedViewMut`, lending each as
+/// its coordinates and channel values.
+pub struct PixelsMut<'a, P: PixelType = Rgba8> {
+    rows: RowsMut<'a, P>,
+    row: &'a mut [P::Channel],
+    x: u32,
+    next_y: u32,
+}
+
+impl<'a, P: PixelType> Iterator for PixelsMut<'a, P> {
+    type Item = (u32, u32, &'a mut [P::Channel]);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.row.is_empty() {
+            self.row = self.rows.next()?;
+            self.x = 0;
+            self.next_y += 1;
+        }
+        let (pixel, rest) = std::mem::take(&mut self.row).split_at_mut(P::CHANNELS);
+        self.row = rest;
+        self.x += 1;
+        Some((self.x - 1, self.next_y - 1, pixel))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let width = self.rows.view.dims.width as usize;
+        let left = self.row.len() / P::CHANNELS + self.rows.len() * width;
+        (left, Some(left))
+    }
+}
+
+impl<'a, P: PixelType> ExactSizeIterator for PixelsMut<'a, P> {}
@@ -0,0 +1,119 @@
+//! Tone adjustments: the 8-bit lookup table against the curves themselves,
+//! and deeper layouts that compute every value directly.
+
+use image_processor::{
+    traits::*,
+    OwnedImage::OwnedImage,
+    utils::Dimensions,
+    pixel::*,
+};
+
+/// Which adjustment to make, and the curve it should follow on a 0.0-1.0 scale.
+type Adjustment = (&'static str, Box<dyn Fn(&OwnedImage<Rgba8>) -> OwnedImage<Rgba8>>, Box<dyn Fn(f32) -> f32>);
+
+fn adjustments() -> Vec<Adjustment> {
+    let mut all: Vec<Adjustment> = Vec::new();
+    for &amount in &[-1.0f32, -0.3, 0.0, 0.1, 0.5] {
+        all.push(("brightness", Box::new(move |image| image.adjust_brightness(amount)), Box::new(move |v| v + amount)));
+    }
+    for &factor in &[0.0f32, 0.5, 1.0, 1.7, 4.0] {
+        all.push(("contrast", Box::new(move |image| image.adjust_contrast(factor)), Box::new(move |v| (v - 0.5) * factor + 0.5)));
+    }
+    for &gamma in &[0.3f32, 1.0, 2.2, 5.0] {
+        all.push(("gamma", Box::new(move |image| image.adjust_gamma(gamma)), Box::new(move |v| v.powf(1.0 / gamma))));
+    }
+    for &stops in &[-2.0f32, -0.5, 0.0, 0.7, 3.0] {
+        all.push(("exposure", Box::new(move |image| image.adjust_exposure(stops)), Box::new(move |v| v * stops.exp2())));
+    }
+    all
+}
+
+/// Every 8-bit value once in each colour channel, in a different order in each, with alpha counting up.
+fn every_byte() -> OwnedImage<Rgba8> {
+    let pixels: Vec<u8> = (0..=255u8).flat_map(|i| vec![i, 255 - i, i.wrapping_mul(97), i]).collect();
+    OwnedImage::new(Dimensions { width: 16, height: 16 }, pixels.into_boxed_slice()).unwrap()
+}
+
+/// What a curve gives for `value` in a channel with `max` as its largest value.
+fn direct(curve: &dyn Fn(f32) -> f32, value: f32, max: f32) -> f32 {
+    (curve(value / max) * max).round().clamp(0.0, max)
+}
+
+/*************************************** Lookup Table *************************************************/
+/******************************************************************************************************/
+
+#[test]
+fn eight_bit_tables_match_the_curves() {
+    let image = every_byte();
+    for (name, adjust, curve) in adjustments() {
+        let adjusted = adjust(&image);
+        for (p, q) in image.pixels().chunks(4).zip(adjusted.pixels().chunks(4)) {
+            for c in 0..3 {
+                assert_eq!(q[c] as f32, direct(&*curve, p[c] as f32, 255.0), "{} of {}", name, p[c]);
+            }
+            assert_eq!(q[3], p[3], "{} changed alpha", name);
+        }
+    }
+}
+
+#[test]
+fn in_place_adjustments_match_the_copies() {
+    let image = every_byte();
+    let mut adjusted = image.converted::<Rgba8>();
+    adjusted.adjust_gamma_mut(1.8);
+    assert_eq!(adjusted.pixels(), image.adjust_gamma(1.8).pixels());
+    adjusted.adjust_exposure_mut(-1.0);
+    adjusted.adjust_contrast_mut(1.3);
+    adjusted.adjust_brightness_mut(0.05);
+    let copies = image.adjust_gamma(1.8).adjust_exposure(-1.0).adjust_contrast(1.3).adjust_brightness(0.05);
+    assert_eq!(adjusted.pixels(), copies.pixels());
+}
+
+/*************************************** Direct Curves ************************************************/
+/******************************************************************************************************/
+
+#[test]
+fn sixteen_bit_values_are_computed_directly() {
+    // values between the ones an 8-bit table would hold
+    let values: Vec<u16> = (0..=65535u32).step_by(97).map(|v| v as u16).chain(vec![1, 127, 128, 32767, 32768, 65534, 65535]).collect();
+    let image = OwnedImage::<Luma16>::new(Dimensions { width: values.len() as u32, height: 1 }, values.clone().into_boxed_slice()).unwrap();
+    let adjusted = [
+        (image.adjust_brightness(0.1), Box::new(|v: f32| v + 0.1) as Box<dyn Fn(f32) -> f32>),
+        (image.adjust_contrast(1.7), Box::new(|v: f32| (v - 0.5) * 1.7 + 0.5)),
+        (image.adjust_gamma(2.2), Box::new(|v: f32| v.powf(1.0 / 2.2))),
+        (image.adjust_exposure(-0.5), Box::new(|v: f32| v * (-0.5f32).exp2())),
+    ];
+    for (result, curve) in adjusted.iter() {
+        for (&value, &original) in result.pixels().iter().zip(&values) {
+            assert_eq!(value as f32, direct(&**curve, original as f32, 65535.0), "from {}", original);
+        }
+    }
+
+    // so the smallest steps survive
+    let nudged = image.adjust_brightness(1.0 / 65535.0);
+    for (&value, &original) in nudged.pixels().iter().zip(&values) {
+        assert_eq!(value, original.saturating_add(1));
+    }
+    assert_eq!(image.adjust_exposure(0.0).pixels(), image.pixels());
+}
+
+#[test]
+fn float_values_are_not_clamped() {
+    let values = vec![-0.5f32, 0.0, 0.2, 0.5, 0.8, 1.0, 1.6];
+    let image = OwnedImage::<Luma32F>::new(Dimensions { width: 7, height: 1 }, values.clone().into_boxed_slice()).unwrap();
+    let close = |result: OwnedImage<Luma32F>, expected: &dyn Fn(f32) -> f32| {
+        for (&value, &original) in result.pixels().iter().zip(&values) {
+            assert!((value - expected(original)).abs() < 1e-6, "{} became {}", original, value);
+        }
+    };
+
+    close(image.adjust_brightness(0.5), &|v| v + 0.5);
+    close(image.adjust_contrast(3.0), &|v| (v - 0.5) * 3.0 + 0.5);
+    close(image.adjust_exposure(2.0), &|v| v * 4.0);
+    // negative values mirror the positive curve
+    close(image.adjust_gamma(2.0), &|v| v.signum() * v.abs().sqrt());
+    // and gammas that make no sense change nothing
+    for &gamma in &[0.0, -2.0, f32::NAN] {
+        assert_eq!(image.adjust_gamma(gamma).pixels(), image.pixels());
+    }
+}
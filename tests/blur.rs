@@ -0,0 +1,195 @@
+//! Gaussian and box blurs, along each axis and at every edge mode.
+
+use image_processor::{
+    traits::*,
+    OwnedImage::{box_blur, OwnedImage},
+    utils::Dimensions,
+    pixel::*,
+    kernel::EdgeMode,
+};
+
+fn luma_image(width: u32, height: u32, luma: impl Fn(u32, u32) -> f32) -> OwnedImage<Luma32F> {
+    let pixels: Vec<f32> = (0..height).flat_map(|y| (0..width).map(move |x| (x, y))).map(|(x, y)| luma(x, y)).collect();
+    OwnedImage::new(Dimensions { width, height }, pixels.into_boxed_slice()).unwrap()
+}
+
+/// A ramp across with a bright square in it, so every edge has something to blur.
+fn ramp() -> OwnedImage<Luma32F> {
+    luma_image(48, 40, |x, y| if (10..20).contains(&x) && (12..22).contains(&y) { 1.0 } else { x as f32 / 47.0 })
+}
+
+fn max_difference(a: &OwnedImage<Luma32F>, b: &OwnedImage<Luma32F>) -> f32 {
+    assert_eq!(a.dimensions(), b.dimensions());
+    a.pixels().iter().zip(b.pixels().iter()).map(|(a, b)| (a - b).abs()).fold(0.0, f32::max)
+}
+
+fn edge_modes() -> Vec<EdgeMode<Luma32F>> {
+    vec![EdgeMode::Clamp, EdgeMode::Mirror, EdgeMode::Wrap, EdgeMode::Skip, EdgeMode::Constant(Luma { luma: 0.25 })]
+}
+
+/*************************************** Gaussian Blur ************************************************/
+/******************************************************************************************************/
+
+#[test]
+fn zero_sigmas_leave_the_image_alone() {
+    let image = ramp();
+    for &sigma in &[0.0, -1.0, f32::NAN] {
+        for edge in edge_modes() {
+            assert_eq!(image.gaussian_blurred(sigma, sigma, edge).pixels(), image.pixels());
+        }
+    }
+}
+
+#[test]
+fn huge_sigmas_flatten_the_image() {
+    let image = ramp();
+    let mean = image.pixels().iter().sum::<f32>() / image.pixels().len() as f32;
+    for &sigma in &[1e4, f32::INFINITY] {
+        // periodic edges average the whole image
+        for edge in &[EdgeMode::Wrap, EdgeMode::Mirror] {
+            let flat = image.gaussian_blurred(sigma, sigma, *edge);
+            assert!(flat.pixels().iter().all(|value| (value - mean).abs() < 1e-3), "{:?}", edge);
+        }
+        // clamped edges run on forever, so each side's pixels count for half
+        let flat = image.gaussian_blurred(sigma, sigma, EdgeMode::Clamp);
+        assert!(flat.pixels().iter().all(|value| (value - 0.5).abs() < 0.01));
+        let flat = image.gaussian_blurred(sigma, sigma, EdgeMode::Constant(Luma { luma: 0.25 }));
+        assert!(flat.pixels().iter().all(|value| (value - 0.25).abs() < 0.01));
+    }
+}
+
+#[test]
+fn the_fast_approximation_takes_over_smoothly() {
+    // just below and above the switch from an exact kernel to box blurs
+    let smooth = luma_image(48, 40, |x, y| (x + 2 * y) as f32 / 125.0);
+    // the edges that carry on smoothly from it; wrapping or a constant colour meets it at a hard edge
+    for edge in &[EdgeMode::Clamp, EdgeMode::Mirror, EdgeMode::Skip] {
+        let difference = max_difference(&smooth.gaussian_blurred(4.0, 4.0, *edge), &smooth.gaussian_blurred(4.01, 4.01, *edge));
+        assert!(difference < 1.5 / 255.0, "{:?} jumps by {}", edge, difference);
+    }
+    // hard edges show the boxes' shape a little more
+    for edge in edge_modes() {
+        let difference = max_difference(&ramp().gaussian_blurred(4.0, 4.0, edge), &ramp().gaussian_blurred(4.01, 4.01, edge));
+        assert!(difference < 0.02, "{:?} jumps by {}", edge, difference);
+    }
+
+    // and keeps changing gradually as sigma grows, which changes the square's peak by ~0.002
+    let mut previous = ramp().gaussian_blurred(4.01, 4.01, EdgeMode::Clamp);
+    for step in 1..300 {
+        let sigma = 4.01 + step as f32 * 0.01;
+        let blurred = ramp().gaussian_blurred(sigma, sigma, EdgeMode::Clamp);
+        assert!(max_difference(&previous, &blurred) < 0.004, "sigma {}", sigma);
+        previous = blurred;
+    }
+}
+
+#[test]
+fn each_axis_has_its_own_sigma() {
+    // stripes across only change down the image, and stripes down only across it
+    let across = luma_image(30, 24, |_, y| (y % 4) as f32 / 3.0);
+    let down = luma_image(30, 24, |x, _| (x % 4) as f32 / 3.0);
+    for &sigma in &[1.5, 9.0] {
+        for edge in edge_modes() {
+            // a constant edge colour would show along the blurred axis
+            if let EdgeMode::Constant(_) = edge {
+                continue;
+            }
+            assert!(max_difference(&across.gaussian_blurred(sigma, 0.0, edge), &across) < 1e-5, "{:?}", edge);
+            assert!(max_difference(&down.gaussian_blurred(0.0, sigma, edge), &down) < 1e-5, "{:?}", edge);
+            assert!(max_difference(&across.gaussian_blurred(0.0, sigma, edge), &across) > 0.1, "{:?}", edge);
+        }
+    }
+
+    // blurring both axes is blurring one after the other, in either order
+    let image = ramp();
+    for &(sigma_x, sigma_y) in &[(1.0, 3.0), (2.0, 8.0), (6.0, 0.5)] {
+        let both = image.gaussian_blurred(sigma_x, sigma_y, EdgeMode::Clamp);
+        let x_first = image.gaussian_blurred(sigma_x, 0.0, EdgeMode::Clamp).gaussian_blurred(0.0, sigma_y, EdgeMode::Clamp);
+        let y_first = image.gaussian_blurred(0.0, sigma_y, EdgeMode::Clamp).gaussian_blurred(sigma_x, 0.0, EdgeMode::Clamp);
+        assert!(max_difference(&both, &x_first) < 1e-5);
+        assert!(max_difference(&both, &y_first) < 1e-5);
+    }
+}
+
+/********************************************* Box Blur ***********************************************/
+/******************************************************************************************************/
+
+/// Colours that differ from every neighbour, with alpha that varies too.
+fn pattern(width: u32, height: u32) -> OwnedImage<Rgba8> {
+    let pixels: Vec<u8> = (0..height).flat_map(|y| (0..width).map(move |x| (x, y)))
+        .flat_map(|(x, y)| vec![((x ^ y) * 37) as u8, (x * 53 + y * 7) as u8, (y * 71) as u8, (x * 30 + 100) as u8])
+        .collect();
+    OwnedImage::new(Dimensions { width, height }, pixels.into_boxed_slice()).unwrap()
+}
+
+/// Where index `i` of a line of `len` reads from, or nothing for a pixel left out or of the constant colour.
+fn source(i: i64, len: i64, edge: &EdgeMode<Rgba8>) -> Option<i64> {
+    if (0..len).contains(&i) {
+        return Some(i);
+    }
+    match edge {
+        EdgeMode::Clamp => Some(i.max(0).min(len - 1)),
+        EdgeMode::Wrap => Some(i.rem_euclid(len)),
+        EdgeMode::Mirror => {
+            let m = i.rem_euclid(2 * len);
+            Some(if m < len { m } else { 2 * len - 1 - m })
+        }
+        EdgeMode::Constant(_) | EdgeMode::Skip | EdgeMode::Crop => None,
+    }
+}
+
+/// The original box blur, generalised to every edge mode: walks the whole
+/// (2r+1)^2 window for every pixel. Alpha is kept.
+fn window_box_blur(image: &OwnedImage<Rgba8>, r: i64, edge: &EdgeMode<Rgba8>) -> OwnedImage<Rgba8> {
+    let (w, h) = (image.dimensions().width as i64, image.dimensions().height as i64);
+    let pixels = image.pixels();
+    let shift = if *edge == EdgeMode::Crop { r } else { 0 };
+    let (out_w, out_h) = ((w - 2 * shift).max(0), (h - 2 * shift).max(0));
+
+    let mut out = Vec::new();
+    for j in shift..shift + out_h {
+        for i in shift..shift + out_w {
+            let (mut totals, mut count) = ([0.0f64; 3], 0.0);
+            for y in j - r..=j + r {
+                for x in i - r..=i + r {
+                    let colour = match (source(x, w, edge), source(y, h, edge), edge) {
+                        (Some(x), Some(y), _) => {
+                            let index = ((y * w + x) * 4) as usize;
+                            [pixels[index], pixels[index + 1], pixels[index + 2]]
+                        }
+                        (_, _, EdgeMode::Constant(Rgba { r, g, b, .. })) => [*r, *g, *b],
+                        _ => continue,
+                    };
+                    for (total, value) in totals.iter_mut().zip(&colour) {
+                        *total += *value as f64;
+                    }
+                    count += 1.0;
+                }
+            }
+            out.extend(totals.iter().map(|total| (total / count).round() as u8));
+            out.push(pixels[((j * w + i) * 4 + 3) as usize]);
+        }
+    }
+    OwnedImage::new(Dimensions { width: out_w as u32, height: out_h as u32 }, out.into_boxed_slice()).unwrap()
+}
+
+#[test]
+fn box_blur_matches_walking_the_window() {
+    let edges = [EdgeMode::Clamp, EdgeMode::Mirror, EdgeMode::Wrap, EdgeMode::Skip, EdgeMode::Crop,
+                 EdgeMode::Constant(Rgba { r: 200, g: 10, b: 90, alpha: 255 })];
+    for &(width, height) in &[(9, 7), (1, 6), (13, 1)] {
+        let image = pattern(width, height);
+        // up to radii reaching past the whole image, more than once for the short side
+        for &radius in &[0, 1, 2, 4, 7, 15] {
+            for edge in edges.iter() {
+                let expected = window_box_blur(&image, radius as i64, edge);
+                let blurred = box_blur(&image, radius, edge);
+                assert_eq!(blurred.dimensions(), expected.dimensions(), "{:?} radius {}", edge, radius);
+                for (a, b) in blurred.pixels().iter().zip(expected.pixels().iter()) {
+                    // rounding the float sums can land either side of a half
+                    assert!((*a as i32 - *b as i32).abs() <= 1, "{:?} radius {} on {}x{}", edge, radius, width, height);
+                }
+            }
+        }
+    }
+}
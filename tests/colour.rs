@@ -0,0 +1,365 @@
+//! Round trips between RGB and HSV/HSL, linear light or CIE spaces, and the
+//! operations built on them.
+
+use image_processor::{
+    traits::*,
+    OwnedImage::OwnedImage,
+    utils::Dimensions,
+    pixel::*,
+    utils::ImageError,
+    colour::{self, Hsv, Hsl, GreyscaleMethod, Xyz, Lab, Lch, DeltaE},
+    linear::LinearImage,
+    kernel::EdgeMode,
+};
+
+/// Every 8-bit RGB colour with channels a multiple of `step` apart, plus white.
+fn rgb8_colours(step: usize) -> impl Iterator<Item = [u8; 3]> {
+    let levels: Vec<u8> = (0..=255).step_by(step).chain(Some(255)).collect();
+    let (l1, l2) = (levels.clone(), levels.clone());
+    levels.into_iter()
+        .flat_map(move |r| l1.clone().into_iter().map(move |g| (r, g)))
+        .flat_map(move |(r, g)| l2.clone().into_iter().map(move |b| [r, g, b]))
+}
+
+fn unit([r, g, b]: [u8; 3]) -> [f32; 3] {
+    [r as f32 / 255.0, g as f32 / 255.0, b as f32 / 255.0]
+}
+
+fn to_u8(rgb: [f32; 3]) -> [u8; 3] {
+    [u8::from_unit(rgb[0]), u8::from_unit(rgb[1]), u8::from_unit(rgb[2])]
+}
+
+fn image_of(colours: &[[u8; 3]]) -> OwnedImage<Rgba8> {
+    let pixels: Vec<u8> = colours.iter().enumerate()
+        .flat_map(|(i, &[r, g, b])| vec![r, g, b, (i * 37) as u8])
+        .collect();
+    OwnedImage::new(Dimensions { width: colours.len() as u32, height: 1 }, pixels.into_boxed_slice()).unwrap()
+}
+
+fn assert_close(a: [f32; 3], b: [f32; 3], tolerance: f32) {
+    for (x, y) in a.iter().zip(&b) {
+        assert!((x - y).abs() <= tolerance, "{:?} != {:?}", a, b);
+    }
+}
+
+/*************************************** Round Trips **************************************************/
+/******************************************************************************************************/
+
+#[test]
+fn rgb8_survives_hsv_round_trip() {
+    for rgb in rgb8_colours(3) {
+        let hsv = Hsv::from_rgb(unit(rgb));
+        assert!(hsv.h >= 0.0 && hsv.h < 360.0 && (0.0..=1.0).contains(&hsv.s) && (0.0..=1.0).contains(&hsv.v));
+        assert_eq!(to_u8(hsv.to_rgb()), rgb);
+    }
+}
+
+#[test]
+fn rgb8_survives_hsl_round_trip() {
+    for rgb in rgb8_colours(3) {
+        let hsl = Hsl::from_rgb(unit(rgb));
+        assert!(hsl.h >= 0.0 && hsl.h < 360.0 && (0.0..=1.0).contains(&hsl.s) && (0.0..=1.0).contains(&hsl.l),
+                "{:?} gave {:?}", rgb, hsl);
+        assert_eq!(to_u8(hsl.to_rgb()), rgb);
+    }
+}
+
+#[test]
+fn float_round_trips_are_nearly_exact() {
+    for rgb in rgb8_colours(17) {
+        let rgb = unit(rgb);
+        assert_close(Hsv::from_rgb(rgb).to_rgb(), rgb, 1e-6);
+        assert_close(Hsl::from_rgb(rgb).to_rgb(), rgb, 1e-6);
+    }
+}
+
+#[test]
+fn known_colours_convert_as_expected() {
+    let cases = [
+        ([1.0, 0.0, 0.0], Hsv { h: 0.0, s: 1.0, v: 1.0 }, Hsl { h: 0.0, s: 1.0, l: 0.5 }),
+        ([0.0, 1.0, 0.0], Hsv { h: 120.0, s: 1.0, v: 1.0 }, Hsl { h: 120.0, s: 1.0, l: 0.5 }),
+        ([0.0, 0.0, 0.5], Hsv { h: 240.0, s: 1.0, v: 0.5 }, Hsl { h: 240.0, s: 1.0, l: 0.25 }),
+        ([1.0, 1.0, 0.0], Hsv { h: 60.0, s: 1.0, v: 1.0 }, Hsl { h: 60.0, s: 1.0, l: 0.5 }),
+        ([1.0, 0.0, 1.0], Hsv { h: 300.0, s: 1.0, v: 1.0 }, Hsl { h: 300.0, s: 1.0, l: 0.5 }),
+        ([0.5, 0.5, 0.5], Hsv { h: 0.0, s: 0.0, v: 0.5 }, Hsl { h: 0.0, s: 0.0, l: 0.5 }),
+        ([0.0, 0.0, 0.0], Hsv { h: 0.0, s: 0.0, v: 0.0 }, Hsl { h: 0.0, s: 0.0, l: 0.0 }),
+        ([1.0, 1.0, 1.0], Hsv { h: 0.0, s: 0.0, v: 1.0 }, Hsl { h: 0.0, s: 0.0, l: 1.0 }),
+    ];
+    for &(rgb, hsv, hsl) in &cases {
+        assert_eq!(Hsv::from_rgb(rgb), hsv);
+        assert_eq!(Hsl::from_rgb(rgb), hsl);
+        assert_close(hsv.to_rgb(), rgb, 1e-6);
+        assert_close(hsl.to_rgb(), rgb, 1e-6);
+    }
+    // hues wrap round the wheel
+    assert_close(Hsl { h: 480.0, s: 1.0, l: 0.5 }.to_rgb(), [0.0, 1.0, 0.0], 1e-6);
+    assert_close(Hsv { h: -120.0, s: 1.0, v: 1.0 }.to_rgb(), [0.0, 0.0, 1.0], 1e-6);
+}
+
+/**************************************** Operations **************************************************/
+/******************************************************************************************************/
+
+#[test]
+fn hue_rotations_undo_each_other() {
+    let colours: Vec<_> = rgb8_colours(15).collect();
+    let image = image_of(&colours);
+    for &degrees in &[0.0, 30.0, 120.0, 200.0, 360.0, -45.0] {
+        let back = image.hue_rotate(degrees).hue_rotate(-degrees);
+        for (a, b) in back.pixels().iter().zip(image.pixels().iter()) {
+            assert!((*a as i32 - *b as i32).abs() <= 1, "rotating by {} drifted", degrees);
+        }
+    }
+    assert_eq!(image.hue_rotate(360.0).pixels(), image.pixels());
+}
+
+#[test]
+fn hue_rotation_cycles_primaries() {
+    let mut image = image_of(&[[255, 0, 0], [0, 255, 0], [0, 0, 255], [90, 90, 90]]);
+    image.hue_rotate_mut(120.0);
+    assert_eq!(&image.pixels()[..], &[0, 255, 0, 0, 0, 0, 255, 37, 255, 0, 0, 74, 90, 90, 90, 111][..]);
+}
+
+#[test]
+fn saturation_keeps_lightness_and_alpha() {
+    let colours: Vec<_> = rgb8_colours(15).collect();
+    let image = image_of(&colours);
+    let grey = image.desaturate(1.0);
+    assert_eq!(grey.pixels(), image.saturate(0.0).pixels());
+    for ((rgb, out), original) in colours.iter().zip(grey.pixels().chunks(4)).zip(image.pixels().chunks(4)) {
+        let l = Hsl::from_rgb(unit(*rgb)).l;
+        assert_eq!(&out[..3], &[u8::from_unit(l); 3][..]);
+        assert_eq!(out[3], original[3]);
+    }
+
+    assert_eq!(image.saturate(1.0).pixels(), image.pixels());
+    let vivid = image.saturate(3.0);
+    for (out, rgb) in vivid.pixels().chunks(4).zip(&colours) {
+        let (before, after) = (Hsl::from_rgb(unit(*rgb)), Hsl::from_rgb(unit([out[0], out[1], out[2]])));
+        assert!((after.l - before.l).abs() <= 1.0 / 255.0);
+        assert!(after.s + 0.02 >= (before.s * 3.0).min(1.0) || before.l < 0.02 || before.l > 0.98);
+    }
+}
+
+#[test]
+fn colorize_sets_hue_and_saturation() {
+    let colours: Vec<_> = rgb8_colours(15).collect();
+    let mut image = image_of(&colours);
+    image.colorize_mut(200.0, 0.6);
+    for (out, rgb) in image.pixels().chunks(4).zip(&colours) {
+        let expected = Hsl { h: 200.0, s: 0.6, l: Hsl::from_rgb(unit(*rgb)).l }.to_rgb();
+        assert_eq!(&out[..3], &to_u8(expected)[..]);
+    }
+}
+
+#[test]
+fn operations_work_on_other_layouts() {
+    let image = image_of(&[[200, 40, 10], [10, 120, 240]]).converted::<Rgba32F>();
+    let rotated = image.hue_rotate(90.0);
+    for (a, b) in image.pixels().chunks(4).zip(rotated.pixels().chunks(4)) {
+        let (before, after) = (Hsv::from_rgb([a[0], a[1], a[2]]), Hsv::from_rgb([b[0], b[1], b[2]]));
+        assert!(((after.h - before.h).rem_euclid(360.0) - 90.0).abs() < 1e-3);
+        assert!((after.s - before.s).abs() < 1e-5 && (after.v - before.v).abs() < 1e-5);
+        assert_eq!(a[3], b[3]);
+    }
+
+    // luma has no hue to turn, and a colourless tint keeps it as it is
+    let luma = image.converted::<Luma8>();
+    assert_eq!(luma.hue_rotate(123.0).pixels(), luma.pixels());
+    assert_eq!(luma.colorize(10.0, 0.0).pixels(), luma.pixels());
+}
+
+/*************************************** Greyscale ****************************************************/
+/******************************************************************************************************/
+
+#[test]
+fn greyscale_methods_weigh_channels() {
+    let orange = [1.0, 0.5, 0.0];
+    let cases = [
+        (GreyscaleMethod::Rec601, 0.299 + 0.587 * 0.5),
+        (GreyscaleMethod::Rec709, 0.2126 + 0.7152 * 0.5),
+        (GreyscaleMethod::Rec2100, 0.2627 + 0.6780 * 0.5),
+        (GreyscaleMethod::Average, 0.5),
+        (GreyscaleMethod::Lightness, 0.5),
+        (GreyscaleMethod::Red, 1.0),
+        (GreyscaleMethod::Green, 0.5),
+        (GreyscaleMethod::Blue, 0.0),
+    ];
+    for &(method, expected) in &cases {
+        assert!((method.grey(orange) - expected).abs() < 1e-6, "{:?}", method);
+        // greys stay exactly as they are
+        assert_eq!(method.grey([0.3, 0.3, 0.3]), 0.3);
+    }
+
+    // pure green is 71.5% as bright as white in linear light, far more than its Rec.709 weight
+    let green = GreyscaleMethod::LinearLuminance.grey([0.0, 1.0, 0.0]);
+    assert_eq!(u8::from_unit(green), 220);
+    assert_eq!(GreyscaleMethod::LinearLuminance.grey([0.5, 0.5, 0.5]), 0.5);
+}
+
+#[test]
+fn greyscale_rounds_to_nearest() {
+    // Rec.601 of (255, 255, 0) is 225.675, which truncation would store as 225
+    let image = image_of(&[[255, 255, 0], [0, 0, 255], [10, 200, 30]]);
+    let grey = image.greyscale();
+    assert_eq!(grey.pixels(), image.greyscale_by(GreyscaleMethod::Rec601).pixels());
+    assert_eq!(&grey.pixels()[..], &[226, 226, 226, 0, 29, 29, 29, 37, 124, 124, 124, 74][..]);
+}
+
+#[test]
+fn luma_output_has_one_channel() {
+    let colours: Vec<_> = rgb8_colours(15).collect();
+    let image = image_of(&colours);
+    for &method in &[GreyscaleMethod::Rec709, GreyscaleMethod::Lightness, GreyscaleMethod::LinearLuminance] {
+        let grey = image.greyscale_by(method);
+        let luma = image.luma::<Luma8>(method);
+        assert_eq!(*luma.dimensions(), *image.dimensions());
+        let reds: Vec<u8> = grey.pixels().chunks(4).map(|p| p[0]).collect();
+        assert_eq!(&luma.pixels()[..], &reds[..]);
+
+        let mut in_place = image_of(&colours);
+        in_place.greyscale_by_mut(method);
+        assert_eq!(in_place.pixels(), grey.pixels());
+    }
+
+    let with_alpha = image.luma::<LumaA8>(GreyscaleMethod::Average);
+    let alphas: Vec<u8> = image.pixels().chunks(4).map(|p| p[3]).collect();
+    assert_eq!(with_alpha.pixels().chunks(2).map(|p| p[1]).collect::<Vec<_>>(), alphas);
+}
+
+/************************************** Linear Light **************************************************/
+/******************************************************************************************************/
+
+#[test]
+fn rgb8_survives_linear_round_trip() {
+    let values: Vec<[u8; 3]> = (0..=255).map(|v| [v, 255 - v, v / 3]).collect();
+    let image = image_of(&values);
+    let linear = image.to_linear();
+    assert_eq!(linear.to_srgb::<Rgba8>().pixels(), image.pixels());
+
+    // the 8-bit table matches exact decoding of the same values
+    let exact = LinearImage::from_srgb(&image.converted::<Rgba32F>());
+    assert_eq!(linear.pixels(), exact.pixels());
+    for (pixel, &[r, ..]) in linear.pixels().chunks(4).zip(&values) {
+        assert_eq!(pixel[0], colour::srgb_to_linear(r as f32 / 255.0));
+    }
+    assert_eq!(colour::srgb_to_linear(0.5), 0.21404114);
+    assert!((colour::linear_to_srgb(colour::srgb_to_linear(0.7)) - 0.7).abs() < 1e-6);
+}
+
+#[test]
+fn blurring_in_linear_light_keeps_edges_bright() {
+    // a hard edge between red and green
+    let colours: Vec<[u8; 3]> = (0..16).map(|x| if x < 8 { [255, 0, 0] } else { [0, 255, 0] }).collect();
+    let image = image_of(&colours);
+    let encoded = image.blurred(3, EdgeMode::Clamp);
+    let mut linear = image_of(&colours);
+    linear.in_linear_light(|image| image.blur(3, EdgeMode::Clamp)).unwrap();
+
+    // mixing encoded values dims the middle to a muddy (128, 128, 0); light mixes to (188, 188, 0)
+    let middle = |image: &OwnedImage<Rgba8>| image.get_pixel(8, 0).unwrap();
+    let (muddy, bright) = (middle(&encoded), middle(&linear));
+    assert!(muddy.r < 150 && muddy.g < 150);
+    assert!(bright.r > 170 && bright.g > 170);
+    assert!(GreyscaleMethod::LinearLuminance.grey([bright.r as f32 / 255.0, bright.g as f32 / 255.0, 0.0])
+        > GreyscaleMethod::LinearLuminance.grey([muddy.r as f32 / 255.0, muddy.g as f32 / 255.0, 0.0]));
+    // alpha isn't light, and passes through
+    assert_eq!(linear.pixels().chunks(4).map(|p| p[3]).collect::<Vec<_>>(),
+               image.pixels().chunks(4).map(|p| p[3]).collect::<Vec<_>>());
+}
+
+#[test]
+fn linear_greyscale_matches_linear_luminance() {
+    let colours: Vec<_> = rgb8_colours(15).collect();
+    let image = image_of(&colours);
+    let mut linear = image.to_linear();
+    linear.greyscale_by_mut(GreyscaleMethod::Rec709);
+    let expected = image.greyscale_by(GreyscaleMethod::LinearLuminance);
+    for (a, b) in linear.to_srgb::<Rgba8>().pixels().iter().zip(expected.pixels().iter()) {
+        assert!((*a as i32 - *b as i32).abs() <= 1);
+    }
+}
+
+/*************************************** CIE Spaces ***************************************************/
+/******************************************************************************************************/
+
+fn assert_lab(lab: Lab, [l, a, b]: [f32; 3], tolerance: f32) {
+    assert!((lab.l - l).abs() <= tolerance && (lab.a - a).abs() <= tolerance && (lab.b - b).abs() <= tolerance,
+            "{:?} != {:?}", lab, [l, a, b]);
+}
+
+#[test]
+fn known_colours_in_lab() {
+    assert_lab(Lab::from_rgb([1.0, 1.0, 1.0]), [100.0, 0.0, 0.0], 0.01);
+    assert_lab(Lab::from_rgb([0.0, 0.0, 0.0]), [0.0, 0.0, 0.0], 0.01);
+    assert_lab(Lab::from_rgb([1.0, 0.0, 0.0]), [53.24, 80.09, 67.20], 0.02);
+    assert_lab(Lab::from_rgb([0.0, 1.0, 0.0]), [87.73, -86.18, 83.18], 0.02);
+    assert_lab(Lab::from_rgb([0.0, 0.0, 1.0]), [32.30, 79.19, -107.86], 0.02);
+
+    let white = Xyz::from_rgb([1.0, 1.0, 1.0]);
+    assert!((white.x - 0.95047).abs() < 1e-4 && (white.y - 1.0).abs() < 1e-4 && (white.z - 1.08883).abs() < 1e-4);
+
+    let red = Lch::from_pixel(Rgba8 { r: 255, g: 0, b: 0, alpha: 10 });
+    assert!((red.c - 104.55).abs() < 0.02 && (red.h - 40.0).abs() < 0.05);
+    assert_eq!(Lch::from_lab(Lab { l: 50.0, a: 0.0, b: 0.0 }).h, 0.0);
+}
+
+#[test]
+fn rgb8_survives_lab_round_trip() {
+    for rgb in rgb8_colours(5) {
+        let pixel = Rgba8 { r: rgb[0], g: rgb[1], b: rgb[2], alpha: 255 };
+        assert_eq!(Lab::from_pixel(pixel).to_pixel::<Rgba8>(), pixel);
+        assert_eq!(Lch::from_pixel(pixel).to_pixel::<Rgba8>(), pixel);
+        assert_eq!(to_u8(Xyz::from_rgb(unit(rgb)).to_rgb()), rgb);
+    }
+}
+
+#[test]
+fn ciede2000_matches_published_pairs() {
+    // from Sharma, Wu and Dalal's test data for the CIEDE2000 formula
+    let pairs = [
+        ([50.0, 2.6772, -79.7751], [50.0, 0.0, -82.7485], 2.0425),
+        ([50.0, 3.1571, -77.2803], [50.0, 0.0, -82.7485], 2.8615),
+        ([50.0, 2.8361, -74.0200], [50.0, 0.0, -82.7485], 3.4412),
+        ([50.0, 0.0, 0.0], [50.0, -1.0, 2.0], 2.3669),
+        ([50.0, -1.0, 2.0], [50.0, 0.0, 0.0], 2.3669),
+        ([50.0, 2.5, 0.0], [73.0, 25.0, -18.0], 27.1492),
+        ([50.0, 2.5, 0.0], [61.0, -5.0, 29.0], 22.8977),
+        ([50.0, 2.5, 0.0], [56.0, -27.0, -3.0], 31.9030),
+        ([50.0, 2.5, 0.0], [58.0, 24.0, 15.0], 19.4535),
+        ([60.2574, -34.0099, 36.2677], [60.4626, -34.1751, 39.4387], 1.2644),
+        ([63.0109, -31.0961, -5.8663], [62.8187, -29.7946, -4.0864], 1.2630),
+        ([2.0776, 0.0795, -1.1350], [0.9033, -0.0636, -0.5514], 0.9082),
+    ];
+    let lab = |[l, a, b]: [f32; 3]| Lab { l, a, b };
+    for &(a, b, expected) in &pairs {
+        let delta = colour::delta_e2000(&lab(a), &lab(b));
+        assert!((delta - expected).abs() < 1e-4, "{:?} vs {:?}: {} != {}", a, b, delta, expected);
+        assert_eq!(DeltaE::Ciede2000.between(&lab(a), &lab(b)), delta);
+    }
+    assert_eq!(colour::delta_e76(&lab([50.0, 0.0, 0.0]), &lab([53.0, 4.0, 0.0])), 5.0);
+}
+
+#[test]
+fn delta_e_maps_compare_images() {
+    let colours: Vec<_> = rgb8_colours(15).collect();
+    let image = image_of(&colours);
+    assert_eq!(colour::mean_delta_e(&image, &image, DeltaE::Ciede2000).unwrap(), 0.0);
+
+    // shift one pixel; the rest stay identical, and alpha doesn't count
+    let mut shifted = image.map_pixels(|p| Rgba8 { alpha: 0, ..p });
+    shifted.crop_mut(3, 0, Dimensions { width: 1, height: 1 }).unwrap()
+        .map_pixels_in_place(|p| Rgba8 { r: p.r ^ 0x80, ..p });
+    let map = colour::delta_e_map(&image, &shifted, DeltaE::Cie76).unwrap();
+    assert_eq!(*map.dimensions(), *image.dimensions());
+    let expected = colour::delta_e76(&Lab::from_pixel(image.get_pixel(3, 0).unwrap()),
+                                     &Lab::from_pixel(shifted.get_pixel(3, 0).unwrap()));
+    assert!(expected > 10.0);
+    for (x, _, delta) in map.pixels_iter() {
+        assert_eq!(delta.luma, if x == 3 { expected } else { 0.0 });
+    }
+    let mean = colour::mean_delta_e(&image, &shifted.converted::<Rgb8>(), DeltaE::Cie76).unwrap();
+    assert!((mean - expected / colours.len() as f32).abs() < 1e-4);
+
+    let smaller = image.crop(0, 0, Dimensions { width: 2, height: 1 }).unwrap();
+    assert!(matches!(colour::mean_delta_e(&image, &smaller, DeltaE::Cie76), Err(ImageError::SizeMismatch { .. })));
+}
@@ -0,0 +1,16 @@
+//! Helpers shared by the integration tests.
+
+use image_processor::{
+    OwnedImage::OwnedImage,
+    utils::Dimensions,
+    pixel::*,
+};
+
+/// An 8-bit RGBA image with each pixel's channels given by `pixel`.
+pub fn rgba_image(width: u32, height: u32, pixel: impl Fn(u32, u32) -> [u8; 4]) -> OwnedImage<Rgba8> {
+    let pixels: Vec<u8> = (0..height)
+        .flat_map(|y| (0..width).map(move |x| (x, y)))
+        .flat_map(|(x, y)| pixel(x, y).to_vec())
+        .collect();
+    OwnedImage::new(Dimensions { width, height }, pixels.into_boxed_slice()).unwrap()
+}
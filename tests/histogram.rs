@@ -0,0 +1,282 @@
+//! Histograms of whole images and regions, and the equalization, matching and
+//! levels adjustments built on them.
+
+use image_processor::{
+    traits::*,
+    OwnedImage::OwnedImage,
+    utils::Dimensions,
+    pixel::*,
+    histogram::{self, Bins, Clahe, ClaheChannels},
+};
+
+mod common;
+use common::rgba_image;
+
+
+/// A grey image holding `values` in order, in one row.
+fn grey_image(values: &[u8]) -> OwnedImage<Rgba8> {
+    rgba_image(values.len() as u32, 1, |x, _| {
+        let v = values[x as usize];
+        [v, v, v, 255]
+    })
+}
+
+fn range(bins: &Bins) -> (usize, usize) {
+    let used: Vec<usize> = (0..bins.len()).filter(|&i| bins[i] > 0).collect();
+    (used[0], used[used.len() - 1])
+}
+
+/**************************************** Histograms **************************************************/
+/******************************************************************************************************/
+
+#[test]
+fn histogram_counts_every_channel() {
+    let image = rgba_image(40, 30, |x, y| [x as u8, y as u8, 200, if x < 10 { 0 } else { 255 }]);
+    let histogram = image.histogram();
+
+    assert_eq!(histogram.total(), 1200);
+    assert!((0..40).all(|v| histogram.red[v] == 30));
+    assert!((0..30).all(|v| histogram.green[v] == 40));
+    assert_eq!(histogram.blue[200], 1200);
+    assert_eq!((histogram.alpha[0], histogram.alpha[255]), (300, 900));
+    assert_eq!(histogram.luma.iter().sum::<u64>(), 1200);
+}
+
+#[test]
+fn grey_layouts_count_grey_as_every_colour() {
+    let image: OwnedImage<Luma8> = grey_image(&[0, 10, 10, 255]).converted();
+    let histogram = image.histogram();
+
+    for bins in [&histogram.red, &histogram.green, &histogram.blue, &histogram.luma] {
+        assert_eq!((bins[0], bins[10], bins[255]), (1, 2, 1));
+    }
+    assert_eq!(histogram.alpha[255], 4);
+
+    // finer channels share the same 256 bins
+    let wide: OwnedImage<Luma16> = image.converted();
+    assert_eq!(wide.histogram(), histogram);
+}
+
+#[test]
+fn histogram_of_a_crop_counts_only_the_crop() {
+    let image = rgba_image(50, 40, |x, y| [(x * 5) as u8, (y * 6) as u8, (x + y) as u8, 255]);
+    let dims = Dimensions { width: 17, height: 23 };
+    let crop = image.crop(9, 11, dims).unwrap();
+
+    assert_eq!(crop.histogram(), crop.map_pixels(|pixel| pixel).histogram());
+    assert_eq!(crop.histogram().total(), 17 * 23);
+}
+
+#[test]
+fn percentiles_skip_empty_bins() {
+    let histogram = grey_image(&[20, 20, 30, 40, 250]).histogram();
+
+    assert_eq!(histogram::percentile(&histogram.luma, 0.0), 20);
+    assert_eq!(histogram::percentile(&histogram.luma, 0.5), 30);
+    assert_eq!(histogram::percentile(&histogram.luma, 0.8), 40);
+    assert_eq!(histogram::percentile(&histogram.luma, 1.0), 250);
+}
+
+/************************************** Equalization **************************************************/
+/******************************************************************************************************/
+
+#[test]
+fn equalization_spreads_values_over_the_range() {
+    let values: Vec<u8> = (0..64).map(|i| 100 + i / 4).collect();
+    let equalized = grey_image(&values).equalize();
+    let histogram = equalized.histogram();
+
+    assert_eq!(range(&histogram.luma), (0, 255));
+    // sixteen equally common values land evenly apart
+    assert!((0..16).all(|i| histogram.luma[i * 17] == 4));
+    assert!(equalized.pixels_iter().all(|(_, _, Rgba { r, g, b, alpha })| r == g && g == b && alpha == 255));
+}
+
+#[test]
+fn equalizing_a_flat_image_keeps_it() {
+    let image = rgba_image(8, 8, |_, _| [90, 120, 30, 77]);
+    assert_eq!(image.equalize().pixels(), image.pixels());
+}
+
+#[test]
+fn equalizing_a_crop_leaves_the_rest() {
+    let mut image = rgba_image(30, 30, |x, y| [(100 + x) as u8, (100 + y) as u8, 120, 255]);
+    let original = image.pixels();
+    let dims = Dimensions { width: 10, height: 10 };
+    let expected = image.crop(5, 5, dims).unwrap().equalize();
+
+    image.crop_mut(5, 5, dims).unwrap().equalize_mut();
+    for (x, y, pixel) in image.pixels_iter() {
+        if (5..15).contains(&x) && (5..15).contains(&y) {
+            assert_eq!(pixel, expected.get_pixel(x - 5, y - 5).unwrap());
+        } else {
+            assert_eq!(pixel, Rgba8::from_channels(&original[((y * 30 + x) * 4) as usize..][..4]));
+        }
+    }
+}
+
+/*************************************** Matching *****************************************************/
+/******************************************************************************************************/
+
+#[test]
+fn matching_takes_the_target_shape() {
+    let source = grey_image(&(0..=255).collect::<Vec<u8>>());
+    let target = grey_image(&(0..=255).map(|v| 64 + v / 2).collect::<Vec<u8>>());
+
+    let matched = source.match_histogram(&target.histogram());
+    assert_eq!(matched.histogram(), target.histogram());
+}
+
+#[test]
+fn matching_its_own_histogram_keeps_an_image() {
+    let image = rgba_image(20, 20, |x, y| [(x * 9) as u8, (y * 3 + 40) as u8, ((x * y) % 200) as u8, 255]);
+    assert_eq!(image.match_histogram(&image.histogram()).pixels(), image.pixels());
+}
+
+#[test]
+fn matching_works_per_channel() {
+    let source = rgba_image(16, 16, |x, y| [(x * 16) as u8, (y * 16) as u8, 128, 255]);
+    let target = rgba_image(16, 16, |x, y| [(y * 4) as u8, 200 + x as u8, (x * y) as u8, 255]).histogram();
+
+    let matched = source.match_histogram(&target).histogram();
+    assert_eq!(range(&matched.red), range(&target.red));
+    assert_eq!(range(&matched.green), range(&target.green));
+}
+
+/*************************************** Auto Levels **************************************************/
+/******************************************************************************************************/
+
+#[test]
+fn auto_levels_stretch_each_channel() {
+    let image = rgba_image(32, 4, |x, _| [50 + x as u8, 100 + 2 * x as u8, 7, 99]);
+    let levelled = image.auto_levels(0.0);
+    let histogram = levelled.histogram();
+
+    assert_eq!(range(&histogram.red), (0, 255));
+    assert_eq!(range(&histogram.green), (0, 255));
+    // a channel with one value has nothing to stretch
+    assert_eq!(range(&histogram.blue), (7, 7));
+    assert_eq!(histogram.alpha[99], 128);
+}
+
+#[test]
+fn auto_levels_clip_outliers() {
+    let mut values: Vec<u8> = (0..200).map(|i| 100 + (i % 50) as u8).collect();
+    values[0] = 0;
+    values[1] = 255;
+    let levelled = grey_image(&values).auto_levels(0.01);
+
+    // the outliers clip, and the bulk of the values fill the range
+    assert_eq!(levelled.get_pixel(0, 0).unwrap().r, 0);
+    assert_eq!(levelled.get_pixel(1, 0).unwrap().r, 255);
+    assert_eq!(levelled.get_pixel(50, 0).unwrap().r, 0);
+    assert_eq!(levelled.get_pixel(49, 0).unwrap().r, 255);
+}
+
+#[test]
+fn auto_levels_work_on_wide_channels() {
+    let values: Vec<u16> = (0..100).map(|i| 20_000 + i * 300).collect();
+    let image = OwnedImage::<Luma16>::new(Dimensions { width: 100, height: 1 }, values.into_boxed_slice()).unwrap();
+    let levelled = image.auto_levels(0.0);
+
+    let lumas: Vec<u16> = levelled.pixels_iter().map(|(_, _, Luma { luma })| luma).collect();
+    assert!(lumas[0] < 300 && lumas[99] > u16::MAX - 300);
+    assert!(lumas.windows(2).all(|pair| pair[0] < pair[1]));
+}
+
+/********************************** Adaptive Equalization *********************************************/
+/******************************************************************************************************/
+
+/// Dark, low-contrast noise on the left, bright on the right.
+fn two_halves() -> OwnedImage<Rgba8> {
+    rgba_image(64, 32, |x, y| {
+        let noise = ((x * 7 + y * 13) % 11) as u8;
+        let v = if x < 32 { 10 + noise } else { 200 + 4 * noise };
+        [v, v, v / 2, 255]
+    })
+}
+
+#[test]
+fn clahe_without_contrast_keeps_the_image() {
+    let image = two_halves();
+    let clahe = Clahe { clip_limit: 0.0, ..Clahe::default() };
+    assert_eq!(image.clahe(clahe).pixels(), image.pixels());
+}
+
+#[test]
+fn unclipped_clahe_on_one_tile_is_plain_equalization() {
+    let image: OwnedImage<Rgba8> = two_halves().greyscale();
+    let clahe = Clahe { tiles: (1, 1), clip_limit: f32::INFINITY, channels: ClaheChannels::Luma };
+    assert_eq!(image.clahe(clahe).pixels(), image.equalize().pixels());
+}
+
+#[test]
+fn clahe_brings_out_dark_detail() {
+    let image = two_halves();
+    let dims = Dimensions { width: 24, height: 32 };
+    let spread = |image: &OwnedImage<Rgba8>| {
+        let (low, high) = range(&image.crop(0, 0, dims).unwrap().histogram().green);
+        high - low
+    };
+
+    let adapted = image.clahe(Clahe { tiles: (4, 2), clip_limit: 4.0, ..Clahe::default() });
+    assert_eq!(spread(&image), 10);
+    assert!(spread(&adapted) > 40, "{}", spread(&adapted));
+}
+
+#[test]
+fn luma_clahe_keeps_chroma_and_alpha() {
+    let image = rgba_image(40, 40, |x, y| [(60 + x) as u8, (40 + y) as u8, 50, (x * 6) as u8]);
+    let adapted = image.clahe(Clahe::default());
+
+    for ((_, _, before), (_, _, after)) in image.pixels_iter().zip(adapted.pixels_iter()) {
+        let (r, g, b) = (after.r as i32, after.g as i32, after.b as i32);
+        assert!((r - g - (before.r as i32 - before.g as i32)).abs() <= 1);
+        assert!((g - b - (before.g as i32 - before.b as i32)).abs() <= 1);
+        assert_eq!(after.alpha, before.alpha);
+    }
+}
+
+#[test]
+fn per_channel_clahe_stretches_each_channel() {
+    let image = rgba_image(32, 32, |x, y| [100 + (x % 8) as u8, 30 + (y % 4) as u8, 90, 255]);
+    let clahe = Clahe { tiles: (2, 2), clip_limit: f32::INFINITY, channels: ClaheChannels::PerChannel };
+    let histogram = image.clahe(clahe).histogram();
+
+    assert_eq!(range(&histogram.red), (0, 255));
+    assert_eq!(range(&histogram.green), (0, 255));
+    assert_eq!(range(&histogram.blue), (90, 90));
+}
+
+#[test]
+fn clahe_blends_smoothly_between_tiles() {
+    let image = rgba_image(128, 128, |x, y| {
+        let v = ((x + y) * 255 / 254) as u8;
+        [v, v, v, 255]
+    });
+    let adapted = image.clahe(Clahe { tiles: (4, 4), ..Clahe::default() });
+
+    for y in 0..128 {
+        for x in 1..128 {
+            let step = adapted.get_pixel(x, y).unwrap().r as i32 - adapted.get_pixel(x - 1, y).unwrap().r as i32;
+            assert!(step.abs() <= 6, "{} at ({}, {})", step, x, y);
+        }
+    }
+}
+
+#[test]
+fn clahe_handles_regions_and_tiny_images() {
+    let mut image = two_halves();
+    let original = two_halves();
+    let dims = Dimensions { width: 3, height: 2 };
+    let expected = image.crop(30, 10, dims).unwrap().clahe(Clahe::default());
+
+    image.crop_mut(30, 10, dims).unwrap().clahe_mut(Clahe::default());
+    for (x, y, pixel) in image.pixels_iter() {
+        if (30..33).contains(&x) && (10..12).contains(&y) {
+            assert_eq!(pixel, expected.get_pixel(x - 30, y - 10).unwrap());
+        } else {
+            assert_eq!(pixel, original.get_pixel(x, y).unwrap());
+        }
+    }
+}
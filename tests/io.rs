@@ -0,0 +1,238 @@
+//! Encoding images of every pixel layout and reading them back, and importing decoded images.
+
+use image_processor::{
+    traits::*,
+    OwnedImage::OwnedImage,
+    utils::{self, Dimensions, ImageError, OutputFormat},
+    pixel::*,
+};
+use image::{DynamicImage, ImageBuffer};
+
+const FORMATS: [OutputFormat; 11] = [
+    OutputFormat::Png,
+    OutputFormat::Jpeg { quality: 95 },
+    OutputFormat::Bmp,
+    OutputFormat::Gif,
+    OutputFormat::Tiff,
+    OutputFormat::Tga,
+    OutputFormat::WebP,
+    OutputFormat::Qoi,
+    OutputFormat::Pnm,
+    OutputFormat::Ico,
+    OutputFormat::Farbfeld,
+];
+
+/// Assorted colours, with every third pixel opaque and the rest partly transparent.
+fn source() -> OwnedImage<Rgba8> {
+    let pixels: Vec<u8> = (0..24u32)
+        .flat_map(|i| vec![(i * 37 % 256) as u8, (i * 91 % 256) as u8, (255 - i * 10) as u8,
+                           if i % 3 == 0 { 255 } else { (60 + i * 7) as u8 }])
+        .collect();
+    OwnedImage::new(Dimensions { width: 6, height: 4 }, pixels.into_boxed_slice()).unwrap()
+}
+
+/// Largest colour and alpha differences between two 8-bit RGBA images.
+fn max_errors(a: &OwnedImage<Rgba8>, b: &OwnedImage<Rgba8>) -> (i32, i32) {
+    a.pixels().chunks(4).zip(b.pixels().chunks(4)).fold((0, 0), |(colour, alpha), (p, q)| {
+        let difference = |i: usize| (p[i] as i32 - q[i] as i32).abs();
+        (colour.max(difference(0)).max(difference(1)).max(difference(2)), alpha.max(difference(3)))
+    })
+}
+
+/// Writes `P`'s version of the source in every format and checks what reads back.
+fn assert_round_trips<P: PixelType>() {
+    let image: OwnedImage<P> = source().converted();
+    let expected = image.converted::<Rgba8>();
+    for &format in FORMATS.iter() {
+        let mut bytes = Vec::new();
+        utils::write_image(&image, &mut bytes, format)
+            .unwrap_or_else(|error| panic!("writing {:?}: {}", format, error));
+        // TGA has no signature to sniff
+        let decoded: OwnedImage<Rgba8> = utils::load_from_memory_with_format(&bytes, format.image_format()).unwrap();
+        assert_eq!(*decoded.dimensions(), *image.dimensions());
+
+        let (colour, alpha) = max_errors(&decoded, &expected);
+        match format {
+            // lossy, and without alpha
+            OutputFormat::Jpeg { .. } => assert!(colour <= 24, "JPEG is off by {}", colour),
+            // alpha is all or nothing
+            OutputFormat::Gif => assert_eq!(colour, 0),
+            _ => assert_eq!((colour, alpha), (0, 0), "{:?}", format),
+        }
+    }
+}
+
+/*************************************** Round Trips **************************************************/
+/******************************************************************************************************/
+
+#[test]
+fn every_layout_can_be_written_in_every_format() {
+    assert_round_trips::<Luma8>();
+    assert_round_trips::<Luma16>();
+    assert_round_trips::<Luma32F>();
+    assert_round_trips::<LumaA8>();
+    assert_round_trips::<LumaA16>();
+    assert_round_trips::<LumaA32F>();
+    assert_round_trips::<Rgb8>();
+    assert_round_trips::<Rgb16>();
+    assert_round_trips::<Rgb32F>();
+    assert_round_trips::<Rgba8>();
+    assert_round_trips::<Rgba16>();
+    assert_round_trips::<Rgba32F>();
+}
+
+#[test]
+fn deep_formats_keep_sixteen_bits() {
+    // values that don't survive a trip through 8 bits
+    let pixels: Vec<u16> = (0..24u32).flat_map(|i| vec![i as u16 * 2711 + 3, 65535 - i as u16 * 977, 1000 + i as u16, 40000 + i as u16]).collect();
+    let deep = OwnedImage::<Rgba16>::new(Dimensions { width: 6, height: 4 }, pixels.into_boxed_slice()).unwrap();
+    for &format in &[OutputFormat::Png, OutputFormat::Tiff, OutputFormat::Pnm, OutputFormat::Farbfeld] {
+        let mut bytes = Vec::new();
+        utils::write_image(&deep, &mut bytes, format).unwrap();
+        let (decoded, _): (OwnedImage<Rgba16>, _) = utils::load_from_memory(&bytes).unwrap();
+        assert_eq!(decoded.pixels(), deep.pixels(), "{:?}", format);
+    }
+
+    // floats are stored as 16-bit PNGs
+    let float = deep.converted::<Rgba32F>();
+    let mut bytes = Vec::new();
+    utils::write_image(&float, &mut bytes, OutputFormat::Png).unwrap();
+    let (decoded, _): (OwnedImage<Rgba16>, _) = utils::load_from_memory(&bytes).unwrap();
+    for (&a, &b) in decoded.pixels().iter().zip(deep.pixels().iter()) {
+        assert!((a as i32 - b as i32).abs() <= 1);
+    }
+}
+
+#[test]
+fn saved_files_load_back() {
+    fn save_and_load<P: PixelType>(name: &str) {
+        let image: OwnedImage<P> = source().converted();
+        let path = std::env::temp_dir().join(format!("image-processor-io-{}-{}.png", std::process::id(), name));
+        assert_eq!(utils::save_image(&image, &path).unwrap(), OutputFormat::Png);
+        let loaded: Result<OwnedImage<P>, _> = utils::import(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.unwrap().converted::<Rgba8>().pixels(), image.converted::<Rgba8>().pixels(), "{}", name);
+    }
+
+    save_and_load::<Luma8>("luma8");
+    save_and_load::<Luma16>("luma16");
+    save_and_load::<Luma32F>("luma32f");
+    save_and_load::<LumaA8>("lumaa8");
+    save_and_load::<LumaA16>("lumaa16");
+    save_and_load::<LumaA32F>("lumaa32f");
+    save_and_load::<Rgb8>("rgb8");
+    save_and_load::<Rgb16>("rgb16");
+    save_and_load::<Rgb32F>("rgb32f");
+    save_and_load::<Rgba8>("rgba8");
+    save_and_load::<Rgba16>("rgba16");
+    save_and_load::<Rgba32F>("rgba32f");
+}
+
+#[test]
+fn unrecognised_images_say_where_they_came_from() {
+    let bytes = b"not an image at all";
+    let path = std::env::temp_dir().join(format!("image-processor-io-{}-unknown.dat", std::process::id()));
+    std::fs::write(&path, bytes).unwrap();
+    let loaded: Result<OwnedImage<Rgba8>, _> = utils::import(&path);
+    std::fs::remove_file(&path).unwrap();
+    let error = match loaded {
+        Err(error) => error,
+        Ok(_) => panic!("decoded text as an image"),
+    };
+    assert!(error.to_string().contains(&*path.to_string_lossy()), "{}", error);
+    assert!(matches!(&error, ImageError::UnknownFileFormat { path: reported } if *reported == path), "{:?}", error);
+
+    // bytes in memory have no path to give
+    let loaded: Result<(OwnedImage<Rgba8>, _), _> = utils::load_from_memory(bytes);
+    assert!(matches!(loaded, Err(ImageError::UnknownFormat)));
+}
+
+/***************************************** Importing **************************************************/
+/******************************************************************************************************/
+
+const WIDTH: u32 = 3;
+const HEIGHT: u32 = 2;
+
+fn import<P: PixelType>(image: &DynamicImage) -> OwnedImage<P> {
+    let imported = OwnedImage::<P>::import(image.clone());
+    assert_eq!(*imported.dimensions(), Dimensions { width: WIDTH, height: HEIGHT });
+    assert_eq!(imported.pixels().len(), (WIDTH * HEIGHT) as usize * P::CHANNELS);
+    imported
+}
+
+fn to_u8(value: u16) -> u8 {
+    (value as f32 / 257.0).round() as u8
+}
+
+#[test]
+fn grey_sources_spread_over_colour() {
+    let values = [0u8, 17, 128, 200, 254, 255];
+    let grey = DynamicImage::ImageLuma8(ImageBuffer::from_raw(WIDTH, HEIGHT, values.to_vec()).unwrap());
+
+    assert_eq!(&*import::<Luma8>(&grey).pixels(), &values[..]);
+    let rgba: Vec<u8> = values.iter().flat_map(|&v| vec![v, v, v, 255]).collect();
+    assert_eq!(&*import::<Rgba8>(&grey).pixels(), &rgba[..]);
+    let deep: Vec<u16> = values.iter().flat_map(|&v| vec![v as u16 * 257, u16::MAX]).collect();
+    assert_eq!(&*import::<LumaA16>(&grey).pixels(), &deep[..]);
+    for (&imported, &v) in import::<Luma32F>(&grey).pixels().iter().zip(&values) {
+        assert!((imported - v as f32 / 255.0).abs() < 1e-6);
+    }
+}
+
+#[test]
+fn colour_sources_gain_opaque_alpha_or_collapse_to_luma() {
+    let values: Vec<u8> = vec![255, 0, 0, 0, 255, 0, 0, 0, 255, 10, 20, 30, 90, 90, 90, 255, 255, 255];
+    let colour = DynamicImage::ImageRgb8(ImageBuffer::from_raw(WIDTH, HEIGHT, values.clone()).unwrap());
+
+    assert_eq!(&*import::<Rgb8>(&colour).pixels(), &values[..]);
+    let rgba: Vec<u8> = values.chunks(3).flat_map(|p| vec![p[0], p[1], p[2], 255]).collect();
+    assert_eq!(&*import::<Rgba8>(&colour).pixels(), &rgba[..]);
+    let deep: Vec<u16> = values.iter().map(|&v| v as u16 * 257).collect();
+    assert_eq!(&*import::<Rgb16>(&colour).pixels(), &deep[..]);
+
+    // Rec. 709 luma, with greys unchanged
+    let luma = import::<Luma8>(&colour);
+    for (&imported, p) in luma.pixels().iter().zip(values.chunks(3)) {
+        let expected = 0.2126 * p[0] as f32 + 0.7152 * p[1] as f32 + 0.0722 * p[2] as f32;
+        assert!((imported as f32 - expected).abs() <= 1.0, "{:?} became {}", p, imported);
+    }
+    assert_eq!(&luma.pixels()[4..], &[90, 255]);
+}
+
+#[test]
+fn sixteen_bit_grey_with_alpha_keeps_both() {
+    let values: Vec<u16> = vec![0, 65535, 1000, 30000, 32896, 257, 65535, 0, 40000, 65000, 12345, 54321];
+    let source = DynamicImage::ImageLumaA16(ImageBuffer::from_raw(WIDTH, HEIGHT, values.clone()).unwrap());
+
+    assert_eq!(&*import::<LumaA16>(&source).pixels(), &values[..]);
+    let rgba: Vec<u8> = values.chunks(2).flat_map(|p| vec![to_u8(p[0]), to_u8(p[0]), to_u8(p[0]), to_u8(p[1])]).collect();
+    assert_eq!(&*import::<Rgba8>(&source).pixels(), &rgba[..]);
+    let luma: Vec<u16> = values.chunks(2).map(|p| p[0]).collect();
+    assert_eq!(&*import::<Luma16>(&source).pixels(), &luma[..]);
+    for (&imported, &v) in import::<Rgba32F>(&source).pixels().iter().skip(3).step_by(4).zip(values.iter().skip(1).step_by(2)) {
+        assert!((imported - v as f32 / 65535.0).abs() < 1e-6);
+    }
+}
+
+#[test]
+fn deep_and_float_sources_are_rescaled() {
+    let deep: Vec<u16> = (0..18).map(|i| i * 3851).collect();
+    let source = DynamicImage::ImageRgb16(ImageBuffer::from_raw(WIDTH, HEIGHT, deep.clone()).unwrap());
+    let rgba: Vec<u8> = deep.chunks(3).flat_map(|p| vec![to_u8(p[0]), to_u8(p[1]), to_u8(p[2]), 255]).collect();
+    assert_eq!(&*import::<Rgba8>(&source).pixels(), &rgba[..]);
+    assert_eq!(&*import::<Rgb16>(&source).pixels(), &deep[..]);
+
+    // floats outside 0.0-1.0 are clamped when stored in integers
+    let floats: Vec<f32> = vec![0.0, 0.5, 1.0, 0.25, -0.5, 1.5, 0.1, 0.2, 0.3, 0.9, 0.8, 0.7, 1.0, 1.0, 1.0, 0.0, 0.0, 0.0,
+                                0.4, 0.6, 0.2, 0.75, 0.01, 0.99];
+    let source = DynamicImage::ImageRgba32F(ImageBuffer::from_raw(WIDTH, HEIGHT, floats.clone()).unwrap());
+    assert_eq!(&*import::<Rgba32F>(&source).pixels(), &floats[..]);
+    let bytes: Vec<u8> = floats.iter().map(|&v| (v.clamp(0.0, 1.0) * 255.0).round() as u8).collect();
+    assert_eq!(&*import::<Rgba8>(&source).pixels(), &bytes[..]);
+    let words: Vec<u16> = floats.iter().map(|&v| (v.clamp(0.0, 1.0) * 65535.0).round() as u16).collect();
+    assert_eq!(&*import::<Rgba16>(&source).pixels(), &words[..]);
+    // alpha passes straight through
+    for (imported, p) in import::<LumaA32F>(&source).pixels().chunks(2).zip(floats.chunks(4)) {
+        assert_eq!(imported[1], p[3]);
+    }
+}
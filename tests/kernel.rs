@@ -0,0 +1,201 @@
+//! Convolution kernels: building them, normalising, bias and alpha handling.
+
+use image_processor::{
+    traits::*,
+    OwnedImage::OwnedImage,
+    utils::{Dimensions, ImageError},
+    pixel::*,
+    kernel::{AlphaMode, EdgeMode, Kernel},
+};
+
+fn luma_image(width: u32, height: u32, luma: impl Fn(u32, u32) -> f32) -> OwnedImage<Luma32F> {
+    let pixels: Vec<f32> = (0..height).flat_map(|y| (0..width).map(move |x| (x, y))).map(|(x, y)| luma(x, y)).collect();
+    OwnedImage::new(Dimensions { width, height }, pixels.into_boxed_slice()).unwrap()
+}
+
+fn rgba_row(pixels: &[[u8; 4]]) -> OwnedImage<Rgba8> {
+    let channels: Vec<u8> = pixels.iter().flatten().copied().collect();
+    OwnedImage::new(Dimensions { width: pixels.len() as u32, height: 1 }, channels.into_boxed_slice()).unwrap()
+}
+
+fn identity() -> Kernel {
+    Kernel::new(3, 3, vec![0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0]).unwrap()
+}
+
+/*************************************** Normalisation ************************************************/
+/******************************************************************************************************/
+
+#[test]
+fn kernels_need_odd_sides_and_matching_weights() {
+    assert!(matches!(Kernel::new(2, 3, vec![1.0; 6]), Err(ImageError::InvalidKernel { width: 2, height: 3, len: 6 })));
+    assert!(matches!(Kernel::new(3, 3, vec![1.0; 8]), Err(ImageError::InvalidKernel { len: 8, .. })));
+    assert!(matches!(Kernel::separable(vec![1.0; 4], vec![1.0]), Err(ImageError::InvalidKernel { .. })));
+    assert!(Kernel::new(1, 5, vec![1.0; 5]).is_ok());
+}
+
+#[test]
+fn normalised_weights_sum_to_one() {
+    let kernel = Kernel::new(3, 3, vec![2.0, 4.0, 2.0, 4.0, 8.0, 4.0, 2.0, 4.0, 2.0]).unwrap().normalized();
+    assert_eq!(kernel.weights(), &[0.0625, 0.125, 0.0625, 0.125, 0.25, 0.125, 0.0625, 0.125, 0.0625]);
+
+    // separable kernels normalise both factors, and still convolve in two passes
+    let separable = Kernel::separable(vec![1.0, 2.0, 1.0], vec![1.0, 2.0, 1.0]).unwrap().normalized();
+    assert!(separable.is_separable());
+    assert_eq!(separable.weights(), kernel.weights());
+    let image = luma_image(11, 9, |x, y| ((x * 7 + y * 3) % 10) as f32 / 10.0);
+    for edge in &[EdgeMode::Clamp, EdgeMode::Mirror, EdgeMode::Wrap, EdgeMode::Skip, EdgeMode::Crop,
+                  EdgeMode::Constant(Luma { luma: 0.75 })] {
+        let one_pass = image.convolved(&kernel, *edge);
+        let two_passes = image.convolved(&separable, *edge);
+        assert_eq!(one_pass.dimensions(), two_passes.dimensions());
+        for (a, b) in one_pass.pixels().iter().zip(two_passes.pixels().iter()) {
+            assert!((a - b).abs() < 1e-6, "{:?}", edge);
+        }
+    }
+
+    // so flat areas keep their brightness
+    let flat = luma_image(6, 5, |_, _| 0.4);
+    for value in flat.convolved(&Kernel::box_filter(2), EdgeMode::Clamp).pixels().iter() {
+        assert!((value - 0.4).abs() < 1e-6);
+    }
+}
+
+#[test]
+fn kernels_summing_to_zero_are_not_normalised() {
+    let edges = Kernel::edge_detect();
+    assert_eq!(edges.clone().normalized(), edges);
+    let flat = luma_image(5, 5, |_, _| 0.6);
+    assert!(flat.convolved(&edges, EdgeMode::Clamp).pixels().iter().all(|&value| value.abs() < 1e-6));
+}
+
+/****************************************** Edge Modes ************************************************/
+/******************************************************************************************************/
+
+fn edges() -> Vec<EdgeMode<Luma32F>> {
+    vec![EdgeMode::Clamp, EdgeMode::Mirror, EdgeMode::Wrap, EdgeMode::Constant(Luma { luma: 0.75 }), EdgeMode::Skip,
+         EdgeMode::Crop]
+}
+
+#[test]
+fn separable_kernels_match_their_expanded_form_at_every_edge() {
+    let image = luma_image(11, 9, |x, y| ((x * 7 + y * 3) % 10) as f32 / 10.0 + if x == y { 0.5 } else { 0.0 });
+    let kernels = [
+        // a blur, Sobel's edge detector, and a lopsided kernel that isn't normalised
+        Kernel::separable(vec![1.0, 2.0, 1.0], vec![1.0, 2.0, 1.0]).unwrap().normalized(),
+        Kernel::separable(vec![-1.0, 0.0, 1.0], vec![1.0, 2.0, 1.0]).unwrap(),
+        Kernel::separable(vec![1.0, 2.0, 0.5], vec![2.0, 1.0, 1.0, 0.5, 3.0]).unwrap(),
+    ];
+    for separable in kernels.iter() {
+        let expanded = Kernel::new(separable.width(), separable.height(), separable.weights().to_vec()).unwrap();
+        assert!(!expanded.is_separable());
+        for edge in edges() {
+            let two_passes = image.convolved(separable, edge);
+            let one_pass = image.convolved(&expanded, edge);
+            assert_eq!(two_passes.dimensions(), one_pass.dimensions());
+            for (a, b) in two_passes.pixels().iter().zip(one_pass.pixels().iter()) {
+                assert!((a - b).abs() < 1e-4, "{:?} with {:?}: {} against {}", separable.weights(), edge, a, b);
+            }
+        }
+    }
+
+    // the Sobel preset finds no edges in a flat image, even against a constant of the same colour
+    let flat = luma_image(4, 4, |_, _| 1.0);
+    let sobel = flat.convolved(&Kernel::sobel_x(), EdgeMode::Constant(Luma { luma: 1.0 }));
+    assert!(sobel.pixels().iter().all(|&value| (value - 0.5).abs() < 1e-6), "{:?}", sobel.pixels());
+}
+
+#[test]
+fn skipping_keeps_the_partial_response_of_zero_sum_kernels() {
+    // the 8 at the centre less one for each neighbour inside the image
+    let flat = luma_image(4, 3, |_, _| 1.0);
+    let edges = flat.convolved(&Kernel::edge_detect(), EdgeMode::Skip);
+    assert_eq!(&*edges.pixels(), &[5.0, 3.0, 3.0, 5.0, 3.0, 0.0, 0.0, 3.0, 5.0, 3.0, 3.0, 5.0]);
+
+    // which is what reading black outside gives
+    let image = luma_image(9, 7, |x, y| ((x * 5 + y * 3) % 8) as f32 / 8.0);
+    let black = EdgeMode::Constant(Luma { luma: 0.0 });
+    for kernel in &[Kernel::edge_detect(), Kernel::sobel_x(), Kernel::sobel_y()] {
+        assert_eq!(image.convolved(kernel, EdgeMode::Skip).pixels(), image.convolved(kernel, black).pixels());
+    }
+}
+
+/******************************************** Bias ****************************************************/
+/******************************************************************************************************/
+
+#[test]
+fn bias_is_added_to_colour_on_a_unit_scale() {
+    let image = luma_image(4, 3, |x, y| (x + y) as f32 / 10.0);
+    let biased = image.convolved(&identity().with_bias(0.25), EdgeMode::Clamp);
+    for (a, b) in biased.pixels().iter().zip(image.pixels().iter()) {
+        assert!((a - b - 0.25).abs() < 1e-6);
+    }
+
+    // integer channels scale it to their range and clip, and alpha is left alone
+    let bytes = rgba_row(&[[0, 100, 250, 30], [10, 20, 30, 255]]);
+    let biased = bytes.convolved(&identity().with_bias(0.25), EdgeMode::Clamp);
+    assert_eq!(&*biased.pixels(), &[64, 164, 255, 30, 74, 84, 94, 255]);
+    let darkened = bytes.convolved(&identity().with_bias(-0.5), EdgeMode::Clamp);
+    assert_eq!(&*darkened.pixels(), &[0, 0, 123, 30, 0, 0, 0, 255]);
+
+    // the Sobel presets put flat areas at mid grey
+    let flat = luma_image(5, 5, |_, _| 0.8);
+    for kernel in &[Kernel::sobel_x(), Kernel::sobel_y()] {
+        assert!(flat.convolved(kernel, EdgeMode::Mirror).pixels().iter().all(|&value| (value - 0.5).abs() < 1e-6));
+    }
+}
+
+/******************************************* Alpha Modes **********************************************/
+/******************************************************************************************************/
+
+/// Opaque red either side of a fully transparent green.
+fn red_around_clear_green() -> OwnedImage<Rgba8> {
+    rgba_row(&[[255, 0, 0, 255], [0, 255, 0, 0], [255, 0, 0, 255]])
+}
+
+fn average_of_three() -> Kernel {
+    Kernel::new(3, 1, vec![1.0; 3]).unwrap().normalized()
+}
+
+#[test]
+fn ignoring_alpha_keeps_each_pixels_own() {
+    let image = red_around_clear_green();
+    let blurred = image.convolved(&average_of_three(), EdgeMode::Clamp);
+    let alphas: Vec<u8> = blurred.pixels().chunks(4).map(|p| p[3]).collect();
+    assert_eq!(alphas, [255, 0, 255]);
+    // and the hidden green still bleeds into the colour
+    assert_eq!(&blurred.pixels()[4..7], &[170, 85, 0]);
+}
+
+#[test]
+fn straight_alpha_is_convolved_like_colour() {
+    let image = red_around_clear_green();
+    let blurred = image.convolved(&average_of_three().with_alpha(AlphaMode::Convolve), EdgeMode::Clamp);
+    assert_eq!(&*blurred.pixels(), &[170, 85, 0, 170, 170, 85, 0, 170, 170, 85, 0, 170]);
+}
+
+#[test]
+fn premultiplied_alpha_keeps_transparent_colour_out() {
+    let image = red_around_clear_green();
+    let blurred = image.convolved(&average_of_three().with_alpha(AlphaMode::Premultiply), EdgeMode::Clamp);
+    // same coverage as convolving straight alpha, but only red shows
+    assert_eq!(&*blurred.pixels(), &[255, 0, 0, 170, 255, 0, 0, 170, 255, 0, 0, 170]);
+
+    // partial alpha weights each colour by how much of it shows
+    let image = rgba_row(&[[255, 0, 0, 255], [0, 0, 255, 85], [255, 0, 0, 255]]);
+    let blurred = image.convolved(&average_of_three().with_alpha(AlphaMode::Premultiply), EdgeMode::Clamp);
+    assert_eq!(&blurred.pixels()[4..8], &[219, 0, 36, 198]);
+
+    // nothing shows at all, so there's no colour to divide out
+    let clear = rgba_row(&[[10, 200, 30, 0]; 3]);
+    let blurred = clear.convolved(&average_of_three().with_alpha(AlphaMode::Premultiply), EdgeMode::Clamp);
+    assert_eq!(&*blurred.pixels(), &[0; 12]);
+}
+
+#[test]
+fn layouts_without_alpha_ignore_the_alpha_mode() {
+    let image = red_around_clear_green().converted::<Rgb8>();
+    let plain = image.convolved(&average_of_three(), EdgeMode::Mirror);
+    for &mode in &[AlphaMode::Convolve, AlphaMode::Premultiply] {
+        assert_eq!(image.convolved(&average_of_three().with_alpha(mode), EdgeMode::Mirror).pixels(), plain.pixels());
+    }
+}
@@ -0,0 +1,66 @@
+//! Threaded operations against the same operations on a single thread.
+#![cfg(feature = "parallel")]
+
+use image_processor::{
+    traits::*,
+    OwnedImage::OwnedImage,
+    utils::Dimensions,
+    pixel::*,
+    parallel,
+    kernel::{Kernel, EdgeMode},
+    resize::Filter,
+};
+
+/// Tall enough for many bands of rows, and with a ragged last one.
+fn image() -> OwnedImage<Rgba8> {
+    let (width, height) = (83u32, 141u32);
+    let pixels: Vec<u8> = (0..height).flat_map(|y| (0..width).map(move |x| (x, y)))
+        .flat_map(|(x, y)| vec![((x * y) % 251) as u8, ((x ^ y) * 13) as u8, (x * 3 + y) as u8, (y * 2) as u8])
+        .collect();
+    OwnedImage::new(Dimensions { width, height }, pixels.into_boxed_slice()).unwrap()
+}
+
+/// Runs `op` on one thread and on several, and checks the pixels are bit-identical.
+fn assert_same_on_any_threads<P: PixelType>(name: &str, op: impl Fn() -> OwnedImage<P> + Send + Sync) {
+    let serial = parallel::with_threads(1, &op).unwrap();
+    for &threads in &[2, 3, 8] {
+        let threaded = parallel::with_threads(threads, &op).unwrap();
+        assert_eq!(threaded.dimensions(), serial.dimensions(), "{} on {} threads", name, threads);
+        assert!(threaded.pixels() == serial.pixels(), "{} differs on {} threads", name, threads);
+    }
+}
+
+/*************************************** Determinism **************************************************/
+/******************************************************************************************************/
+
+#[test]
+fn threads_give_bit_identical_results() {
+    let image = image();
+    let other = image.flipped(true, false);
+
+    assert_same_on_any_threads("map_pixels", || {
+        image.map_pixels(|Rgba { r, g, b, alpha }| Rgba { r: g, g: b, b: r, alpha: 255 - alpha })
+    });
+    assert_same_on_any_threads("zip_map", || {
+        image.zip_map(&other, |a, b| Rgba { r: a.r / 2 + b.r / 2, g: a.g.max(b.g), b: a.b, alpha: b.alpha }).unwrap()
+    });
+    assert_same_on_any_threads("blur", || {
+        let mut copy = image.map_pixels(|p| p);
+        copy.blur(5, EdgeMode::Mirror).unwrap();
+        copy
+    });
+    assert_same_on_any_threads("flip", || {
+        let mut copy = image.map_pixels(|p| p);
+        copy.flip(true, true);
+        copy
+    });
+    assert_same_on_any_threads("resize down", || image.resized(Dimensions { width: 37, height: 50 }, Filter::Lanczos3));
+    assert_same_on_any_threads("resize up", || image.resized(Dimensions { width: 190, height: 301 }, Filter::CatmullRom));
+
+    // float results would show any change in the order values are summed
+    let float = image.converted::<Rgba32F>();
+    assert_same_on_any_threads("gaussian", || float.gaussian_blurred(2.5, 7.0, EdgeMode::Clamp));
+    assert_same_on_any_threads("wide gaussian", || float.gaussian_blurred(30.0, 12.0, EdgeMode::Skip));
+    assert_same_on_any_threads("convolve", || float.convolved(&Kernel::sharpen(), EdgeMode::Wrap));
+    assert_same_on_any_threads("float resize", || float.resized(Dimensions { width: 61, height: 200 }, Filter::Mitchell));
+}
@@ -0,0 +1,226 @@
+//! Palettes picked from images, dithering onto them, and indexed export.
+
+use image_processor::{
+    traits::*,
+    OwnedImage::OwnedImage,
+    utils::{self, Dimensions, ImageError, OutputFormat},
+    pixel::*,
+    quantize::{Palette, PaletteMethod, Dither, IndexedImage},
+};
+
+mod common;
+use common::rgba_image;
+
+
+fn grey(value: u8) -> OwnedImage<Rgba8> {
+    rgba_image(64, 64, |_, _| [value, value, value, 255])
+}
+
+fn rgb(r: u8, g: u8, b: u8) -> Rgb8 {
+    Rgb { r, g, b }
+}
+
+/// A smooth sweep through many colours.
+fn sweep() -> OwnedImage<Rgba8> {
+    rgba_image(96, 64, |x, y| [(x * 255 / 95) as u8, (y * 255 / 63) as u8, ((x + y) * 255 / 158) as u8, 255])
+}
+
+fn mean_error(a: &OwnedImage<Rgba8>, b: &OwnedImage<Rgba8>) -> f64 {
+    let total: u64 = a.pixels().iter().zip(b.pixels().iter()).map(|(&x, &y)| (x as i32 - y as i32).unsigned_abs() as u64).sum();
+    total as f64 / a.pixels().len() as f64
+}
+
+/// Share of pixels set white in an image dithered to black and white.
+fn white_share(image: &IndexedImage) -> f32 {
+    image.indices().iter().filter(|&&i| i == 1).count() as f32 / image.indices().len() as f32
+}
+
+/***************************************** Palettes ***************************************************/
+/******************************************************************************************************/
+
+#[test]
+fn palettes_hold_one_to_256_colours() {
+    assert!(matches!(Palette::new(Vec::new()), Err(ImageError::PaletteSize { count: 0 })));
+    assert!(matches!(Palette::new(vec![rgb(1, 2, 3); 257]), Err(ImageError::PaletteSize { count: 257 })));
+    assert!(Palette::greys(1).is_err());
+    assert!(Palette::from_image(&sweep(), 0, PaletteMethod::MedianCut).is_err());
+
+    let greys = Palette::greys(4).unwrap();
+    assert_eq!(greys.colours(), &[rgb(0, 0, 0), rgb(85, 85, 85), rgb(170, 170, 170), rgb(255, 255, 255)]);
+    assert_eq!(greys.nearest([0.3, 0.35, 0.3]), 1);
+}
+
+#[test]
+fn every_method_finds_the_colours_of_a_small_image() {
+    let colours = [[200, 30, 30], [30, 200, 30], [30, 30, 200], [250, 250, 250]];
+    let image = rgba_image(20, 20, |x, y| {
+        let [r, g, b] = colours[((x / 5 + y) % 4) as usize];
+        [r, g, b, 255]
+    });
+
+    for method in [PaletteMethod::MedianCut, PaletteMethod::Octree, PaletteMethod::KMeans { iterations: 5 }] {
+        for count in [4, 16] {
+            let mut found: Vec<[u8; 3]> = Palette::from_image(&image, count, method).unwrap()
+                .colours().iter().map(|&Rgb { r, g, b }| [r, g, b]).collect();
+            found.sort();
+            let mut expected = colours.to_vec();
+            expected.sort();
+            assert_eq!(found, expected, "{:?} with {} colours", method, count);
+        }
+    }
+}
+
+#[test]
+fn picked_palettes_stay_close_to_the_image() {
+    let image = sweep();
+    let error = |method| {
+        let palette = Palette::from_image(&image, 16, method).unwrap();
+        assert!(palette.colours().len() <= 16);
+        mean_error(&image, &image.quantize(&palette, Dither::None))
+    };
+
+    let (median_cut, octree, k_means) =
+        (error(PaletteMethod::MedianCut), error(PaletteMethod::Octree), error(PaletteMethod::KMeans { iterations: 10 }));
+    assert!(median_cut < 16.0 && octree < 16.0, "{} {}", median_cut, octree);
+    assert!(k_means <= median_cut, "{} {}", k_means, median_cut);
+}
+
+#[test]
+fn empty_images_give_black() {
+    let image = rgba_image(0, 0, |_, _| [9, 9, 9, 9]);
+    assert_eq!(Palette::from_image(&image, 8, PaletteMethod::Octree).unwrap().colours(), &[rgb(0, 0, 0)]);
+}
+
+/**************************************** Dithering ***************************************************/
+/******************************************************************************************************/
+
+#[test]
+fn quantizing_picks_nearest_colours_and_keeps_alpha() {
+    let palette = Palette::new(vec![rgb(0, 0, 0), rgb(255, 0, 0), rgb(255, 255, 255)]).unwrap();
+    let image = rgba_image(4, 1, |x, _| [[10, 10, 10, 0], [180, 40, 20, 50], [240, 230, 220, 100], [90, 80, 70, 255]][x as usize]);
+
+    let indexed = image.to_indexed(&palette, Dither::None);
+    assert_eq!(indexed.indices(), &[0, 1, 2, 0]);
+    assert_eq!(indexed.to_image::<Rgb8>().get_pixel(1, 0).unwrap(), rgb(255, 0, 0));
+
+    let quantized = image.quantize(&palette, Dither::None);
+    assert_eq!(quantized.row(0), &[0, 0, 0, 0, 255, 0, 0, 50, 255, 255, 255, 100, 0, 0, 0, 255]);
+}
+
+#[test]
+fn dithering_keeps_the_average_tone() {
+    let palette = Palette::greys(2).unwrap();
+    for value in [64, 128, 191] {
+        let image = grey(value);
+        assert!(white_share(&image.to_indexed(&palette, Dither::None)) == if value >= 128 { 1.0 } else { 0.0 });
+
+        let wanted = value as f32 / 255.0;
+        // Atkinson drops a quarter of the error, so light and dark tones drift to the ends
+        for (dither, tolerance) in [(Dither::Bayer { size: 8 }, 0.01), (Dither::FloydSteinberg, 0.01),
+                                    (Dither::Sierra, 0.01), (Dither::Atkinson, 0.1)] {
+            let share = white_share(&image.to_indexed(&palette, dither));
+            assert!((share - wanted).abs() <= tolerance, "{:?} on {}: {}", dither, value, share);
+        }
+    }
+}
+
+#[test]
+fn bayer_dithering_of_mid_grey_is_a_checkerboard() {
+    let indexed = grey(128).to_indexed(&Palette::greys(2).unwrap(), Dither::Bayer { size: 2 });
+    for y in 0..64 {
+        for x in 0..64 {
+            assert_ne!(indexed.get_index(x, y).unwrap(), indexed.get_index(x ^ 1, y).unwrap());
+            assert_ne!(indexed.get_index(x, y).unwrap(), indexed.get_index(x, y ^ 1).unwrap());
+        }
+    }
+}
+
+#[test]
+fn dithering_onto_more_levels_stays_near_the_value() {
+    let palette = Palette::greys(4).unwrap();
+    let image = grey(100);
+    for dither in [Dither::Bayer { size: 4 }, Dither::FloydSteinberg, Dither::Atkinson, Dither::Sierra] {
+        // only the two levels either side of the value are used
+        let indexed = image.to_indexed(&palette, dither);
+        assert!(indexed.indices().iter().all(|&i| i == 1 || i == 2), "{:?}", dither);
+    }
+}
+
+#[test]
+fn quantizing_a_crop_leaves_the_rest() {
+    let mut image = sweep();
+    let original = sweep();
+    let palette = Palette::greys(3).unwrap();
+    let dims = Dimensions { width: 20, height: 10 };
+    let expected = image.crop(30, 40, dims).unwrap().quantize(&palette, Dither::FloydSteinberg);
+
+    image.crop_mut(30, 40, dims).unwrap().quantize_mut(&palette, Dither::FloydSteinberg);
+    for (x, y, pixel) in image.pixels_iter() {
+        if (30..50).contains(&x) && (40..50).contains(&y) {
+            assert_eq!(pixel, expected.get_pixel(x - 30, y - 40).unwrap());
+        } else {
+            assert_eq!(pixel, original.get_pixel(x, y).unwrap());
+        }
+    }
+}
+
+/*************************************** Indexed Export ***********************************************/
+/******************************************************************************************************/
+
+#[test]
+fn indexed_png_packs_small_palettes() {
+    let image = sweep();
+    // an odd width, so rows end part-way through a byte
+    let image = image.crop(0, 0, Dimensions { width: 37, height: 21 }).unwrap();
+
+    for (count, depth) in [(2, 1), (4, 2), (16, 4), (100, 8)] {
+        let palette = Palette::from_image(&image, count, PaletteMethod::MedianCut).unwrap();
+        let indexed = image.to_indexed(&palette, Dither::FloydSteinberg);
+
+        let mut png = Vec::new();
+        utils::write_indexed(&indexed, &mut png, OutputFormat::Png).unwrap();
+        // IHDR bit depth, then colour type 3 for a palette
+        assert_eq!((png[24], png[25]), (depth, 3), "{} colours", count);
+
+        let (decoded, _): (OwnedImage<Rgba8>, _) = utils::load_from_memory(&png).unwrap();
+        assert_eq!(decoded.pixels(), indexed.to_image::<Rgba8>().pixels(), "{} colours", count);
+    }
+}
+
+#[test]
+fn indexed_gif_round_trips() {
+    let image = sweep();
+    let palette = Palette::from_image(&image, 64, PaletteMethod::Octree).unwrap();
+    let indexed = image.to_indexed(&palette, Dither::Sierra);
+
+    let mut gif = Vec::new();
+    utils::write_indexed(&indexed, &mut gif, OutputFormat::Gif).unwrap();
+    let (decoded, _): (OwnedImage<Rgba8>, _) = utils::load_from_memory(&gif).unwrap();
+    assert_eq!(decoded.pixels(), indexed.to_image::<Rgba8>().pixels());
+}
+
+#[test]
+fn indexed_images_are_checked() {
+    let palette = Palette::greys(2).unwrap();
+    let dims = Dimensions { width: 2, height: 2 };
+    assert!(matches!(IndexedImage::new(dims, vec![0, 1, 1], palette.clone()), Err(ImageError::BufferSize { .. })));
+    assert!(matches!(IndexedImage::new(dims, vec![0, 1, 2, 1], palette.clone()),
+                     Err(ImageError::PaletteIndex { index: 2, count: 2 })));
+
+    let indexed = IndexedImage::new(dims, vec![0, 1, 1, 0], palette).unwrap();
+    assert!(matches!(indexed.get_index(2, 0), Err(ImageError::IndexOutOfBound { .. })));
+    let result = utils::write_indexed(&indexed, Vec::new(), OutputFormat::Bmp);
+    assert!(matches!(result, Err(ImageError::IndexedFormat { format: OutputFormat::Bmp })));
+
+    // saving as another format leaves the file as it was, or not there at all
+    let path = std::env::temp_dir().join(format!("image-processor-quantize-{}.bmp", std::process::id()));
+    let result = utils::save_indexed(&indexed, &path);
+    assert!(matches!(result, Err(ImageError::IndexedFormat { format: OutputFormat::Bmp })));
+    assert!(!path.exists());
+    std::fs::write(&path, b"keep me").unwrap();
+    let result = utils::save_indexed(&indexed, &path);
+    let kept = std::fs::read(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert!(matches!(result, Err(ImageError::IndexedFormat { .. })));
+    assert_eq!(kept, b"keep me");
+}
@@ -0,0 +1,163 @@
+//! Resampling filters, sizing modes and anchors.
+
+use image_processor::{
+    traits::*,
+    OwnedImage::OwnedImage,
+    utils::Dimensions,
+    pixel::*,
+    resize::{Anchor, Filter, Sizing},
+};
+
+const FILTERS: [Filter; 5] = [Filter::Nearest, Filter::Bilinear, Filter::CatmullRom, Filter::Mitchell, Filter::Lanczos3];
+
+fn dims(width: u32, height: u32) -> Dimensions {
+    Dimensions { width, height }
+}
+
+fn luma_image(width: u32, height: u32, luma: impl Fn(u32, u32) -> u8) -> OwnedImage<Luma8> {
+    let pixels: Vec<u8> = (0..height).flat_map(|y| (0..width).map(move |x| (x, y))).map(|(x, y)| luma(x, y)).collect();
+    OwnedImage::new(dims(width, height), pixels.into_boxed_slice()).unwrap()
+}
+
+/*************************************** Pixel Values *************************************************/
+/******************************************************************************************************/
+
+#[test]
+fn constant_images_stay_constant() {
+    let colour = Rgba { r: 200u8, g: 13, b: 77, alpha: 140 };
+    let image = OwnedImage::<Rgba8>::new(dims(7, 5), [200, 13, 77, 140].repeat(35).into_boxed_slice()).unwrap();
+    let float = image.converted::<Rgba32F>();
+    let expected = float.pixels()[..4].to_vec();
+    // up, down, and up one way while down the other
+    for &target in &[dims(16, 11), dims(3, 2), dims(1, 1), dims(20, 3)] {
+        for &filter in FILTERS.iter() {
+            let resized = image.resized(target, filter);
+            assert_eq!(*resized.dimensions(), target);
+            assert!(resized.pixels().chunks(4).all(|p| p == [colour.r, colour.g, colour.b, colour.alpha]),
+                    "{:?} to {:?}", filter, target);
+
+            // ringing filters have negative weights, but they still sum to one
+            for p in float.resized(target, filter).pixels().chunks(4) {
+                for (value, expected) in p.iter().zip(&expected) {
+                    assert!((value - expected).abs() < 1e-5, "{:?} to {:?}", filter, target);
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn the_same_size_leaves_pixels_alone() {
+    let image = luma_image(9, 6, |x, y| (x * 29 + y * 41) as u8);
+    for &filter in FILTERS.iter() {
+        assert_eq!(image.resized(dims(9, 6), filter).pixels(), image.pixels(), "{:?}", filter);
+    }
+}
+
+#[test]
+fn nearest_doubling_duplicates_pixels() {
+    let image = luma_image(5, 3, |x, y| (x * 50 + y * 7) as u8);
+    let doubled = image.resized(dims(10, 6), Filter::Nearest);
+    let expected = luma_image(10, 6, |x, y| ((x / 2) * 50 + (y / 2) * 7) as u8);
+    assert_eq!(doubled.pixels(), expected.pixels());
+
+    // and halving averages each 2x2 block
+    let halved = doubled.resized(dims(5, 3), Filter::Nearest);
+    assert_eq!(halved.pixels(), image.pixels());
+    let blocks = luma_image(4, 2, |x, y| if (x + y) % 2 == 0 { 10 } else { 30 });
+    assert_eq!(&*blocks.resized(dims(2, 1), Filter::Nearest).pixels(), &[20, 20]);
+}
+
+#[test]
+fn shrinking_averages_every_pixel() {
+    // a one-pixel checkerboard aliases unless every source pixel counts
+    let checkers = luma_image(32, 32, |x, y| if (x + y) % 2 == 0 { 0 } else { 200 });
+    for &filter in FILTERS.iter() {
+        let small = checkers.resized(dims(5, 5), filter);
+        assert!(small.pixels().iter().all(|&v| (v as i32 - 100).abs() <= 12), "{:?}: {:?}", filter, small.pixels());
+    }
+}
+
+#[test]
+fn zero_sizes_give_empty_images() {
+    let image = luma_image(5, 3, |x, _| x as u8);
+    for &target in &[dims(0, 0), dims(0, 4), dims(4, 0)] {
+        for &filter in FILTERS.iter() {
+            let resized = image.resized(target, filter);
+            assert_eq!(*resized.dimensions(), target);
+            assert!(resized.pixels().is_empty());
+            assert_eq!(*image.resized_to(target, filter, Sizing::Cover(Anchor::Centre)).dimensions(), target);
+        }
+        // aspect ratio is kept with at least one pixel each way
+        assert_eq!(*image.resized_to(target, Filter::Bilinear, Sizing::Fit).dimensions(), dims(1, 1));
+    }
+
+    // an empty image has nothing to spread, so it comes out blank
+    let empty = luma_image(0, 0, |_, _| 0);
+    let resized = empty.resized(dims(3, 2), Filter::Lanczos3);
+    assert_eq!(*resized.dimensions(), dims(3, 2));
+    assert!(resized.pixels().iter().all(|&v| v == 0));
+}
+
+/*************************************** Sizing Modes *************************************************/
+/******************************************************************************************************/
+
+#[test]
+fn sizing_modes_keep_the_aspect_ratio() {
+    let image = luma_image(100, 50, |x, y| (x + y) as u8);
+    let target = dims(40, 40);
+    assert_eq!(*image.resized_to(target, Filter::Bilinear, Sizing::Exact).dimensions(), dims(40, 40));
+    assert_eq!(*image.resized_to(target, Filter::Bilinear, Sizing::Fit).dimensions(), dims(40, 20));
+    assert_eq!(*image.resized_to(target, Filter::Bilinear, Sizing::Fill).dimensions(), dims(80, 40));
+    assert_eq!(*image.resized_to(target, Filter::Bilinear, Sizing::Cover(Anchor::Centre)).dimensions(), dims(40, 40));
+
+    // the other way round, and enlarging
+    let tall = luma_image(30, 90, |x, y| (x * y) as u8);
+    assert_eq!(*tall.resized_to(dims(60, 60), Filter::Mitchell, Sizing::Fit).dimensions(), dims(20, 60));
+    assert_eq!(*tall.resized_to(dims(60, 60), Filter::Mitchell, Sizing::Fill).dimensions(), dims(60, 180));
+    assert_eq!(*tall.resized_to(dims(300, 200), Filter::Mitchell, Sizing::Fit).dimensions(), dims(67, 200));
+
+    // fitting and filling are the plain resize to their size
+    let fit = image.resized_to(target, Filter::CatmullRom, Sizing::Fit);
+    assert_eq!(fit.pixels(), image.resized(dims(40, 20), Filter::CatmullRom).pixels());
+}
+
+#[test]
+fn cover_keeps_the_anchored_part() {
+    // every column, and every row, has its own value
+    let wide = luma_image(4, 2, |x, y| (x * 10 + y) as u8);
+    let columns = |anchor| -> Vec<u8> {
+        let covered = wide.resized_to(dims(2, 2), Filter::Nearest, Sizing::Cover(anchor));
+        assert_eq!(*covered.dimensions(), dims(2, 2));
+        covered.pixels()[..2].iter().map(|v| v / 10).collect()
+    };
+    for &anchor in &[Anchor::TopLeft, Anchor::Left, Anchor::BottomLeft] {
+        assert_eq!(columns(anchor), [0, 1], "{:?}", anchor);
+    }
+    for &anchor in &[Anchor::Top, Anchor::Centre, Anchor::Bottom] {
+        assert_eq!(columns(anchor), [1, 2], "{:?}", anchor);
+    }
+    for &anchor in &[Anchor::TopRight, Anchor::Right, Anchor::BottomRight] {
+        assert_eq!(columns(anchor), [2, 3], "{:?}", anchor);
+    }
+
+    let tall = luma_image(2, 4, |x, y| (y * 10 + x) as u8);
+    let rows = |anchor| -> Vec<u8> {
+        let covered = tall.resized_to(dims(2, 2), Filter::Nearest, Sizing::Cover(anchor));
+        covered.pixels().chunks(2).map(|row| row[0] / 10).collect()
+    };
+    for &anchor in &[Anchor::TopLeft, Anchor::Top, Anchor::TopRight] {
+        assert_eq!(rows(anchor), [0, 1], "{:?}", anchor);
+    }
+    for &anchor in &[Anchor::Left, Anchor::Centre, Anchor::Right] {
+        assert_eq!(rows(anchor), [1, 2], "{:?}", anchor);
+    }
+    for &anchor in &[Anchor::BottomLeft, Anchor::Bottom, Anchor::BottomRight] {
+        assert_eq!(rows(anchor), [2, 3], "{:?}", anchor);
+    }
+
+    // scaled first, then cropped
+    let big = luma_image(8, 4, |x, y| (x * 10 + y) as u8);
+    let covered = big.resized_to(dims(2, 2), Filter::Nearest, Sizing::Cover(Anchor::Right));
+    assert_eq!(covered.pixels(), big.resized(dims(4, 2), Filter::Nearest).crop(2, 0, dims(2, 2)).unwrap().pixels());
+}
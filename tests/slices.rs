@@ -0,0 +1,617 @@
+//! Nested crops checked against a reference model: a plain grid of pixels
+//! that is cropped by copying.
+
+use image_processor::{
+    traits::*,
+    OwnedImage::OwnedImage,
+    ImageSlice::{ImageSlice, ImageSliceMut},
+    utils::{Dimensions, ImageError, SliceSpec},
+    pixel::*,
+    geometry::{Interpolation, Canvas},
+    resize::Filter,
+    kernel::{Kernel, EdgeMode},
+};
+
+/************************************** Reference Implementation **************************************/
+/******************************************************************************************************/
+
+/// Rows of pixels, cropped by copying. The width is kept separately so
+/// images without rows still have one.
+#[derive(Clone, Debug, PartialEq)]
+struct Grid<P> {
+    width: u32,
+    rows: Vec<Vec<P>>,
+}
+
+impl<P: PixelType> Grid<P> {
+    fn width(&self) -> u32 {
+        self.width
+    }
+
+    fn height(&self) -> u32 {
+        self.rows.len() as u32
+    }
+
+    fn map<Q: PixelType>(&self, f: impl Fn(&P) -> Q) -> Grid<Q> {
+        Grid { width: self.width, rows: self.rows.iter().map(|row| row.iter().map(&f).collect()).collect() }
+    }
+
+    /// The crop rules: the origin may be anywhere up to and including the far
+    /// edges, and the size is clamped to what's left.
+    fn crop(&self, x: u32, y: u32, dims: Dimensions) -> Option<Grid<P>> {
+        if x > self.width() || y > self.height() {
+            return None;
+        }
+        let w = dims.width.min(self.width() - x) as usize;
+        let h = dims.height.min(self.height() - y) as usize;
+        let rows = self.rows[y as usize..y as usize + h].iter()
+            .map(|row| row[x as usize..x as usize + w].to_vec())
+            .collect();
+        Some(Grid { width: w as u32, rows })
+    }
+
+    /// Overwrites the pixels under `patch`, placed with its top-left at (`x`, `y`).
+    fn paste(&mut self, x: u32, y: u32, patch: &Grid<P>) {
+        for (row, patch_row) in self.rows[y as usize..].iter_mut().zip(&patch.rows) {
+            row[x as usize..x as usize + patch_row.len()].clone_from_slice(patch_row);
+        }
+    }
+
+    fn image(&self) -> OwnedImage<P> {
+        let dims = Dimensions { width: self.width(), height: self.height() };
+        let mut channels = vec![P::Channel::default(); P::CHANNELS];
+        let mut pixels = Vec::new();
+        for pixel in self.rows.iter().flatten() {
+            pixel.write_channels(&mut channels);
+            pixels.extend_from_slice(&channels);
+        }
+        OwnedImage::new(dims, pixels.into_boxed_slice()).unwrap()
+    }
+}
+
+/// Reads every pixel of `image` one at a time.
+fn grid_of<I: Image>(image: &I) -> Grid<I::Pixel> {
+    let dims = *image.dimensions();
+    let view = image.view();
+    let rows = (0..dims.height)
+        .map(|y| (0..dims.width).map(|x| view.get_pixel(x, y).unwrap()).collect())
+        .collect();
+    Grid { width: dims.width, rows }
+}
+
+/// Checks every way of reading `image` against `expected`.
+fn assert_matches<I: Image>(image: &I, expected: &Grid<I::Pixel>) {
+    let dims = Dimensions { width: expected.width(), height: expected.height() };
+    assert_eq!(*image.dimensions(), dims);
+    assert_eq!(grid_of(image), *expected);
+
+    let packed = expected.image();
+    assert_eq!(image.pixels(), packed.pixels());
+    assert_eq!(image.rows().count(), dims.height as usize);
+    for (y, row) in image.rows().enumerate() {
+        assert_eq!(row, packed.row(y as u32));
+        assert_eq!(row, image.row(y as u32));
+    }
+}
+
+/// Deterministic pseudo-random numbers, so failures reproduce.
+struct Lcg(u64);
+
+impl Lcg {
+    fn next(&mut self, below: u32) -> u32 {
+        self.0 = self.0.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+        ((self.0 >> 33) % (below as u64 + 1)) as u32
+    }
+}
+
+fn rgba_grid(width: u32, height: u32) -> Grid<Rgba8> {
+    let rows = (0..height)
+        .map(|y| (0..width)
+            .map(|x| Rgba { r: x as u8, g: y as u8, b: (x * 7 + y * 13) as u8, alpha: 255 - (x ^ y) as u8 })
+            .collect())
+        .collect();
+    Grid { width, rows }
+}
+
+fn dims(width: u32, height: u32) -> Dimensions {
+    Dimensions { width, height }
+}
+
+/******************************************* Nested Crops *********************************************/
+/******************************************************************************************************/
+
+#[test]
+fn single_crop_matches_reference() {
+    let grid = rgba_grid(23, 17);
+    let image = grid.image();
+    for &(x, y, w, h) in &[(0, 0, 23, 17), (3, 4, 5, 6), (22, 16, 1, 1), (10, 0, 13, 17), (0, 9, 23, 8)] {
+        let slice = image.crop(x, y, dims(w, h)).unwrap();
+        assert_matches(&slice, &grid.crop(x, y, dims(w, h)).unwrap());
+    }
+}
+
+#[test]
+fn crop_of_crop_matches_reference() {
+    let grid = rgba_grid(20, 15);
+    let image = grid.image();
+    let outer = image.crop(3, 2, dims(12, 10)).unwrap();
+    let inner = outer.crop(4, 5, dims(6, 3)).unwrap();
+    let expected = grid.crop(3, 2, dims(12, 10)).unwrap().crop(4, 5, dims(6, 3)).unwrap();
+    assert_matches(&inner, &expected);
+
+    // the inner crop starts at (7, 7) of the original
+    assert_eq!(inner.get_pixel(0, 0).unwrap(), image.get_pixel(7, 7).unwrap());
+}
+
+#[test]
+fn deep_nesting_matches_reference() {
+    let grid = rgba_grid(40, 31);
+    let image = grid.image();
+    let mut rng = Lcg(7);
+
+    for _ in 0..200 {
+        let whole = image.crop(0, 0, dims(40, 31)).unwrap();
+        let depth = rng.next(5) + 1;
+        crop_randomly(&whole, &grid, &mut rng, depth);
+    }
+}
+
+/// Crops `slice` at random `depth` times over, checking each level.
+fn crop_randomly<P: PixelType>(slice: &ImageSlice<'_, P>, expected: &Grid<P>, rng: &mut Lcg, depth: u32) {
+    if depth == 0 {
+        return;
+    }
+    let (x, y) = (rng.next(expected.width()), rng.next(expected.height()));
+    let size = dims(rng.next(expected.width() + 2), rng.next(expected.height() + 2));
+
+    let inner = slice.crop(x, y, size).unwrap();
+    let expected = expected.crop(x, y, size).unwrap();
+    assert_matches(&inner, &expected);
+    assert_eq!(*inner.spec(), slice.spec().crop(x, y, size, P::CHANNELS).unwrap());
+    crop_randomly(&inner, &expected, rng, depth - 1);
+}
+
+#[test]
+fn nesting_works_for_every_pixel_layout() {
+    let mut rng = Lcg(11);
+    let rgba = rgba_grid(19, 13);
+    let rgb = rgba.map(|p| p.convert::<Rgb8>());
+    let luma = rgba.map(|p| p.convert::<Luma16>());
+    let float = rgba.map(|p| p.convert::<Rgba32F>());
+
+    for _ in 0..50 {
+        let depth = rng.next(4) + 1;
+        crop_randomly(&rgb.image().crop(0, 0, dims(19, 13)).unwrap(), &rgb, &mut rng, depth);
+        crop_randomly(&luma.image().crop(0, 0, dims(19, 13)).unwrap(), &luma, &mut rng, depth);
+        crop_randomly(&float.image().crop(0, 0, dims(19, 13)).unwrap(), &float, &mut rng, depth);
+    }
+}
+
+#[test]
+fn mutable_crops_nest_like_immutable_ones() {
+    let grid = rgba_grid(16, 12);
+    let mut image = grid.image();
+    let outer = image.crop_mut(2, 3, dims(10, 8)).unwrap();
+    let inner = outer.crop(1, 2, dims(5, 5)).unwrap();
+    let expected = grid.crop(2, 3, dims(10, 8)).unwrap();
+    assert_matches(&outer, &expected);
+    assert_matches(&inner, &expected.crop(1, 2, dims(5, 5)).unwrap());
+}
+
+/********************************************** Edge Cases ********************************************/
+/******************************************************************************************************/
+
+#[test]
+fn sizes_are_clamped_at_every_level() {
+    let grid = rgba_grid(10, 10);
+    let image = grid.image();
+    let outer = image.crop(6, 6, dims(100, 100)).unwrap();
+    assert_eq!(*outer.dimensions(), dims(4, 4));
+
+    // clamped to the slice, not to the parent image
+    let inner = outer.crop(1, 1, dims(100, 100)).unwrap();
+    assert_eq!(*inner.dimensions(), dims(3, 3));
+    assert_matches(&inner, &grid.crop(7, 7, dims(3, 3)).unwrap());
+}
+
+#[test]
+fn empty_crops_at_the_far_edges() {
+    let grid = rgba_grid(8, 6);
+    let image = grid.image();
+    let outer = image.crop(2, 1, dims(6, 5)).unwrap();
+
+    for &(x, y) in &[(6, 0), (0, 5), (6, 5)] {
+        let empty = outer.crop(x, y, dims(3, 3)).unwrap();
+        assert_eq!(empty.pixels().len(), 0);
+        assert_eq!(empty.rows().filter(|row| !row.is_empty()).count(), 0);
+        assert!(empty.crop(0, 0, dims(1, 1)).is_ok());
+    }
+
+    let corner = image.crop(8, 6, dims(1, 1)).unwrap();
+    assert_eq!(*corner.dimensions(), dims(0, 0));
+    assert!(corner.pixels().is_empty());
+}
+
+#[test]
+fn crop_origin_outside_the_slice_fails() {
+    let image = rgba_grid(10, 10).image();
+    let outer = image.crop(2, 2, dims(4, 4)).unwrap();
+
+    // (5, 0) is inside the parent image but outside the slice
+    match outer.crop(5, 0, dims(1, 1)) {
+        Err(ImageError::CropOutOfBound { x: 5, y: 0, width: 4, height: 4 }) => {}
+        other => panic!("expected CropOutOfBound, got {:?}", other.map(|slice| *slice.dimensions())),
+    }
+    assert!(outer.crop(0, 5, dims(1, 1)).is_err());
+}
+
+#[test]
+fn pixel_reads_are_bounded_by_the_slice() {
+    let image = rgba_grid(10, 10).image();
+    let outer = image.crop(1, 1, dims(8, 8)).unwrap();
+    let inner = outer.crop(2, 3, dims(2, 2)).unwrap();
+    assert_eq!(inner.get_pixel(1, 1).unwrap(), image.get_pixel(4, 5).unwrap());
+
+    // still inside the parent, but outside the slice
+    match inner.get_pixel(2, 0) {
+        Err(ImageError::IndexOutOfBound { x: 2, y: 0, width: 2, height: 2 }) => {}
+        other => panic!("expected IndexOutOfBound, got {:?}", other),
+    }
+    assert!(inner.get_pixel(0, 2).is_err());
+}
+
+#[test]
+fn non_square_crops_use_their_own_width_and_height() {
+    let grid = rgba_grid(30, 7);
+    let image = grid.image();
+    let wide = image.crop(1, 1, dims(25, 4)).unwrap();
+    assert_eq!(wide.get_pixel(24, 3).unwrap(), image.get_pixel(25, 4).unwrap());
+    assert!(wide.get_pixel(3, 24).is_err());
+
+    let tall = wide.crop(20, 0, dims(3, 4)).unwrap();
+    assert_matches(&tall, &grid.crop(21, 1, dims(3, 4)).unwrap());
+}
+
+/******************************************** Slice Specs *********************************************/
+/******************************************************************************************************/
+
+#[test]
+fn specs_compose_origins_and_keep_the_stride() {
+    let whole = SliceSpec::whole(dims(10, 8), 4);
+    assert_eq!(whole, SliceSpec { origin: 0, stride: 40, dims: dims(10, 8) });
+
+    let outer = whole.crop(2, 3, dims(5, 4), 4).unwrap();
+    assert_eq!(outer, SliceSpec { origin: 3 * 40 + 2 * 4, stride: 40, dims: dims(5, 4) });
+
+    let inner = outer.crop(1, 1, dims(10, 10), 4).unwrap();
+    assert_eq!(inner, SliceSpec { origin: 4 * 40 + 3 * 4, stride: 40, dims: dims(4, 3) });
+    assert_eq!(inner, whole.crop(3, 4, dims(4, 3), 4).unwrap());
+}
+
+#[test]
+fn slices_over_raw_buffers_are_validated() {
+    let buffer = vec![0u8; 4 * 10 * 5];
+    let fits = SliceSpec { origin: 4 * 12, stride: 40, dims: dims(8, 3) };
+    assert!(ImageSlice::<Rgba8>::new(&buffer, fits).is_ok());
+
+    let too_long = SliceSpec { origin: 4 * 13, stride: 40, dims: dims(8, 4) };
+    assert!(ImageSlice::<Rgba8>::new(&buffer, too_long).is_err());
+
+    let overlapping = SliceSpec { origin: 0, stride: 8, dims: dims(4, 2) };
+    assert!(ImageSlice::<Rgba8>::new(&buffer, overlapping).is_err());
+}
+
+/********************************* Operations on Nested Crops *****************************************/
+/******************************************************************************************************/
+
+#[test]
+fn operations_on_nested_crops_match_operations_on_copies() {
+    let grid = rgba_grid(33, 25);
+    let image = grid.image();
+    let outer = image.crop(4, 3, dims(24, 19)).unwrap();
+    let inner = outer.crop(5, 2, dims(14, 11)).unwrap();
+    let copy = grid.crop(9, 5, dims(14, 11)).unwrap().image();
+
+    assert_eq!(inner.flipped(true, true).pixels(), copy.flipped(true, true).pixels());
+    assert_eq!(inner.greyscale().pixels(), copy.greyscale().pixels());
+    assert_eq!(inner.blurred(2, EdgeMode::Clamp).pixels(), copy.blurred(2, EdgeMode::Clamp).pixels());
+    assert_eq!(inner.gaussian_blurred(1.5, 0.5, EdgeMode::Mirror).pixels(),
+               copy.gaussian_blurred(1.5, 0.5, EdgeMode::Mirror).pixels());
+    assert_eq!(inner.convolved(&Kernel::sharpen(), EdgeMode::Wrap).pixels(),
+               copy.convolved(&Kernel::sharpen(), EdgeMode::Wrap).pixels());
+    assert_eq!(inner.rotated90().pixels(), copy.rotated90().pixels());
+    assert_eq!(inner.rotated(10.0, Interpolation::Bilinear, Canvas::Keep, Rgba8::default()).pixels(),
+               copy.rotated(10.0, Interpolation::Bilinear, Canvas::Keep, Rgba8::default()).pixels());
+    assert_eq!(inner.resized(dims(7, 20), Filter::CatmullRom).pixels(),
+               copy.resized(dims(7, 20), Filter::CatmullRom).pixels());
+    assert_eq!(inner.converted::<Luma8>().pixels(), copy.converted::<Luma8>().pixels());
+}
+
+/// Runs an in-place operation on a nested mutable crop, and checks the parent
+/// against the reference with just that rectangle replaced by the operation's
+/// result on a copy.
+fn assert_edits_region(slice_op: impl Fn(&mut ImageSliceMut<'_, Rgba8>), copy_op: impl Fn(&mut OwnedImage<Rgba8>)) {
+    let grid = rgba_grid(33, 25);
+    let mut image = grid.image();
+    {
+        let mut outer = image.crop_mut(4, 3, dims(24, 19)).unwrap();
+        let mut inner = outer.crop_mut(5, 2, dims(14, 11)).unwrap();
+        slice_op(&mut inner);
+    }
+
+    let mut copy = grid.crop(9, 5, dims(14, 11)).unwrap().image();
+    copy_op(&mut copy);
+    let mut expected = grid.clone();
+    expected.paste(9, 5, &grid_of(&copy));
+    assert_matches(&image, &expected);
+}
+
+macro_rules! assert_edits_region {
+    (|$image:ident| $op:expr) => {
+        assert_edits_region(|$image| $op, |$image| $op)
+    };
+}
+
+#[test]
+fn in_place_operations_only_touch_the_crop() {
+    assert_edits_region!(|image| image.blur(3, EdgeMode::Mirror).unwrap());
+    assert_edits_region!(|image| image.flip(true, false));
+    assert_edits_region!(|image| image.flip(false, true));
+    assert_edits_region!(|image| image.greyscale_mut());
+    assert_edits_region!(|image| image.gaussian_blur(2.5, 1.0, EdgeMode::Clamp).unwrap());
+    assert_edits_region!(|image| image.convolve(&Kernel::sharpen(), EdgeMode::Wrap).unwrap());
+    assert_edits_region!(|image| image.rotate180());
+    assert_edits_region!(|image| image.rotate(30.0, Interpolation::Bilinear, Canvas::Keep, Rgba8::default()).unwrap());
+}
+
+#[test]
+fn copying_into_a_crop_needs_a_source_of_its_size() {
+    let grid = rgba_grid(20, 15);
+    let patch = grid.map(|p| Rgba8 { r: 255 - p.r, ..*p });
+    let mut image = grid.image();
+    let mut slice = image.crop_mut(6, 4, dims(8, 5)).unwrap();
+
+    // larger and smaller sources are rejected and leave the crop untouched
+    for size in &[dims(20, 15), dims(3, 2), dims(8, 4), dims(9, 5)] {
+        let source = patch.crop(0, 0, *size).unwrap().image();
+        match slice.copy_from(&source) {
+            Err(ImageError::DimensionMismatch { width: 8, height: 5, source_width, source_height }) =>
+                assert_eq!(dims(source_width, source_height), *size),
+            other => panic!("expected a dimension mismatch, got {:?}", other.map(|_| ())),
+        }
+    }
+    assert_eq!(*slice.dimensions(), dims(8, 5));
+    assert_matches(&image, &grid);
+
+    let mut slice = image.crop_mut(6, 4, dims(8, 5)).unwrap();
+    slice.copy_from(&patch.crop(0, 0, dims(8, 5)).unwrap().image()).unwrap();
+    let mut expected = grid.clone();
+    expected.paste(6, 4, &patch.crop(0, 0, dims(8, 5)).unwrap());
+    assert_matches(&image, &expected);
+}
+
+#[test]
+fn rotating_a_crop_in_place_needs_a_square() {
+    let grid = rgba_grid(20, 15);
+    let mut image = grid.image();
+
+    // a crop can't take on swapped or grown dimensions, so it is left as it was
+    let mut slice = image.crop_mut(3, 2, dims(8, 5)).unwrap();
+    assert!(matches!(slice.rotate90(), Err(ImageError::DimensionMismatch { .. })));
+    assert!(matches!(slice.rotate270(), Err(ImageError::DimensionMismatch { .. })));
+    let mut square = image.crop_mut(9, 4, dims(6, 6)).unwrap();
+    assert!(matches!(square.rotate(30.0, Interpolation::Bilinear, Canvas::Expand, Rgba8::default()),
+                     Err(ImageError::DimensionMismatch { width: 6, height: 6, .. })));
+    assert_matches(&image, &grid);
+
+    // square crops turn within their rectangle
+    let mut square = image.crop_mut(9, 4, dims(6, 6)).unwrap();
+    square.rotate90().unwrap();
+    let mut expected = grid.clone();
+    expected.paste(9, 4, &grid_of(&grid.crop(9, 4, dims(6, 6)).unwrap().image().rotated90()));
+    assert_matches(&image, &expected);
+    image.crop_mut(9, 4, dims(6, 6)).unwrap().rotate270().unwrap();
+    assert_matches(&image, &grid);
+
+    // owned images swap or grow their dimensions
+    let mut owned = grid.crop(3, 2, dims(8, 5)).unwrap().image();
+    owned.rotate90().unwrap();
+    assert_eq!(*owned.dimensions(), dims(5, 8));
+    owned.rotate270().unwrap();
+    assert_matches(&owned, &grid.crop(3, 2, dims(8, 5)).unwrap());
+    owned.rotate(30.0, Interpolation::Bilinear, Canvas::Expand, Rgba8::default()).unwrap();
+    assert!(owned.dimensions().width > 8 && owned.dimensions().height > 5);
+}
+
+/// A grey image whose pixels are `10 * y + x`, so a pixel's value gives its origin.
+fn numbered(width: u32, height: u32) -> OwnedImage<Luma8> {
+    let pixels: Vec<u8> = (0..height).flat_map(|y| (0..width).map(move |x| (10 * y + x) as u8)).collect();
+    OwnedImage::new(dims(width, height), pixels.into_boxed_slice()).unwrap()
+}
+
+#[test]
+fn quarter_turns_go_the_right_way() {
+    let image = numbered(5, 4);
+    // [11 12 13]
+    // [21 22 23]
+    let crop = image.crop(1, 1, dims(3, 2)).unwrap();
+
+    let clockwise = crop.rotated90();
+    assert_eq!(*clockwise.dimensions(), dims(2, 3));
+    assert_eq!(&*clockwise.pixels(), &[21, 11, 22, 12, 23, 13]);
+    let anticlockwise = crop.rotated270();
+    assert_eq!(*anticlockwise.dimensions(), dims(2, 3));
+    assert_eq!(&*anticlockwise.pixels(), &[13, 23, 12, 22, 11, 21]);
+    assert_eq!(&*crop.rotated180().pixels(), &[23, 22, 21, 13, 12, 11]);
+
+    // turning by any angle lands on the same pixels at right angles
+    for &(width, height) in &[(3, 2), (5, 3), (4, 4), (1, 4)] {
+        let crop = image.crop(0, 0, dims(width, height)).unwrap();
+        let turned = |degrees| grid_of(&crop.rotated(degrees, Interpolation::Nearest, Canvas::Expand, Luma { luma: 255 }));
+        assert_eq!(turned(90.0), grid_of(&crop.rotated90()), "{}x{}", width, height);
+        assert_eq!(turned(180.0), grid_of(&crop.rotated180()), "{}x{}", width, height);
+        assert_eq!(turned(270.0), grid_of(&crop.rotated270()), "{}x{}", width, height);
+        assert_eq!(turned(-90.0), grid_of(&crop.rotated270()), "{}x{}", width, height);
+    }
+}
+
+#[test]
+fn turning_by_any_angle_sizes_and_fills_the_canvas() {
+    let colour = Rgba { r: 90, g: 160, b: 30, alpha: 255 };
+    let background = Rgba { r: 255, g: 0, b: 255, alpha: 0 };
+    let mut image = rgba_grid(60, 40).image();
+    image.crop_mut(5, 5, dims(40, 20)).unwrap().map_pixels_in_place(|_| colour);
+    let crop = image.crop(5, 5, dims(40, 20)).unwrap();
+
+    // 40 * cos 30 + 20 * sin 30 = 44.6 wide, 40 * sin 30 + 20 * cos 30 = 37.3 high
+    let expanded = crop.rotated(30.0, Interpolation::Nearest, Canvas::Expand, background);
+    assert_eq!(*expanded.dimensions(), dims(45, 38));
+    let kept = crop.rotated(30.0, Interpolation::Nearest, Canvas::Keep, background);
+    assert_eq!(*kept.dimensions(), dims(40, 20));
+
+    // the corners are left uncovered, whatever the interpolation
+    for &interpolation in &[Interpolation::Nearest, Interpolation::Bilinear, Interpolation::Bicubic] {
+        for &canvas in &[Canvas::Expand, Canvas::Keep] {
+            let turned = crop.rotated(30.0, interpolation, canvas, background);
+            let Dimensions { width, height } = *turned.dimensions();
+            for &(x, y) in &[(0, 0), (width - 1, 0), (0, height - 1), (width - 1, height - 1)] {
+                assert_eq!(turned.get_pixel(x, y).unwrap(), background, "{:?} {:?} at ({}, {})", interpolation, canvas, x, y);
+            }
+
+            // and blending only mixes in the background at the edges, so the middle keeps its colour
+            let (cx, cy) = (width as i64 / 2, height as i64 / 2);
+            for (x, y, pixel) in turned.pixels_iter() {
+                if (x as i64 - cx).pow(2) + (y as i64 - cy).pow(2) <= 7 * 7 {
+                    assert_eq!(pixel, colour, "{:?} {:?} at ({}, {})", interpolation, canvas, x, y);
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn cropping_edges_only_shrink_owned_images() {
+    let grid = rgba_grid(20, 15);
+    let mut image = grid.image();
+
+    // a crop would keep its size with only its top-left overwritten, so it is refused
+    let mut slice = image.crop_mut(3, 2, dims(12, 10)).unwrap();
+    let mismatch = |result: Result<(), ImageError>| matches!(result, Err(ImageError::DimensionMismatch { .. }));
+    assert!(mismatch(slice.blur(2, EdgeMode::Crop)));
+    assert!(mismatch(slice.convolve(&Kernel::sharpen(), EdgeMode::Crop)));
+    assert!(mismatch(slice.gaussian_blur(1.0, 1.0, EdgeMode::Crop)));
+    assert_eq!(*slice.dimensions(), dims(12, 10));
+    assert_matches(&image, &grid);
+
+    // owned images shrink to the pixels with whole neighbourhoods
+    let source = grid.crop(3, 2, dims(12, 10)).unwrap().image();
+    let mut owned = grid.crop(3, 2, dims(12, 10)).unwrap().image();
+    owned.blur(2, EdgeMode::Crop).unwrap();
+    let blurred = source.blurred(2, EdgeMode::Crop);
+    assert!(owned.dimensions().width < 12 && owned.dimensions().height < 10);
+    assert_eq!((owned.dimensions(), owned.pixels()), (blurred.dimensions(), blurred.pixels()));
+    let mut owned = grid.crop(3, 2, dims(12, 10)).unwrap().image();
+    owned.convolve(&Kernel::sharpen(), EdgeMode::Crop).unwrap();
+    assert_eq!(*owned.dimensions(), dims(10, 8));
+}
+
+#[test]
+fn row_and_column_splits_are_disjoint_and_compose() {
+    let grid = rgba_grid(21, 16);
+    let mut image = grid.image();
+    let (mut top, bottom) = image.split_at_row_mut(6);
+    assert_matches(&top, &grid.crop(0, 0, dims(21, 6)).unwrap());
+    assert_matches(&bottom, &grid.crop(0, 6, dims(21, 10)).unwrap());
+
+    // split a split again, and edit every part while they are all borrowed
+    let (mut left, mut right) = bottom.split_at_col(8);
+    assert_eq!(*right.spec(), SliceSpec { origin: (6 * 21 + 8) * 4, stride: 21 * 4, dims: dims(13, 10) });
+    top.flip(true, false);
+    left.greyscale_mut();
+    right.flip(false, true);
+
+    let mut expected = grid.clone();
+    expected.paste(0, 0, &grid_of(&grid.crop(0, 0, dims(21, 6)).unwrap().image().flipped(true, false)));
+    expected.paste(0, 6, &grid_of(&grid.crop(0, 6, dims(8, 10)).unwrap().image().greyscale()));
+    expected.paste(8, 6, &grid_of(&grid.crop(8, 6, dims(13, 10)).unwrap().image().flipped(false, true)));
+    assert_matches(&image, &expected);
+
+    // out-of-range splits are clamped, leaving an empty part
+    let (all, none) = image.split_at_col_mut(50);
+    assert_eq!((*all.dimensions(), *none.dimensions()), (dims(21, 16), dims(0, 16)));
+}
+
+#[test]
+fn tiles_cover_the_image_once() {
+    let grid = rgba_grid(23, 17);
+    let mut image = grid.image();
+    let mut outer = image.crop_mut(1, 2, dims(20, 14)).unwrap();
+    let tiles = outer.tiles_mut(6, 5);
+
+    // 4 columns (the last 2 wide) by 3 rows (the last 4 high)
+    assert_eq!(tiles.len(), 12);
+    for (i, tile) in tiles.iter().enumerate() {
+        let (x, y) = (i as u32 % 4 * 6, i as u32 / 4 * 5);
+        assert_matches(tile, &grid.crop(1 + x, 2 + y, dims(6, 5)).unwrap().crop(0, 0, dims(20 - x, 14 - y)).unwrap());
+    }
+    assert_eq!(image.tiles_mut(30, 30).len(), 1);
+    let mut empty = OwnedImage::<Rgba8>::new(dims(0, 0), Box::new([])).unwrap();
+    assert!(empty.tiles_mut(4, 4).is_empty());
+}
+
+#[test]
+fn tiles_can_be_edited_on_separate_threads() {
+    let grid = rgba_grid(40, 31);
+    let mut image = grid.image();
+    std::thread::scope(|scope| {
+        for mut tile in image.tiles_mut(9, 7) {
+            scope.spawn(move || tile.blur(2, EdgeMode::Clamp).unwrap());
+        }
+    });
+
+    let mut expected = grid.clone();
+    for y in (0..31).step_by(7) {
+        for x in (0..40).step_by(9) {
+            let tile = grid.crop(x, y, dims(9, 7)).unwrap();
+            expected.paste(x, y, &grid_of(&tile.image().blurred(2, EdgeMode::Clamp)));
+        }
+    }
+    assert_matches(&image, &expected);
+}
+
+#[test]
+fn pixel_iterators_and_maps_follow_the_crop() {
+    let grid = rgba_grid(19, 14);
+    let mut image = grid.image();
+    let expected = grid.crop(2, 3, dims(15, 9)).unwrap().crop(4, 1, dims(8, 6)).unwrap();
+    let mut outer = image.crop_mut(2, 3, dims(15, 9)).unwrap();
+    let mut inner = outer.crop_mut(4, 1, dims(8, 6)).unwrap();
+
+    let visited: Vec<_> = inner.pixels_iter().collect();
+    assert_eq!(inner.pixels_iter().len(), 48);
+    for (i, &(x, y, pixel)) in visited.iter().enumerate() {
+        assert_eq!((x, y), (i as u32 % 8, i as u32 / 8));
+        assert_eq!(pixel, expected.rows[y as usize][x as usize]);
+    }
+
+    let invert = |p: Rgba8| Rgba8 { r: 255 - p.r, g: 255 - p.g, b: 255 - p.b, ..p };
+    let inverted = expected.map(|&p| invert(p));
+    assert_matches(&inner.map_pixels(invert), &inverted);
+    assert_matches(&inner.map_pixels(|p| Luma8 { luma: p.g }), &expected.map(|p| Luma8 { luma: p.g }));
+    let diff = inner.zip_map(&inverted.image(), |a, b| Luma8 { luma: a.r.max(b.r) - a.r.min(b.r) }).unwrap();
+    assert_matches(&diff, &expected.map(|p| Luma8 { luma: (255 - 2 * p.r as i32).unsigned_abs() as u8 }));
+    assert!(matches!(inner.zip_map(&grid.image(), |a, _| a), Err(ImageError::SizeMismatch { .. })));
+
+    // every way of writing in place edits the same pixels
+    for (x, y, channels) in inner.pixels_mut() {
+        assert_eq!(Rgba8::from_channels(channels), expected.rows[y as usize][x as usize]);
+        invert(Rgba8::from_channels(channels)).write_channels(channels);
+    }
+    assert_matches(&inner, &inverted);
+    inner.map_pixels_in_place(invert);
+    assert_matches(&inner, &expected);
+    for row in inner.rows_mut() {
+        row.iter_mut().for_each(|v| *v = 255 - *v);
+    }
+    assert_matches(&inner, &expected.map(|p| Rgba8 { alpha: 255 - p.alpha, ..invert(*p) }));
+}
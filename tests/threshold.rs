@@ -0,0 +1,188 @@
+//! Binary masks from fixed, global and locally adaptive thresholds.
+
+use image_processor::{
+    traits::*,
+    OwnedImage::OwnedImage,
+    utils::Dimensions,
+    pixel::*,
+    histogram::Bins,
+    threshold::{self, Threshold, Mask},
+    kernel::EdgeMode,
+};
+
+fn grey_image(width: u32, height: u32, value: impl Fn(u32, u32) -> f32) -> OwnedImage<Luma8> {
+    let pixels: Vec<u8> = (0..height)
+        .flat_map(|y| (0..width).map(move |x| (x, y)))
+        .map(|(x, y)| u8::from_unit(value(x, y)))
+        .collect();
+    OwnedImage::new(Dimensions { width, height }, pixels.into_boxed_slice()).unwrap()
+}
+
+fn is_stroke(x: u32, y: u32) -> bool {
+    x % 12 < 2 && (8..40).contains(&y)
+}
+
+fn page_luma(x: u32, y: u32) -> f32 {
+    let paper = 0.4 + 0.5 * x as f32 / 95.0;
+    if is_stroke(x, y) { paper - 0.25 } else { paper }
+}
+
+/// A page lit unevenly from the right, with dark vertical strokes.
+fn page() -> OwnedImage<Luma8> {
+    grey_image(96, 48, page_luma)
+}
+
+fn set(mask: &Mask, x: u32, y: u32) -> bool {
+    mask.get_pixel(x, y).unwrap().luma == 255
+}
+
+fn assert_finds_strokes(mask: &Mask) {
+    assert_eq!(mask.dimensions(), &Dimensions { width: 96, height: 48 });
+    for (x, y, Luma { luma }) in mask.pixels_iter() {
+        assert_eq!(luma, if is_stroke(x, y) { 0 } else { 255 }, "at ({}, {})", x, y);
+    }
+}
+
+/************************************** Global Levels *************************************************/
+/******************************************************************************************************/
+
+#[test]
+fn fixed_threshold_sets_brighter_pixels() {
+    let image = grey_image(64, 2, |x, _| x as f32 / 63.0);
+    let mask = image.threshold(Threshold::Fixed(0.5));
+
+    for (x, y, Luma { luma }) in mask.pixels_iter() {
+        assert_eq!(luma, if x >= 32 { 255 } else { 0 }, "at ({}, {})", x, y);
+    }
+}
+
+#[test]
+fn colour_images_are_thresholded_by_luma() {
+    let pixels: Vec<u8> = vec![255, 0, 0, 255, 0, 255, 0, 255, 0, 0, 255, 255];
+    let image = OwnedImage::<Rgba8>::new(Dimensions { width: 3, height: 1 }, pixels.into_boxed_slice()).unwrap();
+    let mask = image.threshold(Threshold::Fixed(0.5));
+
+    // only green carries more than half of Rec.709 luma
+    assert_eq!(mask.row(0), &[0, 255, 0]);
+}
+
+#[test]
+fn otsu_splits_two_populations() {
+    let mut bins: Bins = [0; 256];
+    bins[40] = 30;
+    bins[45] = 50;
+    bins[190] = 20;
+    bins[210] = 60;
+    let level = threshold::otsu_level(&bins);
+    assert!((45..190).contains(&level), "{}", level);
+
+    let image = grey_image(40, 40, |x, y| {
+        let noise = ((x * 7 + y * 3) % 9) as f32 / 255.0;
+        if x < 15 { 0.2 + noise } else { 0.7 - noise }
+    });
+    let mask = image.threshold(Threshold::Otsu);
+    assert!(mask.pixels_iter().all(|(x, _, Luma { luma })| (luma == 255) == (x >= 15)));
+}
+
+#[test]
+fn otsu_fails_on_uneven_lighting() {
+    let mask = page().threshold(Threshold::Otsu);
+    // the dim left of the page falls in with the strokes
+    assert!(!set(&mask, 5, 2) && set(&mask, 90, 2));
+}
+
+/************************************** Adaptive Levels ***********************************************/
+/******************************************************************************************************/
+
+#[test]
+fn adaptive_mean_copes_with_uneven_lighting() {
+    assert_finds_strokes(&page().threshold(Threshold::AdaptiveMean { radius: 7, offset: 0.05, edge: EdgeMode::Skip }));
+}
+
+#[test]
+fn adaptive_gaussian_copes_with_uneven_lighting() {
+    assert_finds_strokes(&page().threshold(Threshold::AdaptiveGaussian { sigma: 4.0, offset: 0.05, edge: EdgeMode::Skip }));
+}
+
+#[test]
+fn niblack_finds_strokes() {
+    let mask = page().threshold(Threshold::Niblack { radius: 7, k: -0.2, edge: EdgeMode::Skip });
+    assert!(mask.pixels_iter().filter(|&(x, y, _)| is_stroke(x, y)).all(|(_, _, Luma { luma })| luma == 0));
+
+    // but it picks up noise where the paper's brightness drifts
+    let paper = mask.pixels_iter().filter(|&(x, y, _)| !is_stroke(x, y));
+    let lost = paper.filter(|&(_, _, Luma { luma })| luma == 0).count();
+    assert!(lost > 0 && lost < 96 * 48 / 20, "{}", lost);
+}
+
+#[test]
+fn sauvola_finds_strokes() {
+    assert_finds_strokes(&page().threshold(Threshold::Sauvola { radius: 7, k: 0.3, edge: EdgeMode::Skip }));
+}
+
+#[test]
+fn sauvola_keeps_flat_paper_clean() {
+    let paper = grey_image(32, 32, |x, y| 0.8 + ((x * 5 + y * 11) % 7) as f32 / 255.0);
+    let sauvola = paper.threshold(Threshold::Sauvola { radius: 5, k: 0.3, edge: EdgeMode::Skip });
+    let niblack = paper.threshold(Threshold::Niblack { radius: 5, k: -0.2, edge: EdgeMode::Skip });
+
+    assert!(sauvola.pixels_iter().all(|(_, _, Luma { luma })| luma == 255));
+    assert!(niblack.pixels_iter().any(|(_, _, Luma { luma })| luma == 0));
+}
+
+#[test]
+fn thresholding_a_crop_sees_only_the_crop() {
+    let image = page();
+    let dims = Dimensions { width: 30, height: 20 };
+    let crop = image.crop(40, 10, dims).unwrap();
+    let method = Threshold::AdaptiveMean { radius: 5, offset: 0.05, edge: EdgeMode::Skip };
+
+    let mask = crop.threshold(method);
+    assert_eq!(mask.dimensions(), &dims);
+    assert_eq!(mask.pixels(), crop.map_pixels(|pixel| pixel).threshold(method).pixels());
+    assert!(mask.pixels_iter().all(|(x, y, Luma { luma })| (luma == 0) == is_stroke(x + 40, y + 10)));
+}
+
+#[test]
+fn windows_read_past_the_edges_by_the_edge_mode() {
+    let paper = grey_image(24, 16, |_, _| 0.8);
+    let offset = 0.05;
+    for &edge in &[EdgeMode::Skip, EdgeMode::Clamp, EdgeMode::Mirror, EdgeMode::Wrap, EdgeMode::Crop] {
+        for &method in &[Threshold::AdaptiveMean { radius: 5, offset, edge },
+                         Threshold::AdaptiveGaussian { sigma: 3.0, offset, edge }] {
+            // reading only paper, everything stays set, and cropping can't shrink the mask
+            let mask = paper.threshold(method);
+            assert_eq!(mask.dimensions(), paper.dimensions());
+            assert!(mask.pixels_iter().all(|(_, _, Luma { luma })| luma == 255), "{:?}", method);
+        }
+    }
+
+    // white outside raises the levels near the edges, but not in the middle
+    let white = EdgeMode::Constant(Luma { luma: 1.0 });
+    let mask = paper.threshold(Threshold::AdaptiveMean { radius: 5, offset, edge: white });
+    assert!(!set(&mask, 0, 0) && !set(&mask, 23, 8) && set(&mask, 12, 8));
+
+    // a constant colour outside is the same as thresholding inside a border of it;
+    // grey this time, so the deviations need its square
+    let grey = u8::from_unit(0.6).to_unit();
+    let border = EdgeMode::Constant(Luma { luma: grey });
+    let radius = 7;
+    let bordered = grey_image(96 + 2 * radius, 48 + 2 * radius, |x, y| {
+        let (x, y) = (x as i64 - radius as i64, y as i64 - radius as i64);
+        if (0..96).contains(&x) && (0..48).contains(&y) {
+            u8::from_unit(page_luma(x as u32, y as u32)).to_unit()
+        } else {
+            grey
+        }
+    });
+    for &(inside, outside) in &[
+        (Threshold::AdaptiveMean { radius, offset, edge: border }, Threshold::AdaptiveMean { radius, offset, edge: EdgeMode::Skip }),
+        (Threshold::Niblack { radius, k: -0.2, edge: border }, Threshold::Niblack { radius, k: -0.2, edge: EdgeMode::Skip }),
+        (Threshold::Sauvola { radius, k: 0.3, edge: border }, Threshold::Sauvola { radius, k: 0.3, edge: EdgeMode::Skip }),
+    ] {
+        let mask = page().threshold(inside);
+        let expected = bordered.threshold(outside);
+        let expected = expected.crop(radius, radius, *mask.dimensions()).unwrap();
+        assert_eq!(mask.pixels(), expected.pixels(), "{:?}", inside);
+    }
+}